@@ -0,0 +1,486 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::db::{self, AppSettings};
+use crate::schedule;
+use crate::syncing::backend::{self, TargetBackend, sftp::SftpTarget};
+use crate::syncing::mount::{self, AfterMountSync, MountWatcher};
+use crate::syncing::options::SyncOptions;
+use crate::syncing::report::SyncOutcome;
+use crate::syncing::space::{InsufficientSpace, SPACE_CHECK_SETTING, SpaceCheck};
+use crate::syncing::sync::Syncer;
+use crate::syncing::watch::{ChangeWatcher, Changes};
+use crate::syncing::{self, TargetMismatch};
+
+const USAGE: &str = r#"Usage: file-sync-rs [COMMAND]
+
+Without a command the graphical interface is started.
+
+Options:
+  --config <DB_FILE>              Use the given database instead of the default one, can also be
+                                  set with the FILE_SYNC_RS_DB environment variable. If a file
+                                  called file-sync-rs.portable is next to the executable, the
+                                  database next to the executable is used.
+
+Commands:
+  export-config <FILE>            Write sources, target and settings to a TOML or JSON file
+  import-config <FILE> [--force]  Read sources, target and settings from a TOML or JSON file,
+                                  --force replaces conflicting target and settings
+  schedule [SCHEDULE | off]       Show or set when syncs run automatically, e.g. "every 6h",
+                                  "every 30m", "daily 02:30" or "cron 0 3 * * 1-5"
+  mount-sync [off | on | notify | unmount]
+                                  Show or set whether the daemon syncs when the target drive is
+                                  mounted, and whether it notifies or unmounts the drive afterwards
+  space-check [refuse | warn | off]
+                                  Show or set whether a sync is refused or only warns if the
+                                  files do not fit on the target, "off" skips the check
+  sftp-target [off | USER@HOST[:PORT] KEY_FILE DIRECTORY [KNOWN_HOSTS_FILE]]
+                                  Show or set the SSH server the target directory is written to
+                                  over SFTP, its host key has to be in ~/.ssh/known_hosts or the
+                                  given file. "off" makes the target a local directory again.
+                                  Mirroring and resuming syncs only work with local targets.
+                                  SFTP targets are not locked and their id is not checked, so
+                                  only one sync may write to them at a time
+  adopt-target                    Use the directory at the target path as the target even if it
+                                  belongs to a different target, e.g. after replacing the drive
+  daemon                          Run scheduled syncs until the process is stopped
+  watch                           Like daemon, but also sync all sources once and then keep
+                                  syncing changed files
+  help                            Print this message"#;
+
+/// Runs the command given by `args` (without the program name) and returns the exit code.
+pub fn run(db: &AppSettings, args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some("export-config") => match args.get(1) {
+            Some(path) => export_config(db, path.into()),
+            None => usage_error(),
+        },
+        Some("import-config") => match args.get(1) {
+            Some(path) => import_config(
+                db,
+                path.into(),
+                args[2..].iter().any(|arg| arg == "--force"),
+            ),
+            None => usage_error(),
+        },
+        Some("schedule") => schedule(db, args.get(1..).unwrap_or_default()),
+        Some("mount-sync") => mount_sync(db, args.get(1).map(String::as_str)),
+        Some("space-check") => space_check(db, args.get(1).map(String::as_str)),
+        Some("sftp-target") => sftp_target(db, args.get(1..).unwrap_or_default()),
+        Some("adopt-target") => adopt_target(db),
+        Some("daemon") => daemon(db, false),
+        Some("watch") => daemon(db, true),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => usage_error(),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}", crate::gui::utils::error_chain_string(error));
+            1
+        }
+    }
+}
+
+/// Removes `--config <DB_FILE>` or `--config=<DB_FILE>` from `args` and returns the path.
+pub fn take_config_arg(args: &mut Vec<String>) -> Result<Option<PathBuf>> {
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == "--config" || arg.starts_with("--config="))
+    else {
+        return Ok(None);
+    };
+
+    let arg = args.remove(index);
+    if let Some(path) = arg.strip_prefix("--config=") {
+        return Ok(Some(path.into()));
+    }
+    if index >= args.len() {
+        anyhow::bail!("--config requires a path to a database file\n\n{}", USAGE);
+    }
+    Ok(Some(args.remove(index).into()))
+}
+
+fn usage_error() -> Result<()> {
+    anyhow::bail!("invalid arguments\n\n{}", USAGE)
+}
+
+fn export_config(db: &AppSettings, path: PathBuf) -> Result<()> {
+    Config::from_db(db)
+        .context("failed to read configuration")?
+        .write(&path)?;
+    println!("Exported configuration to {:?}.", path);
+    Ok(())
+}
+
+fn import_config(db: &AppSettings, path: PathBuf, force: bool) -> Result<()> {
+    let config = Config::read(&path)?;
+    let issues = config.check(db).context("failed to check configuration")?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    if issues.iter().any(|issue| issue.is_blocking()) {
+        anyhow::bail!("configuration {:?} is invalid", path);
+    }
+    if !force && !issues.is_empty() {
+        anyhow::bail!(
+            "configuration {:?} conflicts with the current configuration, use --force to import it anyway",
+            path
+        );
+    }
+
+    config.apply(db).context("failed to import configuration")?;
+    println!("Imported configuration from {:?}.", path);
+    Ok(())
+}
+
+/// Returns the configured sources and target.
+fn sync_parameters(db: &AppSettings) -> Result<(Vec<PathBuf>, PathBuf)> {
+    let target = db
+        .get_setting("target_path")?
+        .context("no target directory is configured")?;
+    let sources = db.get_sources()?;
+    if sources.is_empty() {
+        anyhow::bail!("no source directories are configured");
+    }
+    Ok((sources, target.into()))
+}
+
+/// Runs the syncer to completion and records it in the history.
+/// Runs without changes are only recorded if `always_record` is set.
+async fn run_syncer(
+    db: &AppSettings,
+    syncer: Syncer,
+    backend: Arc<dyn TargetBackend>,
+    always_record: bool,
+) -> SyncOutcome {
+    let options = db
+        .get_setting(syncing::TARGET_ID_SETTING)
+        .and_then(|target_id| Ok((target_id, SyncOptions::load(db)?)));
+    let mut syncer = match options {
+        Ok((target_id, options)) => syncer
+            .with_target_id(target_id)
+            .with_options(options)
+            .with_backend(backend),
+        Err(error) => {
+            eprintln!("{}", crate::gui::utils::error_chain_string(error));
+            return SyncOutcome::Failed;
+        }
+    };
+    // starting from scratch is always possible, so errors are ignored
+    if let Ok(Some(checkpoint)) = syncer.find_checkpoint() {
+        println!(
+            "Resuming the sync started at {}, {} files were already synced.",
+            checkpoint.started().format("%Y-%m-%d %H:%M:%S"),
+            checkpoint.completed_files()
+        );
+        syncer = syncer.resume(checkpoint);
+    }
+
+    let outcome = if let Err(error) = syncer.prepare().await {
+        let is_mismatch = error.downcast_ref::<TargetMismatch>().is_some();
+        eprintln!("{}", crate::gui::utils::error_chain_string(error));
+        if is_mismatch {
+            eprintln!("Run `file-sync-rs adopt-target` to sync into it anyway.");
+        }
+        SyncOutcome::Failed
+    } else {
+        loop {
+            match syncer.async_next().await {
+                None => break SyncOutcome::Success,
+                Some(Ok(_)) => {}
+                Some(Err(error)) => {
+                    let is_insufficient_space = error.downcast_ref::<InsufficientSpace>().is_some();
+                    eprintln!("{}", crate::gui::utils::error_chain_string(error));
+                    if is_insufficient_space {
+                        eprintln!("Run `file-sync-rs space-check warn` to sync anyway.");
+                    }
+                    break SyncOutcome::Failed;
+                }
+            }
+        }
+    };
+
+    let report = syncer.report(outcome);
+    if let Some(target_id) = &report.target_id
+        && let Err(error) = db.set_setting(syncing::TARGET_ID_SETTING, target_id)
+    {
+        eprintln!("{}", crate::gui::utils::error_chain_string(error));
+    }
+    if !always_record && !report.has_changes() {
+        return report.outcome;
+    }
+    println!(
+        "{} sync {}: {} copied ({} reflinked, {} offloaded), {} updated, {} skipped, {} linked, {} moved, {} removed, {} failed",
+        report.finished.format("%Y-%m-%d %H:%M:%S"),
+        String::from(&report.outcome).to_lowercase(),
+        report.statistics.copied,
+        report.statistics.reflinked,
+        report.statistics.offloaded,
+        report.statistics.updated,
+        report.statistics.skipped,
+        report.statistics.linked,
+        report.statistics.moved,
+        report.statistics.removed,
+        report.statistics.failed
+    );
+    if let Err(error) = db.add_history_entry(&db::HistoryEntry::from(&report), &report.errors) {
+        eprintln!("{}", crate::gui::utils::error_chain_string(error));
+    }
+    report.outcome
+}
+
+fn schedule(db: &AppSettings, args: &[String]) -> Result<()> {
+    let value = args.join(" ");
+    if value == "off" {
+        schedule::set_schedule(db, None)?;
+    } else if !value.is_empty() {
+        let new_schedule: schedule::Schedule = value.parse()?;
+        schedule::set_schedule(db, Some(&new_schedule))?;
+    }
+
+    match schedule::get_schedule(db)? {
+        Some(current) => println!("Schedule: {}", current),
+        None => println!("No schedule is set."),
+    }
+    if let Some(next_run) = schedule::next_run(db)? {
+        println!(
+            "Next scheduled sync at {}.",
+            next_run
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+fn sftp_target(db: &AppSettings, args: &[String]) -> Result<()> {
+    match args {
+        [] => {}
+        [off] if off == "off" => SftpTarget::remove(db)?,
+        [address, key_file, directory, known_hosts @ ..] if known_hosts.len() <= 1 => {
+            let mut target = SftpTarget::new(address, key_file.into())?;
+            if let Some(known_hosts) = known_hosts.first() {
+                target.known_hosts = known_hosts.into();
+            }
+            target.save(db)?;
+            db.set_setting("target_path", directory)?;
+            // the id of the old target does not apply to the new one
+            db.del_setting(syncing::TARGET_ID_SETTING)?;
+        }
+        _ => return usage_error(),
+    }
+
+    match SftpTarget::load(db)? {
+        Some(target) => println!(
+            "The target is {}{} with the key {:?}, the host key is verified with {:?}.",
+            target,
+            db.get_setting("target_path")?.unwrap_or_default(),
+            target.key_path,
+            target.known_hosts
+        ),
+        None => println!("The target is a local directory."),
+    }
+    Ok(())
+}
+
+fn adopt_target(db: &AppSettings) -> Result<()> {
+    let (_, target) = sync_parameters(db)?;
+    db.del_setting(syncing::TARGET_ID_SETTING)?;
+    println!("The next sync uses {:?} as the target.", target);
+    Ok(())
+}
+
+fn mount_sync(db: &AppSettings, value: Option<&str>) -> Result<()> {
+    match value {
+        None => {}
+        Some("off") => {
+            db.del_setting(mount::SYNC_ON_MOUNT_SETTING)?;
+            db.del_setting(mount::AFTER_MOUNT_SYNC_SETTING)?;
+        }
+        Some(value @ ("on" | "notify" | "unmount")) => {
+            db.set_setting(mount::SYNC_ON_MOUNT_SETTING, "true")?;
+            let after: &str = (&AfterMountSync::from(value)).into();
+            db.set_setting(mount::AFTER_MOUNT_SYNC_SETTING, after)?;
+        }
+        Some(_) => return usage_error(),
+    }
+
+    if db.get_setting(mount::SYNC_ON_MOUNT_SETTING)?.as_deref() != Some("true") {
+        println!("Syncing when the target drive is mounted is off.");
+        return Ok(());
+    }
+    match get_after_mount_sync(db)? {
+        AfterMountSync::Nothing => println!("Syncing when the target drive is mounted is on."),
+        AfterMountSync::Notify => println!(
+            "Syncing when the target drive is mounted is on, a message is printed when it is done."
+        ),
+        AfterMountSync::Unmount => println!(
+            "Syncing when the target drive is mounted is on, the drive is unmounted when it is done."
+        ),
+    }
+    Ok(())
+}
+
+fn space_check(db: &AppSettings, value: Option<&str>) -> Result<()> {
+    match value {
+        None => {}
+        Some(value @ ("refuse" | "warn" | "off")) => {
+            db.set_setting(SPACE_CHECK_SETTING, value)?;
+        }
+        Some(_) => return usage_error(),
+    }
+
+    match SyncOptions::load(db)?.space_check {
+        SpaceCheck::Refuse => {
+            println!("Syncs are refused if the files do not fit on the target.")
+        }
+        SpaceCheck::Warn => println!(
+            "Syncs run even if the files do not fit on the target, the report contains a warning."
+        ),
+        SpaceCheck::Off => println!("The free space of the target is not checked."),
+    }
+    Ok(())
+}
+
+fn get_after_mount_sync(db: &AppSettings) -> Result<AfterMountSync> {
+    Ok(db
+        .get_setting(mount::AFTER_MOUNT_SYNC_SETTING)?
+        .as_deref()
+        .map(AfterMountSync::from)
+        .unwrap_or_default())
+}
+
+/// Interval in which the daemon checks if a scheduled sync is due.
+const SCHEDULE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// Interval in which the daemon checks if the target drive was mounted.
+const MOUNT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Runs scheduled syncs until the process is stopped.
+/// With `watch` all sources are synced once and changes are synced as they happen.
+/// If enabled, all sources are synced when the target drive is mounted.
+fn daemon(db: &AppSettings, watch: bool) -> Result<()> {
+    let (sources, target) = sync_parameters(db)?;
+    // shared by all syncs, so the connection to an SFTP server is kept open
+    let backend = backend::load(db)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("failed to start async runtime")?;
+
+    runtime.block_on(async {
+        // start watching before the initial sync so no change gets lost
+        let mut watcher = if watch {
+            Some(ChangeWatcher::new(&sources)?)
+        } else {
+            None
+        };
+
+        if watch {
+            let syncer = Syncer::new(sources.clone(), target.clone())
+                .map_err(|error| anyhow::anyhow!("{}", error))?;
+            run_syncer(db, syncer, backend.clone(), true).await;
+            println!("Watching {} sources for changes.", sources.len());
+        }
+        if let Some(next_run) = schedule::next_run(db)? {
+            println!(
+                "Next scheduled sync at {}.",
+                next_run.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+            );
+        }
+        let mut mount_watcher =
+            if db.get_setting(mount::SYNC_ON_MOUNT_SETTING)?.as_deref() == Some("true") {
+                println!("Waiting for the target drive to be mounted.");
+                Some(MountWatcher::new()?)
+            } else {
+                None
+            };
+
+        let mut schedule_interval = tokio::time::interval(SCHEDULE_CHECK_INTERVAL);
+        let mut mount_interval = tokio::time::interval(MOUNT_CHECK_INTERVAL);
+        loop {
+            // runs of watch mode are only recorded if they changed something
+            let (syncer, always_record, mounted) = tokio::select! {
+                changes = async { watcher.as_mut().unwrap().next().await }, if watcher.is_some() => {
+                    match changes {
+                        None => {
+                            watcher = None;
+                            continue;
+                        }
+                        Some(Err(error)) => {
+                            eprintln!("{}", crate::gui::utils::error_chain_string(error));
+                            continue;
+                        }
+                        Some(Ok(Changes::Rescan)) => {
+                            (Syncer::new(sources.clone(), target.clone()), false, None)
+                        }
+                        Some(Ok(Changes::Paths(paths))) => (
+                            Syncer::new_partial(sources.clone(), target.clone(), &paths),
+                            false,
+                            None,
+                        ),
+                    }
+                }
+                _ = schedule_interval.tick() => {
+                    let now = chrono::offset::Utc::now();
+                    match schedule::is_due(db, &now) {
+                        Ok(false) => continue,
+                        Ok(true) => {}
+                        Err(error) => {
+                            eprintln!("{}", crate::gui::utils::error_chain_string(error));
+                            continue;
+                        }
+                    }
+                    if let Err(error) = schedule::mark_run(db, &now) {
+                        eprintln!("{}", crate::gui::utils::error_chain_string(error));
+                    }
+                    (Syncer::new(sources.clone(), target.clone()), true, None)
+                }
+                _ = mount_interval.tick(), if mount_watcher.is_some() => {
+                    match mount_watcher.as_mut().unwrap().poll(&target) {
+                        Ok(Some(mount)) => {
+                            println!("Target drive was mounted at {:?}.", mount.mount_point);
+                            (Syncer::new(sources.clone(), target.clone()), true, Some(mount))
+                        }
+                        Ok(None) => continue,
+                        Err(error) => {
+                            eprintln!("{}", crate::gui::utils::error_chain_string(error));
+                            continue;
+                        }
+                    }
+                }
+            };
+            let outcome = match syncer {
+                Ok(syncer) => run_syncer(db, syncer, backend.clone(), always_record).await,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    SyncOutcome::Failed
+                }
+            };
+
+            let Some(mount) = mounted else {
+                continue;
+            };
+            match get_after_mount_sync(db)? {
+                AfterMountSync::Nothing => {}
+                AfterMountSync::Notify => match outcome {
+                    SyncOutcome::Success => {
+                        println!("Sync finished, the target drive can be removed.")
+                    }
+                    SyncOutcome::Failed => println!("Sync failed, the target drive can be removed."),
+                },
+                AfterMountSync::Unmount => match mount::unmount(&mount) {
+                    Ok(()) => println!("Unmounted the target drive {:?}.", mount.mount_point),
+                    Err(error) => eprintln!("{}", crate::gui::utils::error_chain_string(error)),
+                },
+            }
+        }
+    })
+}
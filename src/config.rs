@@ -0,0 +1,505 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::db::AppSettings;
+use crate::schedule::{SCHEDULE_LAST_RUN_SETTING, SCHEDULE_SETTING, Schedule};
+use crate::syncing::TARGET_ID_SETTING;
+use crate::syncing::backend::sftp::{
+    SFTP_HOST_SETTING, SFTP_KEY_PATH_SETTING, SFTP_KNOWN_HOSTS_SETTING, SFTP_PORT_SETTING,
+};
+use crate::syncing::bandwidth::{self, BANDWIDTH_LIMIT_SETTING, BANDWIDTH_SCHEDULE_SETTING};
+use crate::syncing::options::{
+    self, LARGE_FILE_THRESHOLD_SETTING, LARGE_FILE_WORKERS_SETTING, WORKERS_SETTING,
+};
+use crate::syncing::space::SPACE_CHECK_SETTING;
+
+/// The setting key under which the target directory is stored.
+const TARGET_SETTING: &str = "target_path";
+
+/// Settings that only apply to this machine. They are neither exported nor imported.
+const LOCAL_SETTINGS: &[&str] = &[
+    TARGET_ID_SETTING,
+    SCHEDULE_LAST_RUN_SETTING,
+    SFTP_KEY_PATH_SETTING,
+    SFTP_KNOWN_HOSTS_SETTING,
+];
+
+/// The contents of [`AppSettings`] in a form that can be written to and read from a file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+}
+
+/// The file formats a [`Config`] can be stored in. Chosen by the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Returns [`ConfigFormat::Json`] for `.json` files and [`ConfigFormat::Toml`] otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+}
+
+/// A problem found while checking a [`Config`] before importing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportIssue {
+    /// The source does not exist on this machine.
+    SourceDoesNotExist(PathBuf),
+    /// The target does not exist on this machine.
+    TargetDoesNotExist(PathBuf),
+    /// The imported source overlaps with another configured or imported source.
+    SourcesOverlap(PathBuf, PathBuf),
+    /// The target is located inside of the source.
+    TargetInSource(PathBuf),
+    /// The source is located inside of the target.
+    SourceInTarget(PathBuf),
+    /// The source is already configured and will be skipped.
+    SourceExists(PathBuf),
+    /// A different target is configured and will be replaced.
+    TargetDiffers { current: PathBuf, new: PathBuf },
+    /// The value of a setting can not be parsed.
+    InvalidSetting { key: String, error: String },
+    /// A setting has a different value and will be replaced.
+    SettingDiffers {
+        key: String,
+        current: String,
+        new: String,
+    },
+}
+
+impl ImportIssue {
+    /// Blocking issues make the config invalid. All others are conflicts that can be accepted.
+    pub fn is_blocking(&self) -> bool {
+        matches!(
+            self,
+            Self::SourceDoesNotExist(_)
+                | Self::TargetDoesNotExist(_)
+                | Self::SourcesOverlap(_, _)
+                | Self::TargetInSource(_)
+                | Self::SourceInTarget(_)
+                | Self::InvalidSetting { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for ImportIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SourceDoesNotExist(path) => write!(f, "Source {:?} does not exist.", path),
+            Self::TargetDoesNotExist(path) => write!(f, "Target {:?} does not exist.", path),
+            Self::SourcesOverlap(path1, path2) => {
+                write!(f, "Sources {:?} and {:?} overlap.", path1, path2)
+            }
+            Self::TargetInSource(path) => {
+                write!(f, "The target is located inside of source {:?}.", path)
+            }
+            Self::SourceInTarget(path) => {
+                write!(f, "Source {:?} is located inside of the target.", path)
+            }
+            Self::SourceExists(path) => {
+                write!(f, "Source {:?} already exists and will be skipped.", path)
+            }
+            Self::TargetDiffers { current, new } => {
+                write!(f, "Target {:?} will be replaced with {:?}.", current, new)
+            }
+            Self::InvalidSetting { key, error } => {
+                write!(f, "Setting {:?} is invalid: {}", key, error)
+            }
+            Self::SettingDiffers { key, current, new } => write!(
+                f,
+                "Setting {:?} will be changed from {:?} to {:?}.",
+                key, current, new
+            ),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the current configuration from the database.
+    pub fn from_db(db: &AppSettings) -> Result<Self> {
+        let mut config = Self {
+            sources: db.get_sources()?,
+            ..Default::default()
+        };
+        for (key, value) in db.get_settings()? {
+            if key == TARGET_SETTING {
+                config.target = Some(value.into());
+            } else if !LOCAL_SETTINGS.contains(&key.as_str()) {
+                config.settings.insert(key, value);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {:?}", path))?;
+        match ConfigFormat::from_path(path) {
+            ConfigFormat::Toml => toml::from_str(&content).context("Invalid TOML config file"),
+            ConfigFormat::Json => {
+                serde_json::from_str(&content).context("Invalid JSON config file")
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = match ConfigFormat::from_path(path) {
+            ConfigFormat::Toml => {
+                toml::to_string_pretty(self).context("Converting to toml failed.")?
+            }
+            ConfigFormat::Json => {
+                serde_json::to_string_pretty(self).context("Converting to json failed.")?
+            }
+        };
+        std::fs::write(path, content)
+            .with_context(|| format!("Could not write config file {:?}", path))
+    }
+
+    /// Compares the config with the current state of the database and validates its paths and settings.
+    pub fn check(&self, db: &AppSettings) -> Result<Vec<ImportIssue>> {
+        let mut issues = Vec::new();
+        // the target of an SFTP server is not on this machine
+        let remote = self.settings.contains_key(SFTP_HOST_SETTING)
+            || db.get_setting(SFTP_HOST_SETTING)?.is_some();
+
+        if let Some(target) = &self.target {
+            if !remote && !target.is_dir() {
+                issues.push(ImportIssue::TargetDoesNotExist(target.clone()));
+            }
+            if let Some(current) = db.get_setting(TARGET_SETTING)?.map(PathBuf::from)
+                && &current != target
+            {
+                issues.push(ImportIssue::TargetDiffers {
+                    current,
+                    new: target.clone(),
+                });
+            }
+        }
+
+        let mut known_sources = db.get_sources()?;
+        for source in &self.sources {
+            if known_sources.contains(source) {
+                issues.push(ImportIssue::SourceExists(source.clone()));
+                continue;
+            }
+            if !source.is_dir() && !source.is_file() {
+                issues.push(ImportIssue::SourceDoesNotExist(source.clone()));
+            }
+            if let Some(target) = self.target.as_ref().filter(|_| !remote) {
+                if source.starts_with(target) {
+                    issues.push(ImportIssue::SourceInTarget(source.clone()));
+                } else if target.starts_with(source) {
+                    issues.push(ImportIssue::TargetInSource(source.clone()));
+                }
+            }
+            for known_source in &known_sources {
+                if known_source.starts_with(source) || source.starts_with(known_source) {
+                    issues.push(ImportIssue::SourcesOverlap(
+                        source.clone(),
+                        known_source.clone(),
+                    ));
+                }
+            }
+            known_sources.push(source.clone());
+        }
+
+        for (key, new) in self.portable_settings() {
+            if let Err(error) = check_setting(key, new) {
+                issues.push(ImportIssue::InvalidSetting {
+                    key: key.clone(),
+                    error: format!("{:#}", error),
+                });
+            } else if let Some(current) = db.get_setting(key)?
+                && &current != new
+            {
+                issues.push(ImportIssue::SettingDiffers {
+                    key: key.clone(),
+                    current,
+                    new: new.clone(),
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Writes the config into the database. Existing sources are kept, target and settings are replaced.
+    /// Call [`Config::check`] before to find invalid paths and conflicts.
+    pub fn apply(&self, db: &AppSettings) -> Result<()> {
+        let existing_sources = db.get_sources()?;
+        for source in &self.sources {
+            if !existing_sources.contains(source) {
+                db.add_source(source.clone())
+                    .with_context(|| format!("failed to add source {:?}", source))?;
+            }
+        }
+        if let Some(target) = &self.target
+            && db.get_setting(TARGET_SETTING)?.as_deref() != target.to_str()
+        {
+            db.set_setting(TARGET_SETTING, target.to_str().unwrap())?;
+            // the id of the old target does not apply to the new one
+            db.del_setting(crate::syncing::TARGET_ID_SETTING)?;
+        }
+        for (key, value) in self.portable_settings() {
+            if key == SCHEDULE_SETTING && db.get_setting(key)?.as_ref() != Some(value) {
+                // counting starts with the import, like when the schedule is entered
+                crate::schedule::mark_run(db, &chrono::offset::Utc::now())?;
+            }
+            db.set_setting(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// The settings without the [`LOCAL_SETTINGS`], which files exported by older versions contain.
+    fn portable_settings(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.settings
+            .iter()
+            .filter(|(key, _)| !LOCAL_SETTINGS.contains(&key.as_str()))
+    }
+}
+
+/// Parses the value of a known setting like it is parsed when it is used.
+fn check_setting(key: &str, value: &str) -> Result<()> {
+    match key {
+        SCHEDULE_SETTING if !value.trim().is_empty() => {
+            value.parse::<Schedule>()?;
+        }
+        BANDWIDTH_LIMIT_SETTING => {
+            bandwidth::parse_limit(value)?;
+        }
+        BANDWIDTH_SCHEDULE_SETTING => {
+            bandwidth::parse_schedule(value)?;
+        }
+        WORKERS_SETTING | LARGE_FILE_WORKERS_SETTING | LARGE_FILE_THRESHOLD_SETTING => {
+            options::parse_count_value(key, value)?;
+        }
+        SPACE_CHECK_SETTING if !matches!(value, "refuse" | "warn" | "off") => {
+            anyhow::bail!("has to be \"refuse\", \"warn\" or \"off\"");
+        }
+        SFTP_PORT_SETTING => {
+            value
+                .parse::<u16>()
+                .with_context(|| format!("invalid port {:?}", value))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database and a directory with a source and a target in it.
+    fn fixture(name: &str) -> (AppSettings, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sync-rs-config-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("source")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        (AppSettings::new(dir.join("db")).unwrap(), dir)
+    }
+
+    #[test]
+    fn local_settings_are_not_exported_or_imported() {
+        let (exporting, dir) = fixture("export");
+        exporting.add_source(dir.join("source")).unwrap();
+        exporting
+            .set_setting(TARGET_SETTING, dir.join("target").to_str().unwrap())
+            .unwrap();
+        exporting.set_setting("mirror", "true").unwrap();
+        exporting
+            .set_setting(TARGET_ID_SETTING, "exported")
+            .unwrap();
+        exporting
+            .set_setting(SCHEDULE_LAST_RUN_SETTING, "2025-01-01T00:00:00+00:00")
+            .unwrap();
+        exporting
+            .set_setting(SFTP_KEY_PATH_SETTING, "/home/user/.ssh/id_ed25519")
+            .unwrap();
+
+        let path = dir.join("config.toml");
+        Config::from_db(&exporting).unwrap().write(&path).unwrap();
+        let config = Config::read(&path).unwrap();
+        assert_eq!(config.target, Some(dir.join("target")));
+        assert_eq!(config.sources, vec![dir.join("source")]);
+        assert_eq!(
+            config.settings,
+            BTreeMap::from([("mirror".to_owned(), "true".to_owned())])
+        );
+
+        let (importing, _) = fixture("import");
+        importing.set_setting(TARGET_ID_SETTING, "local").unwrap();
+        assert!(config.check(&importing).unwrap().is_empty());
+        config.apply(&importing).unwrap();
+        assert_eq!(importing.get_sources().unwrap(), vec![dir.join("source")]);
+        assert_eq!(
+            importing.get_setting("mirror").unwrap(),
+            Some("true".to_owned())
+        );
+        // the target changed, so the id of the old one is gone
+        assert_eq!(importing.get_setting(TARGET_ID_SETTING).unwrap(), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn local_settings_of_old_exports_are_ignored() {
+        let (db, dir) = fixture("old-export");
+        db.set_setting(TARGET_ID_SETTING, "local").unwrap();
+        let config = Config {
+            settings: BTreeMap::from([
+                (TARGET_ID_SETTING.to_owned(), "exported".to_owned()),
+                (
+                    SFTP_KNOWN_HOSTS_SETTING.to_owned(),
+                    "/root/known_hosts".to_owned(),
+                ),
+            ]),
+            ..Default::default()
+        };
+        assert!(config.check(&db).unwrap().is_empty());
+        config.apply(&db).unwrap();
+        assert_eq!(
+            db.get_setting(TARGET_ID_SETTING).unwrap(),
+            Some("local".to_owned())
+        );
+        assert_eq!(db.get_setting(SFTP_KNOWN_HOSTS_SETTING).unwrap(), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn imported_schedule_starts_counting() {
+        let (db, dir) = fixture("schedule");
+        let config = Config {
+            settings: BTreeMap::from([(SCHEDULE_SETTING.to_owned(), "every 6h".to_owned())]),
+            ..Default::default()
+        };
+        config.apply(&db).unwrap();
+        let last_run = db.get_setting(SCHEDULE_LAST_RUN_SETTING).unwrap();
+        assert!(last_run.is_some());
+
+        // importing the same schedule again keeps the count
+        db.set_setting(SCHEDULE_LAST_RUN_SETTING, "2025-01-01T00:00:00+00:00")
+            .unwrap();
+        config.apply(&db).unwrap();
+        assert_eq!(
+            db.get_setting(SCHEDULE_LAST_RUN_SETTING)
+                .unwrap()
+                .as_deref(),
+            Some("2025-01-01T00:00:00+00:00")
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn check_finds_invalid_paths_and_conflicts() {
+        let (db, dir) = fixture("check");
+        db.add_source(dir.join("source")).unwrap();
+        db.set_setting(TARGET_SETTING, "/media/backup").unwrap();
+        db.set_setting("mirror", "true").unwrap();
+        let config = Config {
+            target: Some(dir.join("missing")),
+            sources: vec![
+                dir.join("source"),
+                dir.join("source").join("nested"),
+                dir.join("missing").join("inner"),
+            ],
+            settings: BTreeMap::from([("mirror".to_owned(), "false".to_owned())]),
+        };
+        let issues = config.check(&db).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                ImportIssue::TargetDoesNotExist(dir.join("missing")),
+                ImportIssue::TargetDiffers {
+                    current: "/media/backup".into(),
+                    new: dir.join("missing"),
+                },
+                ImportIssue::SourceExists(dir.join("source")),
+                ImportIssue::SourceDoesNotExist(dir.join("source").join("nested")),
+                ImportIssue::SourcesOverlap(dir.join("source").join("nested"), dir.join("source")),
+                ImportIssue::SourceDoesNotExist(dir.join("missing").join("inner")),
+                ImportIssue::SourceInTarget(dir.join("missing").join("inner")),
+                ImportIssue::SettingDiffers {
+                    key: "mirror".to_owned(),
+                    current: "true".to_owned(),
+                    new: "false".to_owned(),
+                },
+            ]
+        );
+        assert!(issues[0].is_blocking());
+        assert!(!issues[1].is_blocking());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn check_finds_invalid_settings() {
+        let (db, dir) = fixture("invalid-settings");
+        let setting = |key: &str, value: &str| (key.to_owned(), value.to_owned());
+        let config = Config {
+            settings: BTreeMap::from([
+                setting(SCHEDULE_SETTING, "every day"),
+                setting(BANDWIDTH_LIMIT_SETTING, "fast"),
+                setting(BANDWIDTH_SCHEDULE_SETTING, "08:00-18:00=1000000,18:00"),
+                setting(WORKERS_SETTING, "0"),
+                setting(LARGE_FILE_WORKERS_SETTING, "2"),
+                setting(SPACE_CHECK_SETTING, "ignore"),
+                setting(SFTP_PORT_SETTING, "70000"),
+                setting("mirror", "true"),
+            ]),
+            ..Default::default()
+        };
+        let invalid: Vec<String> = config
+            .check(&db)
+            .unwrap()
+            .into_iter()
+            .map(|issue| {
+                assert!(issue.is_blocking());
+                match issue {
+                    ImportIssue::InvalidSetting { key, .. } => key,
+                    issue => panic!("unexpected issue {:?}", issue),
+                }
+            })
+            .collect();
+        assert_eq!(
+            invalid,
+            vec![
+                BANDWIDTH_LIMIT_SETTING,
+                BANDWIDTH_SCHEDULE_SETTING,
+                SCHEDULE_SETTING,
+                SFTP_PORT_SETTING,
+                SPACE_CHECK_SETTING,
+                WORKERS_SETTING,
+            ]
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn targets_on_sftp_servers_are_not_checked_locally() {
+        let (db, dir) = fixture("sftp");
+        let config = Config {
+            target: Some("/srv/backup".into()),
+            sources: vec![dir.join("source")],
+            settings: BTreeMap::from([(SFTP_HOST_SETTING.to_owned(), "backup.local".to_owned())]),
+        };
+        assert!(config.check(&db).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
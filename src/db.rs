@@ -1,12 +1,75 @@
 use anyhow::{Context, Result};
-use rusqlite::{self, Connection, OptionalExtension};
+use rusqlite::{self, Connection, OptionalExtension, params};
 use std::path::PathBuf;
 
+use crate::syncing::{
+    DateTime,
+    report::{Report, SyncOutcome},
+    sync::{Statistics, SyncError},
+};
+
 trait DBManager {
     fn get_path(&self) -> String;
-    fn create_tables(&self, connection: &Connection) -> Result<()>;
+    /// Ordered list of SQL scripts. The schema version of a database is the number of applied scripts.
+    fn migrations(&self) -> &'static [&'static str];
     fn connect(&self) -> Result<Connection> {
-        Connection::open(self.get_path()).context("Failed to open database")
+        let connection = Connection::open(self.get_path()).context("Failed to open database")?;
+        // SQLite only enforces foreign keys, e.g. ON DELETE CASCADE, if enabled per connection
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .context("failed to enable foreign keys")?;
+        Ok(connection)
+    }
+
+    /// Returns the schema version of the database or 0 if it was created before versioning existed.
+    fn schema_version(&self, connection: &Connection) -> Result<usize> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)",
+                [],
+            )
+            .context("failed to create schema version table")?;
+        let version = connection
+            .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+                row.get::<usize, Option<i64>>(0)
+            })
+            .context("failed to query schema version")?;
+        Ok(version.unwrap_or(0) as usize)
+    }
+
+    /// Applies all migrations the database is missing, each one in its own transaction.
+    fn migrate(&self, connection: &mut Connection) -> Result<()> {
+        let migrations = self.migrations();
+        let version = self.schema_version(connection)?;
+        if version > migrations.len() {
+            anyhow::bail!(
+                "database schema version {} is newer than the supported version {}",
+                version,
+                migrations.len()
+            );
+        }
+
+        for (index, migration) in migrations.iter().enumerate().skip(version) {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction")?;
+            transaction
+                .execute_batch(migration)
+                .with_context(|| format!("migration to schema version {} failed", index + 1))?;
+            transaction
+                .execute("DELETE FROM schema_version", [])
+                .context("failed to update schema version")?;
+            transaction
+                .execute(
+                    "INSERT INTO schema_version (version) VALUES (?1)",
+                    [(index + 1) as i64],
+                )
+                .context("failed to update schema version")?;
+            transaction
+                .commit()
+                .context("failed to commit transaction")?;
+        }
+        Ok(())
     }
 }
 
@@ -18,10 +81,10 @@ impl AppSettings {
     pub fn new(path: PathBuf) -> Result<Self> {
         let new_self = AppSettings { path };
 
-        let connection = new_self.connect()?;
+        let mut connection = new_self.connect()?;
         new_self
-            .create_tables(&connection)
-            .context("failed to create tables")?;
+            .migrate(&mut connection)
+            .context("failed to migrate database")?;
 
         Ok(new_self)
     }
@@ -37,7 +100,6 @@ impl AppSettings {
         Ok(())
     }
 
-    #[allow(unused)]
     pub fn del_setting(&self, key: &str) -> Result<()> {
         let connection = self.connect()?;
         connection
@@ -58,6 +120,22 @@ impl AppSettings {
         Ok(result)
     }
 
+    /// Returns all settings as key value pairs.
+    pub fn get_settings(&self) -> Result<Vec<(String, String)>> {
+        let connection = self.connect()?;
+        let mut smtp = connection
+            .prepare("SELECT key, value FROM settings ORDER BY key;")
+            .context("failed to prepare statement")?;
+        let result = smtp
+            .query_map([], |row| {
+                Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?))
+            })
+            .context("failed to query statement")?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()
+            .context("failed to read settings")?;
+        Ok(result)
+    }
+
     pub fn add_source(&self, path: PathBuf) -> Result<()> {
         let connection = self.connect()?;
         connection
@@ -95,6 +173,157 @@ impl AppSettings {
 
         Ok(result)
     }
+
+    /// Stores a finished sync run together with its errors and returns the id of the new entry.
+    pub fn add_history_entry(&self, entry: &HistoryEntry, errors: &[SyncError]) -> Result<i64> {
+        let mut connection = self.connect()?;
+        let transaction = connection
+            .transaction()
+            .context("failed to start transaction")?;
+        transaction
+            .execute(
+                "
+            INSERT INTO sync_history (
+                target, started, finished, files_copied, files_updated,
+                files_skipped, files_failed, bytes, outcome, files_linked, files_reflinked,
+                files_offloaded, files_removed, files_moved
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);
+            ",
+                params![
+                    entry.target.to_str().unwrap(),
+                    entry.started.to_rfc3339(),
+                    entry.finished.to_rfc3339(),
+                    entry.statistics.copied as i64,
+                    entry.statistics.updated as i64,
+                    entry.statistics.skipped as i64,
+                    entry.statistics.failed as i64,
+                    entry.statistics.bytes as i64,
+                    String::from(&entry.outcome),
+                    entry.statistics.linked as i64,
+                    entry.statistics.reflinked as i64,
+                    entry.statistics.offloaded as i64,
+                    entry.statistics.removed as i64,
+                    entry.statistics.moved as i64,
+                ],
+            )
+            .context("failed to insert history entry")?;
+        let id = transaction.last_insert_rowid();
+        for error in errors {
+            transaction
+                .execute(
+                    "INSERT INTO sync_history_errors (history_id, path, message) VALUES (?1, ?2, ?3)",
+                    params![id, error.path().to_str().unwrap(), error.message()],
+                )
+                .context("failed to insert history error")?;
+        }
+        transaction
+            .commit()
+            .context("failed to commit transaction")?;
+        Ok(id)
+    }
+
+    /// Returns all recorded sync runs, newest first.
+    pub fn get_history(&self) -> Result<Vec<HistoryEntry>> {
+        let connection = self.connect()?;
+        let mut smtp = connection
+            .prepare(
+                "
+            SELECT id, target, started, finished, files_copied, files_updated,
+                files_skipped, files_failed, bytes, outcome, files_linked, files_reflinked,
+                files_offloaded, files_removed, files_moved
+            FROM sync_history ORDER BY started DESC, id DESC;
+            ",
+            )
+            .context("failed to prepare statement")?;
+        let rows = smtp
+            .query_map([], |row| {
+                Ok((
+                    row.get::<usize, i64>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, String>(2)?,
+                    row.get::<usize, String>(3)?,
+                    Statistics {
+                        copied: row.get::<usize, i64>(4)? as usize,
+                        updated: row.get::<usize, i64>(5)? as usize,
+                        skipped: row.get::<usize, i64>(6)? as usize,
+                        failed: row.get::<usize, i64>(7)? as usize,
+                        bytes: row.get::<usize, i64>(8)? as u64,
+                        linked: row.get::<usize, i64>(10)? as usize,
+                        reflinked: row.get::<usize, i64>(11)? as usize,
+                        offloaded: row.get::<usize, i64>(12)? as usize,
+                        removed: row.get::<usize, i64>(13)? as usize,
+                        moved: row.get::<usize, i64>(14)? as usize,
+                    },
+                    row.get::<usize, String>(9)?,
+                ))
+            })
+            .context("failed to query statement")?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let (id, target, started, finished, statistics, outcome) =
+                row.context("failed to read history row")?;
+            result.push(HistoryEntry {
+                id: Some(id),
+                target: target.into(),
+                started: parse_timestamp(&started)?,
+                finished: parse_timestamp(&finished)?,
+                statistics,
+                outcome: SyncOutcome::from(outcome.as_str()),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Returns the errors that were recorded for the sync run with the given id.
+    pub fn get_history_errors(&self, history_id: i64) -> Result<Vec<SyncError>> {
+        let connection = self.connect()?;
+        let mut smtp = connection
+            .prepare("SELECT path, message FROM sync_history_errors WHERE history_id=?1;")
+            .context("failed to prepare statement")?;
+        let result = smtp
+            .query_map([history_id], |row| {
+                Ok(SyncError::from_parts(
+                    row.get::<usize, String>(0)?.into(),
+                    row.get::<usize, String>(1)?,
+                ))
+            })
+            .context("failed to query statement")?
+            .collect::<rusqlite::Result<Vec<SyncError>>>()
+            .context("failed to read history errors")?;
+        Ok(result)
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime> {
+    Ok(chrono::DateTime::parse_from_rfc3339(value)
+        .with_context(|| format!("invalid timestamp {:?} in database", value))?
+        .with_timezone(&chrono::offset::Utc))
+}
+
+/// A single sync run as recorded in the history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// `None` until the entry is stored in the database
+    pub id: Option<i64>,
+    pub target: PathBuf,
+    pub started: DateTime,
+    pub finished: DateTime,
+    pub statistics: Statistics,
+    pub outcome: SyncOutcome,
+}
+
+impl From<&Report> for HistoryEntry {
+    fn from(report: &Report) -> Self {
+        Self {
+            id: None,
+            target: report.target.clone(),
+            started: report.started,
+            finished: report.finished,
+            statistics: report.statistics.clone(),
+            outcome: report.outcome,
+        }
+    }
 }
 
 impl DBManager for AppSettings {
@@ -102,26 +331,225 @@ impl DBManager for AppSettings {
         self.path.to_str().unwrap().to_owned()
     }
 
-    fn create_tables(&self, connection: &Connection) -> Result<()> {
-        connection.execute(
-            "
-            CREATE TABLE IF NOT EXISTS sources (
-                path TEXT NOT NULL
-            );
-            ",
-            [],
-        )?;
-
-        connection.execute(
-            "
-            CREATE TABLE IF NOT EXISTS settings (
-                key TEXT NOT NULL UNIQUE PRIMARY KEY,
-                value TEXT NOT NULL
-            )
-            ",
-            [],
-        )?;
+    fn migrations(&self) -> &'static [&'static str] {
+        APP_SETTINGS_MIGRATIONS
+    }
+}
 
-        Ok(())
+/// Migrations of the [`AppSettings`] database. Never change or remove an entry, only append new ones.
+const APP_SETTINGS_MIGRATIONS: &[&str] = &[
+    // 1: initial schema, databases created before versioning already contain these tables
+    "
+    CREATE TABLE IF NOT EXISTS sources (
+        path TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        key TEXT NOT NULL UNIQUE PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+    // 2: sync history
+    "
+    CREATE TABLE IF NOT EXISTS sync_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        target TEXT NOT NULL,
+        started TEXT NOT NULL,
+        finished TEXT NOT NULL,
+        files_copied INTEGER NOT NULL,
+        files_updated INTEGER NOT NULL,
+        files_skipped INTEGER NOT NULL,
+        files_failed INTEGER NOT NULL,
+        bytes INTEGER NOT NULL,
+        outcome TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sync_history_errors (
+        history_id INTEGER NOT NULL REFERENCES sync_history(id) ON DELETE CASCADE,
+        path TEXT NOT NULL,
+        message TEXT NOT NULL
+    );
+    ",
+    // 3: sources have to be unique, duplicates are removed
+    "
+    DELETE FROM sources WHERE rowid NOT IN (SELECT MIN(rowid) FROM sources GROUP BY path);
+    CREATE UNIQUE INDEX IF NOT EXISTS sources_path_unique ON sources (path);
+    ",
+    // 4: files created as hard links
+    "
+    ALTER TABLE sync_history ADD COLUMN files_linked INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 5: files copied as reflinks or by copy_file_range
+    "
+    ALTER TABLE sync_history ADD COLUMN files_reflinked INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE sync_history ADD COLUMN files_offloaded INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 6: files and directories removed in mirror mode
+    "
+    ALTER TABLE sync_history ADD COLUMN files_removed INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 7: files renamed in the target because they were moved in the sources
+    "
+    ALTER TABLE sync_history ADD COLUMN files_moved INTEGER NOT NULL DEFAULT 0;
+    ",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schema of databases created before versioning was introduced.
+    const FIXTURE_UNVERSIONED: &str = "
+        CREATE TABLE sources (path TEXT NOT NULL);
+        CREATE TABLE settings (key TEXT NOT NULL UNIQUE PRIMARY KEY, value TEXT NOT NULL);
+        INSERT INTO sources (path) VALUES ('/home/user/documents');
+        INSERT INTO sources (path) VALUES ('/home/user/pictures');
+        INSERT INTO sources (path) VALUES ('/home/user/documents');
+        INSERT INTO settings (key, value) VALUES ('target_path', '/media/backup');
+        INSERT INTO settings (key, value) VALUES ('Lang', 'German');
+    ";
+
+    /// Database at schema version 2 containing a recorded sync run.
+    const FIXTURE_VERSION_2: &str = "
+        CREATE TABLE schema_version (version INTEGER NOT NULL);
+        INSERT INTO schema_version (version) VALUES (2);
+        CREATE TABLE sources (path TEXT NOT NULL);
+        CREATE TABLE settings (key TEXT NOT NULL UNIQUE PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE sync_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            target TEXT NOT NULL,
+            started TEXT NOT NULL,
+            finished TEXT NOT NULL,
+            files_copied INTEGER NOT NULL,
+            files_updated INTEGER NOT NULL,
+            files_skipped INTEGER NOT NULL,
+            files_failed INTEGER NOT NULL,
+            bytes INTEGER NOT NULL,
+            outcome TEXT NOT NULL
+        );
+        CREATE TABLE sync_history_errors (
+            history_id INTEGER NOT NULL REFERENCES sync_history(id) ON DELETE CASCADE,
+            path TEXT NOT NULL,
+            message TEXT NOT NULL
+        );
+        INSERT INTO sources (path) VALUES ('/home/user/documents');
+        INSERT INTO sync_history (
+            target, started, finished, files_copied, files_updated,
+            files_skipped, files_failed, bytes, outcome
+        ) VALUES (
+            '/media/backup', '2025-01-01T10:00:00+00:00', '2025-01-01T10:05:00+00:00',
+            3, 1, 7, 1, 4096, 'Failed'
+        );
+        INSERT INTO sync_history_errors (history_id, path, message)
+            VALUES (1, '/home/user/documents/locked', 'permission denied');
+    ";
+
+    fn fixture_db(name: &str, fixture: Option<&str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "file-sync-rs-test-{}-{}.db",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        if let Some(fixture) = fixture {
+            Connection::open(&path)
+                .unwrap()
+                .execute_batch(fixture)
+                .unwrap();
+        }
+        path
+    }
+
+    fn version_of(db: &AppSettings) -> usize {
+        db.schema_version(&db.connect().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn new_database_has_latest_version() {
+        let db = AppSettings::new(fixture_db("new", None)).unwrap();
+        assert_eq!(version_of(&db), APP_SETTINGS_MIGRATIONS.len());
+        assert!(db.get_sources().unwrap().is_empty());
+        assert!(db.get_history().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unversioned_database_is_migrated() {
+        let db = AppSettings::new(fixture_db("unversioned", Some(FIXTURE_UNVERSIONED))).unwrap();
+        assert_eq!(version_of(&db), APP_SETTINGS_MIGRATIONS.len());
+        assert_eq!(
+            db.get_setting("target_path").unwrap(),
+            Some(String::from("/media/backup"))
+        );
+        assert_eq!(
+            db.get_setting("Lang").unwrap(),
+            Some(String::from("German"))
+        );
+        assert_eq!(
+            db.get_sources().unwrap(),
+            vec![
+                PathBuf::from("/home/user/documents"),
+                PathBuf::from("/home/user/pictures")
+            ]
+        );
+    }
+
+    #[test]
+    fn version_2_database_is_migrated() {
+        let db = AppSettings::new(fixture_db("version-2", Some(FIXTURE_VERSION_2))).unwrap();
+        assert_eq!(version_of(&db), APP_SETTINGS_MIGRATIONS.len());
+        let history = db.get_history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].statistics.copied, 3);
+        assert_eq!(history[0].statistics.bytes, 4096);
+        assert_eq!(history[0].statistics.linked, 0);
+        assert_eq!(history[0].statistics.reflinked, 0);
+        assert_eq!(history[0].outcome, SyncOutcome::Failed);
+        let errors = db.get_history_errors(history[0].id.unwrap()).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message(), "permission denied");
+    }
+
+    #[test]
+    fn migrating_twice_keeps_data() {
+        let path = fixture_db("twice", Some(FIXTURE_UNVERSIONED));
+        AppSettings::new(path.clone()).unwrap();
+        let db = AppSettings::new(path).unwrap();
+        assert_eq!(version_of(&db), APP_SETTINGS_MIGRATIONS.len());
+        assert_eq!(db.get_sources().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn duplicate_sources_are_rejected() {
+        let db = AppSettings::new(fixture_db("duplicate", None)).unwrap();
+        db.add_source("/home/user/documents".into()).unwrap();
+        assert!(db.add_source("/home/user/documents".into()).is_err());
+        assert_eq!(db.get_sources().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn history_errors_are_deleted_with_their_entry() {
+        let db = AppSettings::new(fixture_db("cascade", Some(FIXTURE_VERSION_2))).unwrap();
+        let connection = db.connect().unwrap();
+        connection
+            .execute("DELETE FROM sync_history WHERE id = 1", [])
+            .unwrap();
+        let errors: i64 = connection
+            .query_row("SELECT COUNT(*) FROM sync_history_errors", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(errors, 0);
+    }
+
+    #[test]
+    fn newer_database_is_rejected() {
+        let path = fixture_db("newer", None);
+        AppSettings::new(path.clone()).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "UPDATE schema_version SET version = ?1",
+                [(APP_SETTINGS_MIGRATIONS.len() + 1) as i64],
+            )
+            .unwrap();
+        assert!(AppSettings::new(path).is_err());
     }
 }
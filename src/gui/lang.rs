@@ -1,5 +1,13 @@
 use std::path::{Path, PathBuf};
 
+use super::utils::format_bytes;
+use crate::config::ImportIssue;
+use crate::syncing::DateTime;
+use crate::syncing::mount::AfterMountSync;
+use crate::syncing::report::{ReportFormat, SyncOutcome};
+use crate::syncing::space::{SpaceCheck, SpaceEstimate};
+use crate::syncing::sync::Statistics;
+
 #[derive(Debug)]
 pub enum Lang {
     German,
@@ -117,6 +125,21 @@ pub fn target_in_source_error(lang: &Lang, source: &Path) -> String {
     }
 }
 
+pub fn sources_with_same_name_error(lang: &Lang, first: &Path, second: &Path) -> String {
+    match lang {
+        Lang::German => format!(
+            "Die Quellen {} und {} haben denselben Namen und würden in dasselbe Verzeichnis synchronisiert",
+            first.to_str().unwrap(),
+            second.to_str().unwrap()
+        ),
+        _ => format!(
+            "The sources {} and {} have the same name and would be synced to the same directory",
+            first.to_str().unwrap(),
+            second.to_str().unwrap()
+        ),
+    }
+}
+
 pub fn last_sync(lang: &Lang) -> String {
     match lang {
         Lang::German => "Letzte Synchronisierung",
@@ -153,3 +176,483 @@ pub fn app_update_finished_description(
         }
     }
 }
+
+pub fn history(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Verlauf",
+        _ => "History",
+    }
+}
+
+pub fn back(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Zurück",
+        _ => "Back",
+    }
+}
+
+pub fn no_history(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Es wurde noch keine Synchronisierung durchgeführt.",
+        _ => "No synchronisation has been run yet.",
+    }
+}
+
+pub fn sync_outcome(lang: &Lang, outcome: &SyncOutcome) -> &'static str {
+    match (lang, outcome) {
+        (Lang::German, SyncOutcome::Success) => "Erfolgreich",
+        (Lang::German, SyncOutcome::Failed) => "Fehlgeschlagen",
+        (_, SyncOutcome::Success) => "Successful",
+        (_, SyncOutcome::Failed) => "Failed",
+    }
+}
+
+pub fn sync_statistics(lang: &Lang, statistics: &Statistics) -> String {
+    match lang {
+        Lang::German => format!(
+            "Kopiert: {} ({} per Reflink, {} vom Dateisystem), Aktualisiert: {}, Übersprungen: {}, Verlinkt: {}, Verschoben: {}, Entfernt: {}, Fehlgeschlagen: {}, Geschrieben: {}",
+            statistics.copied,
+            statistics.reflinked,
+            statistics.offloaded,
+            statistics.updated,
+            statistics.skipped,
+            statistics.linked,
+            statistics.moved,
+            statistics.removed,
+            statistics.failed,
+            format_bytes(statistics.bytes)
+        ),
+        _ => format!(
+            "Copied: {} ({} reflinked, {} offloaded), Updated: {}, Skipped: {}, Linked: {}, Moved: {}, Removed: {}, Failed: {}, Written: {}",
+            statistics.copied,
+            statistics.reflinked,
+            statistics.offloaded,
+            statistics.updated,
+            statistics.skipped,
+            statistics.linked,
+            statistics.moved,
+            statistics.removed,
+            statistics.failed,
+            format_bytes(statistics.bytes)
+        ),
+    }
+}
+
+pub fn history_errors(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Fehler",
+        _ => "Errors",
+    }
+}
+
+pub fn no_history_errors(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bei dieser Synchronisierung sind keine Fehler aufgetreten.",
+        _ => "No errors occurred during this synchronisation.",
+    }
+}
+
+pub fn export_report(lang: &Lang, format: ReportFormat) -> &'static str {
+    match (lang, format) {
+        (Lang::German, ReportFormat::Json) => "Bericht als JSON exportieren",
+        (Lang::German, ReportFormat::Html) => "Bericht als HTML exportieren",
+        (_, ReportFormat::Json) => "Export Report as JSON",
+        (_, ReportFormat::Html) => "Export Report as HTML",
+    }
+}
+
+pub fn export_config(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Konfiguration Exportieren",
+        _ => "Export Configuration",
+    }
+}
+
+pub fn import_config(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Konfiguration Importieren",
+        _ => "Import Configuration",
+    }
+}
+
+pub fn import_config_invalid(lang: &Lang, issues: &str) -> String {
+    match lang {
+        Lang::German => format!("Die Konfiguration ist ungültig:\n{}", issues),
+        _ => format!("The configuration is invalid:\n{}", issues),
+    }
+}
+
+pub fn import_config_conflicts(lang: &Lang, issues: &str) -> String {
+    match lang {
+        Lang::German => format!(
+            "Die Konfiguration weicht von der aktuellen ab:\n{}\n\nTrotzdem importieren?",
+            issues
+        ),
+        _ => format!(
+            "The configuration differs from the current one:\n{}\n\nImport anyway?",
+            issues
+        ),
+    }
+}
+
+pub fn import_issue(lang: &Lang, issue: &ImportIssue) -> String {
+    match lang {
+        Lang::German => match issue {
+            ImportIssue::SourceDoesNotExist(path) => {
+                format!("Die Quelle {} existiert nicht.", path.to_str().unwrap())
+            }
+            ImportIssue::TargetDoesNotExist(path) => {
+                format!("Das Ziel {} existiert nicht.", path.to_str().unwrap())
+            }
+            ImportIssue::SourcesOverlap(path1, path2) => format!(
+                "Die Quellen {} und {} überlappen.",
+                path1.to_str().unwrap(),
+                path2.to_str().unwrap()
+            ),
+            ImportIssue::TargetInSource(path) => format!(
+                "Das Ziel befindet sich in der Quelle {}.",
+                path.to_str().unwrap()
+            ),
+            ImportIssue::SourceInTarget(path) => format!(
+                "Die Quelle {} befindet sich im Ziel.",
+                path.to_str().unwrap()
+            ),
+            ImportIssue::SourceExists(path) => format!(
+                "Die Quelle {} existiert bereits und wird übersprungen.",
+                path.to_str().unwrap()
+            ),
+            ImportIssue::TargetDiffers { current, new } => format!(
+                "Das Ziel {} wird durch {} ersetzt.",
+                current.to_str().unwrap(),
+                new.to_str().unwrap()
+            ),
+            ImportIssue::InvalidSetting { key, error } => {
+                format!("Die Einstellung {} ist ungültig: {}", key, error)
+            }
+            ImportIssue::SettingDiffers { key, current, new } => format!(
+                "Die Einstellung {} wird von {} auf {} geändert.",
+                key, current, new
+            ),
+        },
+        _ => issue.to_string(),
+    }
+}
+
+pub fn watch_mode(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Änderungen automatisch synchronisieren",
+        _ => "Sync Changes Automatically",
+    }
+}
+
+pub fn schedule(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Zeitplan",
+        _ => "Schedule",
+    }
+}
+
+pub fn schedule_placeholder(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "z.B. every 6h, daily 02:30, cron 0 3 * * *",
+        _ => "e.g. every 6h, daily 02:30, cron 0 3 * * *",
+    }
+}
+
+pub fn save(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Speichern",
+        _ => "Save",
+    }
+}
+
+pub fn next_scheduled_sync(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Nächste geplante Synchronisierung",
+        _ => "Next Scheduled Synchronisation",
+    }
+}
+
+pub fn sync_on_mount(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Synchronisieren, wenn das Ziellaufwerk eingehängt wird",
+        _ => "Sync When the Target Drive Is Mounted",
+    }
+}
+
+pub fn after_mount_sync(lang: &Lang, after: AfterMountSync) -> &'static str {
+    match (lang, after) {
+        (Lang::German, AfterMountSync::Nothing) => "Nichts tun",
+        (Lang::German, AfterMountSync::Notify) => "Benachrichtigen",
+        (Lang::German, AfterMountSync::Unmount) => "Aushängen",
+        (_, AfterMountSync::Nothing) => "Do Nothing",
+        (_, AfterMountSync::Notify) => "Notify",
+        (_, AfterMountSync::Unmount) => "Unmount",
+    }
+}
+
+pub fn drive_can_be_removed(lang: &Lang, outcome: &SyncOutcome) -> &'static str {
+    match (lang, outcome) {
+        (Lang::German, SyncOutcome::Success) => {
+            "Die Synchronisierung ist abgeschlossen, das Ziellaufwerk kann entfernt werden."
+        }
+        (Lang::German, SyncOutcome::Failed) => {
+            "Die Synchronisierung ist fehlgeschlagen, das Ziellaufwerk kann entfernt werden."
+        }
+        (_, SyncOutcome::Success) => "The sync finished, the target drive can be removed.",
+        (_, SyncOutcome::Failed) => "The sync failed, the target drive can be removed.",
+    }
+}
+
+pub fn target_mismatch(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Anderes Ziel",
+        _ => "Different Target",
+    }
+}
+
+pub fn adopt_target(lang: &Lang, reason: &str) -> String {
+    match lang {
+        Lang::German => format!(
+            "{}\n\nMöglicherweise ist ein anderes Laufwerk am Zielpfad eingehängt. Soll trotzdem in dieses Verzeichnis synchronisiert und es als Ziel übernommen werden?",
+            reason
+        ),
+        _ => format!(
+            "{}\n\nA different drive may be mounted at the target path. Do you want to sync into this directory anyway and use it as the target from now on?",
+            reason
+        ),
+    }
+}
+
+pub fn resume_sync(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Synchronisierung fortsetzen",
+        _ => "Resume Synchronisation",
+    }
+}
+
+pub fn resume_sync_question(lang: &Lang, started: &DateTime, completed_files: usize) -> String {
+    let started = started
+        .with_timezone(&chrono::Local)
+        .format("%d.%m.%Y %H:%M");
+    match lang {
+        Lang::German => format!(
+            "Die Synchronisierung vom {} wurde nach {} Dateien unterbrochen. Soll sie fortgesetzt werden? Sonst werden alle Dateien erneut verglichen.",
+            started, completed_files
+        ),
+        _ => format!(
+            "The sync started at {} was interrupted after {} files. Do you want to resume it? Otherwise all files are compared again.",
+            started, completed_files
+        ),
+    }
+}
+
+pub fn settings(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Einstellungen",
+        _ => "Settings",
+    }
+}
+
+pub fn performance(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Leistung",
+        _ => "Performance",
+    }
+}
+
+pub fn workers(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Gleichzeitig synchronisierte Dateien",
+        _ => "Files Synced at Once",
+    }
+}
+
+pub fn large_file_workers(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Gleichzeitig synchronisierte große Dateien",
+        _ => "Large Files Synced at Once",
+    }
+}
+
+pub fn large_file_threshold(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Große Dateien ab (MiB)",
+        _ => "Large Files From (MiB)",
+    }
+}
+
+pub fn low_io_priority(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Niedrige Festplattenpriorität",
+        _ => "Low Disk Priority",
+    }
+}
+
+pub fn delta_transfer(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bei großen Dateien nur geänderte Teile schreiben (nur lokale Ziele)",
+        _ => "Only Write the Changed Parts of Large Files (Local Targets Only)",
+    }
+}
+
+pub fn invalid_count(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bitte eine Zahl größer als 0 eingeben.",
+        _ => "Please enter a number greater than 0.",
+    }
+}
+
+pub fn indexing(lang: &Lang, dirs: usize, files: usize) -> String {
+    match lang {
+        Lang::German => format!("Durchsuche: {} Ordner, {} Dateien", dirs, files),
+        _ => format!("Indexing: {} directories, {} files", dirs, files),
+    }
+}
+
+pub fn bandwidth(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bandbreite",
+        _ => "Bandwidth",
+    }
+}
+
+pub fn bandwidth_limit(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bandbreitenlimit (Bytes/s)",
+        _ => "Bandwidth Limit (bytes/s)",
+    }
+}
+
+pub fn bandwidth_schedule(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Limits nach Uhrzeit (0 = unbegrenzt)",
+        _ => "Limits by Time of Day (0 = unlimited)",
+    }
+}
+
+pub fn unlimited(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Unbegrenzt",
+        _ => "Unlimited",
+    }
+}
+
+pub fn invalid_bandwidth_limit(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Bitte eine Zahl eingeben oder das Feld für unbegrenzt leeren.",
+        _ => "Please enter a number or clear the field for no limit.",
+    }
+}
+
+pub fn space_estimate(lang: &Lang, space: &SpaceEstimate) -> String {
+    match lang {
+        Lang::German => format!(
+            "Benötigt: {}, frei: {} von {}",
+            format_bytes(space.needed),
+            format_bytes(space.available),
+            format_bytes(space.capacity)
+        ),
+        _ => format!(
+            "Needed: {}, free: {} of {}",
+            format_bytes(space.needed),
+            format_bytes(space.available),
+            format_bytes(space.capacity)
+        ),
+    }
+}
+
+pub fn insufficient_space(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Nicht genug Speicherplatz",
+        _ => "Not Enough Space",
+    }
+}
+
+pub fn sync_anyway_question(lang: &Lang, space: &SpaceEstimate) -> String {
+    match lang {
+        Lang::German => format!(
+            "Die Synchronisation benötigt {}, auf dem Ziel sind aber nur {} von {} frei. Es wurde nichts geschrieben.\n\nTrotzdem synchronisieren?",
+            format_bytes(space.needed),
+            format_bytes(space.available),
+            format_bytes(space.capacity)
+        ),
+        _ => format!(
+            "The sync needs {}, but only {} of {} are free on the target. Nothing was written.\n\nSync anyway?",
+            format_bytes(space.needed),
+            format_bytes(space.available),
+            format_bytes(space.capacity)
+        ),
+    }
+}
+
+pub fn space_check(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Wenn der Platz auf dem Ziel nicht reicht",
+        _ => "If the Target Does Not Have Enough Space",
+    }
+}
+
+pub fn space_check_option(lang: &Lang, check: SpaceCheck) -> &'static str {
+    match (lang, check) {
+        (Lang::German, SpaceCheck::Refuse) => "Nicht synchronisieren",
+        (Lang::German, SpaceCheck::Warn) => "Warnen",
+        (Lang::German, SpaceCheck::Off) => "Nicht prüfen",
+        (_, SpaceCheck::Refuse) => "Do Not Sync",
+        (_, SpaceCheck::Warn) => "Warn",
+        (_, SpaceCheck::Off) => "Do Not Check",
+    }
+}
+
+pub fn metadata(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Metadaten",
+        _ => "Metadata",
+    }
+}
+
+pub fn preserve_metadata(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Besitzer, Rechte und erweiterte Attribute übernehmen",
+        _ => "Preserve Owner, Permissions and Extended Attributes",
+    }
+}
+
+pub fn preserve_acls(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "POSIX-ACLs übernehmen (nur Linux)",
+        _ => "Preserve POSIX ACLs (Linux only)",
+    }
+}
+
+pub fn directories(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Ordner",
+        _ => "Directories",
+    }
+}
+
+pub fn skip_empty_dirs(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "Leere Ordner nicht anlegen",
+        _ => "Do Not Create Empty Directories",
+    }
+}
+
+pub fn mirror(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => {
+            "Spiegeln: in den Quellen gelöschte Dateien und Ordner im Ziel löschen, verschobene im Ziel verschieben (nur lokale Ziele)"
+        }
+        _ => {
+            "Mirror: Delete Files and Directories From the Target That Were Deleted in the Sources, Move the Moved Ones (Local Targets Only)"
+        }
+    }
+}
+
+pub fn sftp_target_cli_only(lang: &Lang) -> &'static str {
+    match lang {
+        Lang::German => "SFTP-Ziele werden mit dem Befehl \"sftp-target\" eingerichtet.",
+        _ => "SFTP targets are set up with the \"sftp-target\" command.",
+    }
+}
@@ -1,12 +1,18 @@
 use anyhow::{Context, Result};
 use iced::widget::{self, button, column, row};
 use iced::{Element, Length, Task};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use utils::async_error_popup;
 
+use crate::config;
 use crate::db;
-use crate::syncing::{self, sync};
+use crate::schedule;
+use crate::syncing::bandwidth::RateLimiter;
+use crate::syncing::mount::{self, AfterMountSync};
+use crate::syncing::options::SyncOptions;
+use crate::syncing::space::{InsufficientSpace, SpaceCheck, SpaceEstimate};
+use crate::syncing::{self, report, sync, watch};
 use crate::update;
 
 mod lang;
@@ -19,6 +25,31 @@ struct App {
     syncer_state: Option<sync::State>,
     last_sync: Option<syncing::LastSync>,
     currently_syncing: bool,
+    show_history: bool,
+    show_settings: bool,
+    settings_inputs: views::settings::Inputs,
+    /// the recorded syncs, loaded when the history is opened and after each sync
+    history: Vec<db::HistoryEntry>,
+    selected_history: Option<i64>,
+    /// the errors of the selected sync
+    history_errors: Vec<sync::SyncError>,
+    last_report: Option<Arc<report::Report>>,
+    /// whether changes in the sources are synced automatically
+    watching: bool,
+    /// changes that were reported while a sync was running
+    pending_changes: Option<watch::Changes>,
+    /// whether the running sync was started by watch mode
+    watch_sync: bool,
+    schedule_input: String,
+    /// why the entered schedule could not be saved
+    schedule_error: Option<String>,
+    /// set if syncing when the target drive is mounted is enabled
+    mount_watcher: Option<mount::MountWatcher>,
+    after_mount_sync: AfterMountSync,
+    /// the target drive if the running sync was started by mounting it
+    mounted: Option<mount::Mount>,
+    /// shared with the running sync, so the bandwidth limit can be changed while it runs
+    rate_limiter: RateLimiter,
 }
 
 #[derive(Debug, Clone)]
@@ -26,9 +57,34 @@ enum Message {
     SwitchLanguage,
     TargetView(views::target::Message),
     SourceView(views::source::Message),
+    HistoryView(views::history::Message),
+    SettingsView(views::settings::Message),
+    ScheduleView(views::schedule::Message),
+    CheckSchedule,
+    ToggleHistory,
+    ToggleSettings,
     StartSync,
-    FinishedSync,
+    /// Start the sync after asking whether the interrupted sync should be resumed.
+    ResumeSync(bool),
+    ToggleWatch(bool),
+    ToggleSyncOnMount(bool),
+    SelectAfterMountSync(AfterMountSync),
+    CheckMount,
+    WatchChanges(watch::Changes),
+    FinishedSync(Arc<report::Report>),
+    ExportReport(report::ReportFormat),
+    ExportConfig,
+    ImportConfig,
+    ApplyImport(config::Config),
     SyncUpdate(sync::State),
+    /// The target has a different id, contains the reason.
+    TargetMismatch(String),
+    /// Use the target even though it has a different id.
+    AdoptTarget,
+    /// The files do not fit on the target, ask whether to sync anyway.
+    InsufficientSpace(SpaceEstimate),
+    /// Sync without refusing because of the free space of the target.
+    SyncIgnoringSpace,
     UpdateLastSync,
     UpdateApplication,
     Error(Arc<anyhow::Error>),
@@ -48,19 +104,28 @@ impl App {
             _ => lang::Lang::English,
         };
 
-        let last_sync = if let Ok(Some(target_path)) = db.get_setting("target_path") {
-            match syncing::get_last_sync(target_path.into())
-                .context("error while loading last sync state")
-            {
-                Err(error) => {
-                    let error_message = utils::error_chain_string(error);
-                    utils::error_popup(&error_message);
-                    panic!("{}", error_message);
-                }
-                Ok(last_sync) => last_sync,
+        let last_sync = match load_last_sync(&db).context("error while loading last sync state") {
+            Err(error) => {
+                let error_message = utils::error_chain_string(error);
+                utils::error_popup(&error_message);
+                panic!("{}", error_message);
             }
-        } else {
-            None
+            Ok(last_sync) => last_sync,
+        };
+
+        let (schedule_input, schedule_error) = match schedule::get_schedule(&db) {
+            Ok(Some(schedule)) => (schedule.to_string(), None),
+            Ok(None) => (String::new(), None),
+            Err(error) => (String::new(), Some(format!("{:#}", error))),
+        };
+
+        let mount_watcher = match db.get_setting(mount::SYNC_ON_MOUNT_SETTING) {
+            Ok(Some(value)) if value == "true" => mount::MountWatcher::new().ok(),
+            _ => None,
+        };
+        let after_mount_sync = match db.get_setting(mount::AFTER_MOUNT_SYNC_SETTING) {
+            Ok(Some(value)) => AfterMountSync::from(value.as_str()),
+            _ => AfterMountSync::default(),
         };
 
         (
@@ -70,6 +135,22 @@ impl App {
                 db,
                 currently_syncing: false,
                 syncer_state: None,
+                show_history: false,
+                show_settings: false,
+                settings_inputs: views::settings::Inputs::default(),
+                history: Vec::new(),
+                selected_history: None,
+                history_errors: Vec::new(),
+                last_report: None,
+                watching: false,
+                pending_changes: None,
+                watch_sync: false,
+                schedule_input,
+                schedule_error,
+                mount_watcher,
+                after_mount_sync,
+                mounted: None,
+                rate_limiter: RateLimiter::default(),
             },
             Task::none(),
         )
@@ -83,6 +164,24 @@ impl App {
                     button("Language")
                         .on_press(Message::SwitchLanguage)
                         .style(gray_button),
+                    button(lang::history(&self.lang))
+                        .on_press(Message::ToggleHistory)
+                        .style(gray_button),
+                    button(lang::settings(&self.lang))
+                        .on_press(Message::ToggleSettings)
+                        .style(gray_button),
+                    button(lang::export_config(&self.lang))
+                        .on_press(Message::ExportConfig)
+                        .style(gray_button),
+                    button(lang::import_config(&self.lang))
+                        .on_press_maybe({
+                            if self.currently_syncing {
+                                None
+                            } else {
+                                Some(Message::ImportConfig)
+                            }
+                        })
+                        .style(gray_button),
                     button("Update")
                         .on_press(Message::UpdateApplication)
                         .style(gray_button),
@@ -94,6 +193,20 @@ impl App {
             .width(iced::Length::Fill)
             .height(iced::Length::Shrink)
             .padding(10),
+        ];
+
+        if self.show_history {
+            return root_col
+                .push(views::history::view(self).map(Message::HistoryView))
+                .into();
+        }
+        if self.show_settings {
+            return root_col
+                .push(views::settings::view(self).map(Message::SettingsView))
+                .into();
+        }
+
+        root_col = root_col.push(
             column![
                 views::source::view(self).map(Message::SourceView),
                 views::target::view(self).map(Message::TargetView),
@@ -144,12 +257,22 @@ impl App {
                 })
                 .padding(15)
                 .width(Length::Fill),
+                widget::Container::new(
+                    widget::toggler(self.watching)
+                        .label(lang::watch_mode(&self.lang))
+                        .on_toggle(Message::ToggleWatch)
+                        .width(Length::Shrink)
+                )
+                .align_x(iced::alignment::Horizontal::Center)
+                .width(Length::Fill),
+                self.mount_sync_view(),
+                views::schedule::view(self).map(Message::ScheduleView),
             ]
             .height(Length::FillPortion(20))
             .width(Length::Fill)
             .spacing(10)
             .padding(iced::Padding::from(10.0)),
-        ];
+        );
 
         if self.currently_syncing {
             root_col = root_col.push(
@@ -167,6 +290,18 @@ impl App {
                             String::from("Indexing")
                         }
                     )),
+                    widget::text(match &self.syncer_state {
+                        Some(state) if state.indexing() => {
+                            lang::indexing(&self.lang, state.indexed_dirs(), state.indexed_files())
+                        }
+                        _ => String::new(),
+                    }),
+                    widget::text(
+                        match self.syncer_state.as_ref().and_then(sync::State::space) {
+                            Some(space) => lang::space_estimate(&self.lang, &space),
+                            None => String::new(),
+                        }
+                    ),
                     widget::progress_bar(
                         0_f32..=if let Some(state) = &self.syncer_state {
                             state.total()
@@ -183,6 +318,23 @@ impl App {
                 ]
                 .align_x(iced::Alignment::Center),
             )
+        } else if self.last_report.is_some() {
+            root_col = root_col.push(
+                widget::Container::new(
+                    row![
+                        button(lang::export_report(&self.lang, report::ReportFormat::Json))
+                            .on_press(Message::ExportReport(report::ReportFormat::Json))
+                            .style(gray_button),
+                        button(lang::export_report(&self.lang, report::ReportFormat::Html))
+                            .on_press(Message::ExportReport(report::ReportFormat::Html))
+                            .style(gray_button),
+                    ]
+                    .spacing(10),
+                )
+                .align_x(iced::alignment::Horizontal::Center)
+                .width(Length::Fill)
+                .padding(10),
+            )
         }
 
         root_col.into()
@@ -212,65 +364,469 @@ impl App {
             Message::SourceView(view_message) => {
                 return views::source::update(self, view_message).map(Message::SourceView);
             }
-            Message::StartSync => {
-                // check if target is set
-                let target = match match self.db.get_setting("target_path") {
-                    Ok(value) => value,
-                    Err(error) => {
-                        return Task::future(utils::async_error_popup(&utils::error_chain_string(
-                            error,
-                        )))
-                        .discard();
-                    }
-                } {
-                    None => {
-                        return Task::future(utils::async_error_popup(
-                            &lang::target_does_not_exist_error(&self.lang),
-                        ))
-                        .discard();
+            Message::ScheduleView(view_message) => {
+                return views::schedule::update(self, view_message).map(Message::ScheduleView);
+            }
+            Message::CheckSchedule => {
+                let now = chrono::offset::Utc::now();
+                match schedule::is_due(&self.db, &now) {
+                    Ok(true) if !self.currently_syncing => {}
+                    Ok(_) => return Task::none(),
+                    Err(error) => return Task::done(Message::Error(error.into())),
+                }
+                if let Err(error) = schedule::mark_run(&self.db, &now) {
+                    return Task::done(Message::Error(error.into()));
+                }
+                return match self.create_syncer(None) {
+                    Ok(syncer) => {
+                        self.currently_syncing = true;
+                        self.watch_sync = false;
+                        create_sync_task(resume_interrupted(syncer))
                     }
-                    Some(target_string) => PathBuf::from(target_string),
+                    Err(task) => task,
                 };
-
-                // check if sources are available
-                let sources = self.db.get_sources().unwrap();
-                if sources.is_empty() {
-                    return Task::future(utils::async_error_popup(
-                        &lang::sources_does_not_exist_error(&self.lang),
-                    ))
-                    .discard();
+            }
+            Message::HistoryView(view_message) => {
+                return views::history::update(self, view_message).map(Message::HistoryView);
+            }
+            Message::ToggleHistory => {
+                self.show_history = !self.show_history;
+                self.show_settings = false;
+                self.selected_history = None;
+                self.history_errors.clear();
+                if self.show_history
+                    && let Err(error) = self.reload_history()
+                {
+                    return Task::done(Message::Error(error.into()));
                 }
-
+            }
+            Message::SettingsView(view_message) => {
+                return views::settings::update(self, view_message).map(Message::SettingsView);
+            }
+            Message::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                self.show_history = false;
+                self.settings_inputs = views::settings::Inputs::default();
+            }
+            Message::StartSync => {
                 // check if a syncer is already running
                 if !self.currently_syncing {
-                    // create and set syncer
-                    self.currently_syncing = true;
-                    return create_sync_task(match sync::Syncer::new(sources, target) {
-                        Ok(syncer) => syncer,
-                        Err(error) => {
-                            return sync_invalid_parameters_popup(&self.lang, error);
+                    return match self.create_syncer(None) {
+                        Ok(syncer) => match syncer.find_checkpoint() {
+                            Ok(Some(checkpoint)) => Task::future(
+                                rfd::AsyncMessageDialog::new()
+                                    .set_title(lang::resume_sync(&self.lang))
+                                    .set_buttons(rfd::MessageButtons::YesNo)
+                                    .set_description(lang::resume_sync_question(
+                                        &self.lang,
+                                        checkpoint.started(),
+                                        checkpoint.completed_files(),
+                                    ))
+                                    .show(),
+                            )
+                            .map(|result| {
+                                Message::ResumeSync(result == rfd::MessageDialogResult::Yes)
+                            }),
+                            _ => {
+                                self.currently_syncing = true;
+                                self.watch_sync = false;
+                                create_sync_task(syncer)
+                            }
+                        },
+                        Err(task) => task,
+                    };
+                }
+            }
+            Message::ResumeSync(resume) => {
+                if !self.currently_syncing {
+                    return match self.create_syncer(None) {
+                        Ok(syncer) => {
+                            self.currently_syncing = true;
+                            self.watch_sync = false;
+                            create_sync_task(if resume {
+                                resume_interrupted(syncer)
+                            } else {
+                                syncer
+                            })
                         }
+                        Err(task) => task,
+                    };
+                }
+            }
+            Message::ToggleWatch(watching) => {
+                self.watching = watching;
+                self.pending_changes = None;
+            }
+            Message::ToggleSyncOnMount(enabled) => {
+                let result = if enabled {
+                    mount::MountWatcher::new().and_then(|watcher| {
+                        self.db.set_setting(mount::SYNC_ON_MOUNT_SETTING, "true")?;
+                        self.mount_watcher = Some(watcher);
+                        Ok(())
+                    })
+                } else {
+                    self.mount_watcher = None;
+                    self.db.del_setting(mount::SYNC_ON_MOUNT_SETTING)
+                };
+                if let Err(error) = result {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
+            Message::SelectAfterMountSync(after) => {
+                self.after_mount_sync = after;
+                if let Err(error) = self
+                    .db
+                    .set_setting(mount::AFTER_MOUNT_SYNC_SETTING, (&after).into())
+                {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
+            Message::CheckMount => {
+                let Some(mount_watcher) = &mut self.mount_watcher else {
+                    return Task::none();
+                };
+                let Ok(Some(target)) = self.db.get_setting("target_path") else {
+                    return Task::none();
+                };
+                match mount_watcher.poll(Path::new(&target)) {
+                    Ok(Some(mount)) if !self.currently_syncing => {
+                        return match self.create_syncer(None) {
+                            Ok(syncer) => {
+                                self.currently_syncing = true;
+                                self.watch_sync = false;
+                                self.mounted = Some(mount);
+                                create_sync_task(resume_interrupted(syncer))
+                            }
+                            Err(task) => task,
+                        };
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        self.mount_watcher = None;
+                        return Task::done(Message::Error(error.into()));
+                    }
+                }
+            }
+            Message::WatchChanges(changes) => {
+                if !self.watching {
+                    return Task::none();
+                }
+                if self.currently_syncing {
+                    self.pending_changes = Some(match self.pending_changes.take() {
+                        Some(pending) => pending.merge(changes),
+                        None => changes,
                     });
+                    return Task::none();
                 }
+                return self.start_watch_sync(changes);
             }
-            Message::FinishedSync => {
+            Message::FinishedSync(report) => {
                 self.currently_syncing = false;
                 self.syncer_state = None;
+                if let Some(target_id) = &report.target_id
+                    && let Err(error) = self.db.set_setting(syncing::TARGET_ID_SETTING, target_id)
+                {
+                    return Task::done(Message::Error(error.into()));
+                }
+                if !self.watch_sync || report.has_changes() {
+                    let history_result = self
+                        .db
+                        .add_history_entry(&db::HistoryEntry::from(report.as_ref()), &report.errors)
+                        .context("failed to record sync history");
+                    self.last_report = Some(report.clone());
+                    if let Err(error) = history_result {
+                        return Task::done(Message::Error(error.into()));
+                    }
+                }
                 if let Err(error) = self.reload_last_sync() {
                     return Task::done(Message::Error(error.into()));
                 }
+                if self.show_history
+                    && let Err(error) = self.reload_history()
+                {
+                    return Task::done(Message::Error(error.into()));
+                }
+                if let Some(mount) = self.mounted.take() {
+                    return self.finish_mount_sync(&mount, &report.outcome);
+                }
+                if self.watching
+                    && let Some(changes) = self.pending_changes.take()
+                {
+                    return self.start_watch_sync(changes);
+                }
             }
             Message::SyncUpdate(state) => self.syncer_state = Some(state),
+            Message::TargetMismatch(reason) => {
+                // every further sync would be refused as well
+                self.watching = false;
+                self.pending_changes = None;
+                return Task::future(
+                    rfd::AsyncMessageDialog::new()
+                        .set_title(lang::target_mismatch(&self.lang))
+                        .set_level(rfd::MessageLevel::Warning)
+                        .set_buttons(rfd::MessageButtons::YesNo)
+                        .set_description(lang::adopt_target(&self.lang, &reason))
+                        .show(),
+                )
+                .then(|result| {
+                    if result == rfd::MessageDialogResult::Yes {
+                        Task::done(Message::AdoptTarget)
+                    } else {
+                        Task::none()
+                    }
+                });
+            }
+            Message::InsufficientSpace(space) => {
+                // further watch syncs would be refused as well
+                self.watching = false;
+                self.pending_changes = None;
+                return Task::future(
+                    rfd::AsyncMessageDialog::new()
+                        .set_title(lang::insufficient_space(&self.lang))
+                        .set_level(rfd::MessageLevel::Warning)
+                        .set_buttons(rfd::MessageButtons::YesNo)
+                        .set_description(lang::sync_anyway_question(&self.lang, &space))
+                        .show(),
+                )
+                .then(|result| {
+                    if result == rfd::MessageDialogResult::Yes {
+                        Task::done(Message::SyncIgnoringSpace)
+                    } else {
+                        Task::none()
+                    }
+                });
+            }
+            Message::SyncIgnoringSpace => {
+                if !self.currently_syncing {
+                    return match self.create_syncer(None) {
+                        Ok(syncer) => {
+                            self.currently_syncing = true;
+                            self.watch_sync = false;
+                            create_sync_task(resume_interrupted(
+                                syncer.with_space_check(SpaceCheck::Warn),
+                            ))
+                        }
+                        Err(task) => task,
+                    };
+                }
+            }
+            Message::AdoptTarget => {
+                if let Err(error) = self.db.del_setting(syncing::TARGET_ID_SETTING) {
+                    return Task::done(Message::Error(error.into()));
+                }
+                return Task::done(Message::StartSync);
+            }
             Message::UpdateLastSync => {
                 if let Err(error) = self.reload_last_sync() {
                     return Task::done(Message::Error(error.into()));
                 }
             }
             Message::UpdateApplication => return self.update_application(),
+            Message::ExportConfig => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("TOML", &["toml"])
+                    .add_filter("JSON", &["json"])
+                    .set_file_name("file_sync_rs.toml")
+                    .save_file()
+                    && let Err(error) =
+                        config::Config::from_db(&self.db).and_then(|config| config.write(&path))
+                {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
+            Message::ImportConfig => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("TOML / JSON", &["toml", "json"])
+                    .pick_file()
+                {
+                    return match self.check_import(&path) {
+                        Ok(task) => task,
+                        Err(error) => Task::done(Message::Error(error.into())),
+                    };
+                }
+            }
+            Message::ApplyImport(config) => {
+                if let Err(error) = config.apply(&self.db) {
+                    return Task::done(Message::Error(error.into()));
+                }
+                if let Ok(Some(lang_str)) = self.db.get_setting("Lang") {
+                    self.lang = lang::Lang::from(lang_str.as_str());
+                }
+                if let Err(error) = self.reload_last_sync() {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
+            Message::ExportReport(format) => {
+                if let Some(report) = &self.last_report
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter(format.extension(), &[format.extension()])
+                        .set_file_name(format!(
+                            "file_sync_rs_report_{}.{}",
+                            report.started.format("%Y-%m-%d_%H-%M-%S"),
+                            format.extension()
+                        ))
+                        .save_file()
+                    && let Err(error) = report.write(&path, format)
+                {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
         }
         Task::none()
     }
 
+    /// Creates a syncer for the configured sources and target.
+    /// With `changes` only the changed paths are synced.
+    /// Returns a task showing the error if the configuration is invalid.
+    fn create_syncer(
+        &self,
+        changes: Option<&watch::Changes>,
+    ) -> Result<sync::Syncer, Task<Message>> {
+        // check if target is set
+        let target = match match self.db.get_setting("target_path") {
+            Ok(value) => value,
+            Err(error) => {
+                return Err(
+                    Task::future(utils::async_error_popup(&utils::error_chain_string(error)))
+                        .discard(),
+                );
+            }
+        } {
+            None => {
+                return Err(Task::future(utils::async_error_popup(
+                    &lang::target_does_not_exist_error(&self.lang),
+                ))
+                .discard());
+            }
+            Some(target_string) => PathBuf::from(target_string),
+        };
+
+        // check if sources are available
+        let sources = match self.db.get_sources() {
+            Ok(sources) => sources,
+            Err(error) => return Err(Task::done(Message::Error(error.into()))),
+        };
+        let target_id = match self.db.get_setting(syncing::TARGET_ID_SETTING) {
+            Ok(target_id) => target_id,
+            Err(error) => return Err(Task::done(Message::Error(error.into()))),
+        };
+        let options = match SyncOptions::load(&self.db) {
+            Ok(options) => options,
+            Err(error) => return Err(Task::done(Message::Error(error.into()))),
+        };
+        let backend = match syncing::backend::load(&self.db) {
+            Ok(backend) => backend,
+            Err(error) => return Err(Task::done(Message::Error(error.into()))),
+        };
+        if sources.is_empty() {
+            return Err(Task::future(utils::async_error_popup(
+                &lang::sources_does_not_exist_error(&self.lang),
+            ))
+            .discard());
+        }
+
+        match changes {
+            Some(watch::Changes::Paths(paths)) => sync::Syncer::new_partial(sources, target, paths),
+            Some(watch::Changes::Rescan) | None => sync::Syncer::new(sources, target),
+        }
+        .map(|syncer| {
+            self.rate_limiter.set_limit(options.bandwidth_limit);
+            self.rate_limiter
+                .set_schedule(options.bandwidth_schedule.clone());
+            syncer
+                .with_target_id(target_id)
+                .with_options(options)
+                .with_rate_limiter(self.rate_limiter.clone())
+                .with_backend(backend)
+        })
+        .map_err(|error| sync_invalid_parameters_popup(&self.lang, error))
+    }
+
+    /// Syncs the changes reported by watch mode. Watch mode is disabled if that is not possible.
+    fn start_watch_sync(&mut self, changes: watch::Changes) -> Task<Message> {
+        match self.create_syncer(Some(&changes)) {
+            Ok(syncer) => {
+                self.currently_syncing = true;
+                self.watch_sync = true;
+                create_sync_task(resume_interrupted(syncer))
+            }
+            Err(task) => {
+                self.watching = false;
+                task
+            }
+        }
+    }
+
+    fn mount_sync_view(&self) -> Element<'_, Message> {
+        let mut col = column![
+            widget::toggler(self.mount_watcher.is_some())
+                .label(lang::sync_on_mount(&self.lang))
+                .on_toggle(Message::ToggleSyncOnMount)
+                .width(Length::Shrink)
+        ]
+        .align_x(iced::Alignment::Center)
+        .spacing(5);
+        if self.mount_watcher.is_some() {
+            col = col.push(
+                row(AfterMountSync::ALL.into_iter().map(|after| {
+                    widget::radio(
+                        lang::after_mount_sync(&self.lang, after),
+                        after,
+                        Some(self.after_mount_sync),
+                        Message::SelectAfterMountSync,
+                    )
+                    .into()
+                }))
+                .spacing(15),
+            );
+        }
+        widget::Container::new(col)
+            .align_x(iced::alignment::Horizontal::Center)
+            .width(Length::Fill)
+            .into()
+    }
+
+    /// Notifies the user or unmounts the drive after a sync that was started by mounting it.
+    fn finish_mount_sync(
+        &self,
+        mount: &mount::Mount,
+        outcome: &report::SyncOutcome,
+    ) -> Task<Message> {
+        match self.after_mount_sync {
+            AfterMountSync::Nothing => return Task::none(),
+            AfterMountSync::Notify => {}
+            AfterMountSync::Unmount => {
+                if let Err(error) = mount::unmount(mount) {
+                    return Task::done(Message::Error(error.into()));
+                }
+            }
+        }
+        Task::future(utils::async_info_popup(lang::drive_can_be_removed(
+            &self.lang, outcome,
+        )))
+        .discard()
+    }
+
+    fn subscription(&self) -> iced::Subscription<Message> {
+        let mut subscriptions = vec![
+            iced::time::every(std::time::Duration::from_secs(1)).map(|_| Message::UpdateLastSync),
+            iced::time::every(std::time::Duration::from_secs(30)).map(|_| Message::CheckSchedule),
+        ];
+        if self.mount_watcher.is_some() {
+            subscriptions.push(
+                iced::time::every(std::time::Duration::from_secs(5)).map(|_| Message::CheckMount),
+            );
+        }
+        if self.watching {
+            let sources = self.db.get_sources().unwrap_or_default();
+            subscriptions.push(iced::Subscription::run_with_id(
+                sources.clone(),
+                watch_stream(sources),
+            ));
+        }
+        iced::Subscription::batch(subscriptions)
+    }
+
     fn is_currently_syncing(&self) -> bool {
         self.currently_syncing
     }
@@ -297,52 +853,155 @@ impl App {
         }
     }
 
+    /// Reads the config at `path` and asks the user to accept conflicts before importing it.
+    fn check_import(&self, path: &std::path::Path) -> Result<Task<Message>> {
+        let config = config::Config::read(path)?;
+        let issues = config.check(&self.db)?;
+        let issues_string = issues
+            .iter()
+            .map(|issue| lang::import_issue(&self.lang, issue))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        if issues.iter().any(|issue| issue.is_blocking()) {
+            return Ok(
+                Task::future(utils::async_error_popup(&lang::import_config_invalid(
+                    &self.lang,
+                    &issues_string,
+                )))
+                .discard(),
+            );
+        }
+        if issues.is_empty() {
+            return Ok(Task::done(Message::ApplyImport(config)));
+        }
+
+        Ok(Task::future(
+            rfd::AsyncMessageDialog::new()
+                .set_title(lang::import_config(&self.lang))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .set_description(lang::import_config_conflicts(&self.lang, &issues_string))
+                .show(),
+        )
+        .then(move |result| {
+            if result == rfd::MessageDialogResult::Yes {
+                Task::done(Message::ApplyImport(config.clone()))
+            } else {
+                Task::none()
+            }
+        }))
+    }
+
     fn reload_last_sync(&mut self) -> Result<()> {
-        self.last_sync = if let Ok(Some(target_path)) = self.db.get_setting("target_path") {
-            syncing::get_last_sync(target_path.into()).context("failed to load setting from db")?
-        } else {
-            None
-        };
+        self.last_sync = load_last_sync(&self.db)?;
+
+        Ok(())
+    }
+
+    fn reload_history(&mut self) -> Result<()> {
+        self.history = self
+            .db
+            .get_history()
+            .context("failed to load the sync history")?;
 
         Ok(())
     }
 }
 
+/// Reads the last sync from the target, `None` for SFTP targets since it is only recorded in
+/// local targets.
+fn load_last_sync(db: &crate::db::AppSettings) -> Result<Option<syncing::LastSync>> {
+    let is_remote = matches!(
+        db.get_setting(syncing::backend::sftp::SFTP_HOST_SETTING),
+        Ok(Some(_))
+    );
+    if !is_remote && let Ok(Some(target_path)) = db.get_setting("target_path") {
+        syncing::get_last_sync(target_path.into()).context("failed to load setting from db")
+    } else {
+        Ok(None)
+    }
+}
+
+/// Resumes the interrupted sync of the same sources, if there is one.
+fn resume_interrupted(syncer: sync::Syncer) -> sync::Syncer {
+    match syncer.find_checkpoint() {
+        Ok(Some(checkpoint)) => syncer.resume(checkpoint),
+        // starting from scratch is always possible
+        _ => syncer,
+    }
+}
+
 fn create_sync_task(mut syncer: sync::Syncer) -> Task<Message> {
     Task::run(
         iced::stream::channel(100, |mut output| async move {
             use iced::futures::sink::SinkExt;
 
-            if let Err(error) = syncer.prepare().await {
-                output.send(Message::Error(error.into())).await.unwrap();
+            let outcome = if let Err(error) = syncer.prepare().await {
+                let message = match error.downcast_ref::<syncing::TargetMismatch>() {
+                    Some(mismatch) => Message::TargetMismatch(mismatch.to_string()),
+                    None => Message::Error(error.into()),
+                };
+                output.send(message).await.unwrap();
+                report::SyncOutcome::Failed
             } else {
                 loop {
                     let syncer_result = syncer.async_next().await;
                     match syncer_result {
                         None => {
-                            break;
+                            break report::SyncOutcome::Success;
                         }
                         Some(Ok(state)) => {
                             output.send(Message::SyncUpdate(state)).await.unwrap();
                         }
                         Some(Err(err)) => {
-                            output.send(Message::Error(err.into())).await.unwrap();
-                            break;
+                            let message = match err.downcast_ref::<InsufficientSpace>() {
+                                Some(InsufficientSpace(space)) => {
+                                    Message::InsufficientSpace(*space)
+                                }
+                                None => Message::Error(err.into()),
+                            };
+                            output.send(message).await.unwrap();
+                            break report::SyncOutcome::Failed;
                         }
                     }
                 }
-            }
+            };
 
-            output.send(Message::FinishedSync).await.unwrap();
-
-            loop {
-                tokio::task::yield_now().await;
-            }
+            output
+                .send(Message::FinishedSync(Arc::new(syncer.report(outcome))))
+                .await
+                .unwrap();
         }),
         |x| x,
     )
 }
 
+fn watch_stream(sources: Vec<PathBuf>) -> impl iced::futures::Stream<Item = Message> {
+    iced::stream::channel(100, |mut output| async move {
+        use iced::futures::sink::SinkExt;
+
+        match watch::ChangeWatcher::new(&sources) {
+            Err(error) => {
+                output.send(Message::Error(error.into())).await.unwrap();
+                output.send(Message::ToggleWatch(false)).await.unwrap();
+            }
+            Ok(mut watcher) => {
+                while let Some(changes) = watcher.next().await {
+                    let message = match changes {
+                        Ok(changes) => Message::WatchChanges(changes),
+                        Err(error) => Message::Error(error.into()),
+                    };
+                    if output.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        iced::futures::future::pending::<()>().await;
+    })
+}
+
 fn sync_invalid_parameters_popup(
     lang: &lang::Lang,
     error: sync::InvalidSyncerParameters,
@@ -362,14 +1021,16 @@ fn sync_invalid_parameters_popup(
             utils::async_error_popup(&lang::target_in_source_error(lang, &source)),
         )
         .discard(),
+        sync::InvalidSyncerParameters::SameName(first, second) => iced::Task::future(
+            utils::async_error_popup(&lang::sources_with_same_name_error(lang, &first, &second)),
+        )
+        .discard(),
     }
 }
 
 pub fn run(db: db::AppSettings) {
     iced::application("File Sync RS", App::update, App::view)
-        .subscription(|_| {
-            iced::time::every(std::time::Duration::from_secs(1)).map(|_| Message::UpdateLastSync)
-        })
+        .subscription(App::subscription)
         .theme(|_| iced::Theme::Light)
         .run_with(move || App::new(db))
         .unwrap();
@@ -8,6 +8,17 @@ pub fn async_error_popup(
         .show()
 }
 
+pub fn async_info_popup(
+    prompt: &str,
+) -> impl std::future::Future<Output = rfd::MessageDialogResult> + use<> {
+    rfd::AsyncMessageDialog::new()
+        .set_title("Info")
+        .set_level(rfd::MessageLevel::Info)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .set_description(prompt)
+        .show()
+}
+
 pub fn error_popup(prompt: &str) {
     rfd::MessageDialog::new()
         .set_title("Error")
@@ -29,3 +40,19 @@ pub fn error_chain_string(error: anyhow::Error) -> String {
 
     message
 }
+
+/// Formats a number of bytes into a human readable string (e.g. `1.5 MiB`).
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,131 @@
+use iced::widget::{self, Column, button, column, row, scrollable, text};
+use iced::{Element, Length};
+
+use super::super::{App, gray_button, lang, utils};
+use crate::syncing::report::SyncOutcome;
+
+#[derive(Debug, Clone)]
+pub(in super::super) enum Message {
+    Back,
+    Select(i64),
+}
+
+pub(in super::super) fn view(app: &App) -> Element<'_, Message> {
+    let mut col = column![
+        row![
+            button(lang::back(&app.lang))
+                .on_press(Message::Back)
+                .style(gray_button),
+            text(lang::history(&app.lang)).size(20),
+        ]
+        .align_y(iced::Alignment::Center)
+        .spacing(10),
+    ]
+    .spacing(10)
+    .padding(10)
+    .width(Length::Fill);
+
+    if app.history.is_empty() {
+        col = col.push(text(lang::no_history(&app.lang)));
+    }
+
+    col = col.push(
+        scrollable(generate_history_list(app))
+            .width(Length::Fill)
+            .height(Length::FillPortion(3)),
+    );
+
+    if app.selected_history.is_some() {
+        col = col.push(
+            scrollable(generate_error_list(app))
+                .width(Length::Fill)
+                .height(Length::FillPortion(2)),
+        );
+    }
+
+    col.into()
+}
+
+pub(in super::super) fn update(app: &mut App, message: Message) -> iced::Task<Message> {
+    match message {
+        Message::Back => {
+            app.show_history = false;
+            app.selected_history = None;
+            app.history_errors.clear();
+        }
+        Message::Select(id) => {
+            app.selected_history = Some(id);
+            match app.db.get_history_errors(id) {
+                Ok(errors) => app.history_errors = errors,
+                Err(error) => {
+                    app.history_errors.clear();
+                    return iced::Task::future(utils::async_error_popup(
+                        &utils::error_chain_string(error),
+                    ))
+                    .discard();
+                }
+            }
+        }
+    }
+    iced::Task::none()
+}
+
+fn generate_history_list(app: &App) -> Element<'_, Message> {
+    let mut col = Column::new().spacing(5);
+    for entry in &app.history {
+        let id = entry.id.unwrap();
+        let outcome_color = match entry.outcome {
+            SyncOutcome::Success => iced::Color::from_rgb8(50, 150, 50),
+            SyncOutcome::Failed => iced::Color::from_rgb8(183, 79, 79),
+        };
+        let selected = app.selected_history == Some(id);
+        col = col.push(
+            button(column![
+                row![
+                    text(format!(
+                        "{} - {}",
+                        entry.started.format("%d.%m.%Y %H:%M:%S"),
+                        entry.finished.format("%H:%M:%S")
+                    )),
+                    text(lang::sync_outcome(&app.lang, &entry.outcome)).color(outcome_color),
+                ]
+                .spacing(10),
+                text(entry.target.to_str().unwrap().to_string()),
+                text(lang::sync_statistics(&app.lang, &entry.statistics)),
+            ])
+            .on_press(Message::Select(id))
+            .width(Length::Fill)
+            .style(move |theme, status| {
+                let mut style = widget::button::secondary(theme, status);
+                style.background = Some(iced::Background::Color(if selected {
+                    iced::Color::from_rgb8(207, 207, 207)
+                } else {
+                    iced::Color::from_rgb8(237, 237, 237)
+                }));
+                style.text_color = iced::Color::BLACK;
+                style.border.radius = iced::border::Radius::new(10.0);
+                style
+            }),
+        );
+    }
+    col.into()
+}
+
+fn generate_error_list(app: &App) -> Element<'_, Message> {
+    let mut col = Column::new()
+        .spacing(5)
+        .push(text(lang::history_errors(&app.lang)).size(18));
+    if app.history_errors.is_empty() {
+        col = col.push(text(lang::no_history_errors(&app.lang)));
+    }
+    for error in &app.history_errors {
+        col = col.push(
+            column![
+                text(error.path().to_str().unwrap().to_string()),
+                text(error.message().clone()).color(iced::Color::from_rgb8(183, 79, 79)),
+            ]
+            .padding(5),
+        );
+    }
+    col.into()
+}
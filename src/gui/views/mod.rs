@@ -1,2 +1,5 @@
+pub mod history;
+pub mod schedule;
+pub mod settings;
 pub mod source;
 pub mod target;
@@ -0,0 +1,87 @@
+use iced::widget::{self, button, column, row, text, text_input};
+use iced::{Element, Length};
+
+use super::super::{App, gray_button, lang, utils};
+use crate::schedule;
+
+#[derive(Debug, Clone)]
+pub(in super::super) enum Message {
+    Edit(String),
+    Save,
+}
+
+pub(in super::super) fn view(app: &App) -> Element<'_, Message> {
+    let mut col = column![
+        row![
+            text(lang::schedule(&app.lang)),
+            text_input(lang::schedule_placeholder(&app.lang), &app.schedule_input)
+                .on_input_maybe(if app.is_currently_syncing() {
+                    None
+                } else {
+                    Some(Message::Edit)
+                })
+                .on_submit(Message::Save)
+                .width(Length::Fixed(250.0)),
+            button(lang::save(&app.lang))
+                .on_press_maybe(if app.is_currently_syncing() {
+                    None
+                } else {
+                    Some(Message::Save)
+                })
+                .style(gray_button),
+        ]
+        .align_y(iced::Alignment::Center)
+        .spacing(10),
+    ]
+    .align_x(iced::Alignment::Center)
+    .spacing(5);
+
+    if let Some(error) = &app.schedule_error {
+        col = col.push(text(error.clone()).color(iced::Color::from_rgb8(183, 79, 79)));
+    } else if let Ok(Some(next_run)) = schedule::next_run(&app.db) {
+        col = col.push(text(format!(
+            "{}: {}",
+            lang::next_scheduled_sync(&app.lang),
+            next_run
+                .with_timezone(&chrono::Local)
+                .format("%d.%m.%Y %H:%M")
+        )));
+    }
+
+    widget::Container::new(col)
+        .align_x(iced::alignment::Horizontal::Center)
+        .width(Length::Fill)
+        .into()
+}
+
+pub(in super::super) fn update(app: &mut App, message: Message) -> iced::Task<Message> {
+    match message {
+        Message::Edit(value) => {
+            app.schedule_input = value;
+            app.schedule_error = None;
+        }
+        Message::Save => {
+            let result = if app.schedule_input.trim().is_empty() {
+                schedule::set_schedule(&app.db, None)
+            } else {
+                match app.schedule_input.parse::<schedule::Schedule>() {
+                    Ok(new_schedule) => {
+                        app.schedule_input = new_schedule.to_string();
+                        schedule::set_schedule(&app.db, Some(&new_schedule))
+                    }
+                    Err(error) => {
+                        app.schedule_error = Some(format!("{:#}", error));
+                        return iced::Task::none();
+                    }
+                }
+            };
+            if let Err(error) = result {
+                return iced::Task::future(utils::async_error_popup(&utils::error_chain_string(
+                    error,
+                )))
+                .discard();
+            }
+        }
+    }
+    iced::Task::none()
+}
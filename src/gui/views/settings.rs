@@ -0,0 +1,301 @@
+use iced::widget::{self, button, column, row, text, text_input};
+use iced::{Element, Length};
+use std::collections::HashMap;
+
+use super::super::{App, gray_button, lang};
+use crate::syncing::bandwidth;
+use crate::syncing::delta;
+use crate::syncing::metadata;
+use crate::syncing::mirror;
+use crate::syncing::options::{self, SyncOptions};
+use crate::syncing::space::{self, SpaceCheck};
+
+#[derive(Debug, Clone)]
+pub(in super::super) enum Message {
+    Back,
+    /// A number setting was edited, contains the setting key and the entered text.
+    EditCount(&'static str, String),
+    ToggleLowIoPriority(bool),
+    ToggleDeltaTransfer(bool),
+    EditBandwidthLimit(String),
+    EditBandwidthSchedule(String),
+    SelectSpaceCheck(SpaceCheck),
+    TogglePreserveMetadata(bool),
+    TogglePreserveAcls(bool),
+    ToggleSkipEmptyDirs(bool),
+    ToggleMirror(bool),
+}
+
+/// Entered values that are not saved yet because they are invalid.
+#[derive(Debug, Default)]
+pub(in super::super) struct Inputs {
+    values: HashMap<&'static str, String>,
+    error: Option<String>,
+}
+
+pub(in super::super) fn view(app: &App) -> Element<'_, Message> {
+    let current = SyncOptions::load(&app.db).unwrap_or_default();
+
+    let mut col = column![
+        row![
+            button(lang::back(&app.lang))
+                .on_press(Message::Back)
+                .style(gray_button),
+            text(lang::settings(&app.lang)).size(20),
+        ]
+        .align_y(iced::Alignment::Center)
+        .spacing(10),
+        text(lang::performance(&app.lang)).size(16),
+        count_input(
+            app,
+            lang::workers(&app.lang),
+            options::WORKERS_SETTING,
+            current.workers.to_string(),
+        ),
+        count_input(
+            app,
+            lang::large_file_workers(&app.lang),
+            options::LARGE_FILE_WORKERS_SETTING,
+            current.large_file_workers.to_string(),
+        ),
+        count_input(
+            app,
+            lang::large_file_threshold(&app.lang),
+            options::LARGE_FILE_THRESHOLD_SETTING,
+            (current.large_file_threshold / 1024 / 1024).to_string(),
+        ),
+        widget::toggler(current.low_io_priority)
+            .label(lang::low_io_priority(&app.lang))
+            .on_toggle_maybe((!app.is_currently_syncing()).then_some(Message::ToggleLowIoPriority))
+            .width(Length::Shrink),
+        widget::toggler(current.delta_transfer)
+            .label(lang::delta_transfer(&app.lang))
+            .on_toggle_maybe((!app.is_currently_syncing()).then_some(Message::ToggleDeltaTransfer))
+            .width(Length::Shrink),
+        text(lang::bandwidth(&app.lang)).size(16),
+        // the limits can be changed while syncing, the running sync uses them right away
+        row![
+            text(lang::bandwidth_limit(&app.lang)).width(Length::Fixed(300.0)),
+            text_input(
+                lang::unlimited(&app.lang),
+                &input_value(
+                    app,
+                    bandwidth::BANDWIDTH_LIMIT_SETTING,
+                    current
+                        .bandwidth_limit
+                        .map(|limit| limit.to_string())
+                        .unwrap_or_default()
+                )
+            )
+            .on_input(Message::EditBandwidthLimit)
+            .width(Length::Fixed(150.0)),
+        ]
+        .align_y(iced::Alignment::Center)
+        .spacing(10),
+        row![
+            text(lang::bandwidth_schedule(&app.lang)).width(Length::Fixed(300.0)),
+            text_input(
+                "08:00-18:00=1000000, 18:00-22:00=0",
+                &input_value(
+                    app,
+                    bandwidth::BANDWIDTH_SCHEDULE_SETTING,
+                    current
+                        .bandwidth_schedule
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            )
+            .on_input(Message::EditBandwidthSchedule),
+        ]
+        .align_y(iced::Alignment::Center)
+        .spacing(10),
+        text(lang::space_check(&app.lang)).size(16),
+        widget::row(SpaceCheck::ALL.into_iter().map(|check| {
+            widget::radio(
+                lang::space_check_option(&app.lang, check),
+                check,
+                Some(current.space_check),
+                Message::SelectSpaceCheck,
+            )
+            .into()
+        }))
+        .spacing(15),
+        text(lang::metadata(&app.lang)).size(16),
+        widget::toggler(current.preserve_metadata)
+            .label(lang::preserve_metadata(&app.lang))
+            .on_toggle_maybe(
+                (!app.is_currently_syncing()).then_some(Message::TogglePreserveMetadata)
+            )
+            .width(Length::Shrink),
+        widget::toggler(current.preserve_acls)
+            .label(lang::preserve_acls(&app.lang))
+            .on_toggle_maybe((!app.is_currently_syncing()).then_some(Message::TogglePreserveAcls))
+            .width(Length::Shrink),
+        text(lang::directories(&app.lang)).size(16),
+        widget::toggler(current.skip_empty_dirs)
+            .label(lang::skip_empty_dirs(&app.lang))
+            .on_toggle_maybe((!app.is_currently_syncing()).then_some(Message::ToggleSkipEmptyDirs))
+            .width(Length::Shrink),
+        widget::toggler(current.mirror)
+            .label(lang::mirror(&app.lang))
+            .on_toggle_maybe((!app.is_currently_syncing()).then_some(Message::ToggleMirror))
+            .width(Length::Shrink),
+    ]
+    .spacing(10)
+    .padding(10)
+    .width(Length::Fill);
+
+    if let Some(error) = &app.settings_inputs.error {
+        col = col.push(text(error.clone()).color(iced::Color::from_rgb8(183, 79, 79)));
+    }
+
+    col.into()
+}
+
+/// Returns the entered text for the setting, or `current` if nothing was entered.
+fn input_value(app: &App, key: &'static str, current: String) -> String {
+    app.settings_inputs
+        .values
+        .get(key)
+        .cloned()
+        .unwrap_or(current)
+}
+
+fn count_input<'a>(
+    app: &'a App,
+    label: &'static str,
+    key: &'static str,
+    current: String,
+) -> Element<'a, Message> {
+    let value = input_value(app, key, current);
+    row![
+        text(label).width(Length::Fixed(300.0)),
+        text_input("", &value)
+            .on_input_maybe(
+                (!app.is_currently_syncing())
+                    .then_some(move |value| Message::EditCount(key, value))
+            )
+            .width(Length::Fixed(100.0)),
+    ]
+    .align_y(iced::Alignment::Center)
+    .spacing(10)
+    .into()
+}
+
+pub(in super::super) fn update(app: &mut App, message: Message) -> iced::Task<Message> {
+    let result = match message {
+        Message::Back => {
+            app.show_settings = false;
+            app.settings_inputs = Inputs::default();
+            Ok(())
+        }
+        Message::EditCount(key, value) => {
+            let valid = value.trim().parse::<usize>().is_ok_and(|count| count > 0);
+            app.settings_inputs.values.insert(key, value.clone());
+            if valid {
+                app.settings_inputs.error = None;
+                app.db.set_setting(key, value.trim())
+            } else {
+                app.settings_inputs.error = Some(lang::invalid_count(&app.lang).to_owned());
+                Ok(())
+            }
+        }
+        Message::ToggleLowIoPriority(enabled) => {
+            if enabled {
+                app.db.set_setting(options::LOW_IO_PRIORITY_SETTING, "true")
+            } else {
+                app.db.del_setting(options::LOW_IO_PRIORITY_SETTING)
+            }
+        }
+        Message::ToggleDeltaTransfer(enabled) => {
+            if enabled {
+                app.db.set_setting(delta::DELTA_TRANSFER_SETTING, "true")
+            } else {
+                app.db.del_setting(delta::DELTA_TRANSFER_SETTING)
+            }
+        }
+        Message::EditBandwidthLimit(value) => {
+            app.settings_inputs
+                .values
+                .insert(bandwidth::BANDWIDTH_LIMIT_SETTING, value.clone());
+            let limit = match value.trim() {
+                "" => Ok(None),
+                value => value
+                    .parse::<u64>()
+                    .map(|limit| (limit > 0).then_some(limit)),
+            };
+            match limit {
+                Ok(limit) => {
+                    app.settings_inputs.error = None;
+                    app.rate_limiter.set_limit(limit);
+                    bandwidth::set_limit(&app.db, limit)
+                }
+                Err(_) => {
+                    app.settings_inputs.error =
+                        Some(lang::invalid_bandwidth_limit(&app.lang).to_owned());
+                    Ok(())
+                }
+            }
+        }
+        Message::TogglePreserveMetadata(enabled) => {
+            if enabled {
+                app.db
+                    .set_setting(metadata::PRESERVE_METADATA_SETTING, "true")
+            } else {
+                app.db.del_setting(metadata::PRESERVE_METADATA_SETTING)
+            }
+        }
+        Message::TogglePreserveAcls(enabled) => {
+            if enabled {
+                app.db.set_setting(metadata::PRESERVE_ACLS_SETTING, "true")
+            } else {
+                app.db.del_setting(metadata::PRESERVE_ACLS_SETTING)
+            }
+        }
+        Message::ToggleSkipEmptyDirs(enabled) => {
+            if enabled {
+                app.db.set_setting(options::SKIP_EMPTY_DIRS_SETTING, "true")
+            } else {
+                app.db.del_setting(options::SKIP_EMPTY_DIRS_SETTING)
+            }
+        }
+        Message::ToggleMirror(enabled) => {
+            if enabled {
+                app.db.set_setting(mirror::MIRROR_SETTING, "true")
+            } else {
+                app.db.del_setting(mirror::MIRROR_SETTING)
+            }
+        }
+        Message::SelectSpaceCheck(check) => {
+            let value: &str = (&check).into();
+            app.db.set_setting(space::SPACE_CHECK_SETTING, value)
+        }
+        Message::EditBandwidthSchedule(value) => {
+            app.settings_inputs
+                .values
+                .insert(bandwidth::BANDWIDTH_SCHEDULE_SETTING, value.clone());
+            match bandwidth::parse_schedule(&value) {
+                Ok(schedule) => {
+                    app.settings_inputs.error = None;
+                    app.rate_limiter.set_schedule(schedule);
+                    if value.trim().is_empty() {
+                        app.db.del_setting(bandwidth::BANDWIDTH_SCHEDULE_SETTING)
+                    } else {
+                        app.db
+                            .set_setting(bandwidth::BANDWIDTH_SCHEDULE_SETTING, value.trim())
+                    }
+                }
+                Err(error) => {
+                    app.settings_inputs.error = Some(format!("{:#}", error));
+                    Ok(())
+                }
+            }
+        }
+    };
+    if let Err(error) = result {
+        app.settings_inputs.error = Some(format!("{:#}", error));
+    }
+    iced::Task::none()
+}
@@ -2,6 +2,8 @@ use iced::Element;
 use iced::widget::{self, Column, button, text};
 
 use super::super::{App, lang, utils};
+use crate::syncing;
+use crate::syncing::backend::sftp::SftpTarget;
 
 #[derive(Debug, Clone)]
 pub(in super::super) enum Message {
@@ -36,7 +38,14 @@ pub(in super::super) fn view(app: &App) -> Element<'_, Message> {
     );
 
     if let Some(target) = app.db.get_setting("target_path").unwrap_or(None) {
-        col = col.push(text(target));
+        match SftpTarget::load(&app.db) {
+            Ok(Some(server)) => {
+                col = col
+                    .push(text(format!("{}{}", server, target)))
+                    .push(text(lang::sftp_target_cli_only(&app.lang)));
+            }
+            _ => col = col.push(text(target)),
+        }
     }
 
     if let Some(last_sync) = &app.last_sync {
@@ -68,13 +77,18 @@ pub(in super::super) fn view(app: &App) -> Element<'_, Message> {
 pub(in super::super) fn update(app: &mut App, message: Message) -> iced::Task<Message> {
     match message {
         Message::ChangeTarget => {
-            if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                if let Err(error) = app.db.set_setting("target_path", path.to_str().unwrap()) {
-                    return iced::Task::future(utils::async_error_popup(
-                        &utils::error_chain_string(error),
-                    ))
-                    .discard();
-                }
+            // the id of the old target does not apply to the new one, which is a local directory
+            if let Some(path) = rfd::FileDialog::new().pick_folder()
+                && let Err(error) = app
+                    .db
+                    .set_setting("target_path", path.to_str().unwrap())
+                    .and_then(|()| app.db.del_setting(syncing::TARGET_ID_SETTING))
+                    .and_then(|()| SftpTarget::remove(&app.db))
+            {
+                return iced::Task::future(utils::async_error_popup(&utils::error_chain_string(
+                    error,
+                )))
+                .discard();
             }
 
             if let Err(error) = app.reload_last_sync() {
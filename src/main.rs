@@ -1,34 +1,86 @@
 #![windows_subsystem = "windows"]
 
+mod cli;
+mod config;
 mod db;
 mod gui;
+mod schedule;
 mod syncing;
 mod update;
 use crate::gui::run;
 
+use std::path::PathBuf;
+
+/// Environment variable that overrides the database path.
+const DB_PATH_ENV: &str = "FILE_SYNC_RS_DB";
+/// If a file with this name is next to the executable, the database is stored next to it as well.
+const PORTABLE_MARKER: &str = "file-sync-rs.portable";
+/// Name of the database file in portable mode.
+const PORTABLE_DB_NAME: &str = "data.db";
+
 #[cfg(debug_assertions)]
-fn get_db_path() -> String {
-    String::from("development.db")
+fn get_default_db_path() -> PathBuf {
+    PathBuf::from("development.db")
 }
 
 #[cfg(not(debug_assertions))]
-fn get_db_path() -> String {
+fn get_default_db_path() -> PathBuf {
     let app_data_dir = dirs::data_dir().unwrap().join("file-sync-rs");
     if !app_data_dir.is_dir() {
         std::fs::create_dir(&app_data_dir).unwrap();
     }
-    app_data_dir.join("data.db").to_str().unwrap().into()
+    app_data_dir.join("data.db")
+}
+
+/// Returns the database path of portable mode if the marker file exists next to the executable.
+fn get_portable_db_path() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    if exe_dir.join(PORTABLE_MARKER).is_file() {
+        Some(exe_dir.join(PORTABLE_DB_NAME))
+    } else {
+        None
+    }
+}
+
+/// Chooses the database path in the order: `--config` argument, environment variable, portable mode, default.
+fn get_db_path(config_arg: Option<PathBuf>) -> PathBuf {
+    config_arg
+        .or_else(|| {
+            std::env::var_os(DB_PATH_ENV)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        })
+        .or_else(get_portable_db_path)
+        .unwrap_or_else(get_default_db_path)
 }
 
 fn main() {
-    let app_settings = match db::AppSettings::new(get_db_path().into()) {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let config_arg = match cli::take_config_arg(&mut args) {
+        Ok(config_arg) => config_arg,
+        Err(error) => {
+            eprintln!("{}", gui::utils::error_chain_string(error));
+            std::process::exit(1);
+        }
+    };
+
+    let app_settings = match db::AppSettings::new(get_db_path(config_arg)) {
         Ok(db) => db,
         Err(error) => {
             let error_string = gui::utils::error_chain_string(error);
+            if !args.is_empty() {
+                eprintln!("{}", error_string);
+                std::process::exit(1);
+            }
             gui::utils::error_popup(&error_string);
             panic!("{}", error_string);
         }
     };
 
+    if !args.is_empty() {
+        std::process::exit(cli::run(&app_settings, &args));
+    }
+
     run(app_settings);
 }
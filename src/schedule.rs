@@ -0,0 +1,364 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime, TimeZone};
+
+use crate::db::AppSettings;
+use crate::syncing::DateTime;
+
+/// The setting key under which the schedule is stored.
+pub const SCHEDULE_SETTING: &str = "schedule";
+/// The setting key under which the point in time of the last scheduled sync is stored.
+pub const SCHEDULE_LAST_RUN_SETTING: &str = "schedule_last_run";
+
+/// When syncs are started automatically.
+///
+/// Stored as text in one of the forms `every 6h`, `every 30m`, `daily 02:30` or `cron 0 3 * * 1-5`.
+/// Times are local times.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every given number of minutes.
+    Interval(u32),
+    /// Every day at the given time.
+    Daily(NaiveTime),
+    /// At the times matching the cron expression.
+    Cron(Box<croner::Cron>),
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let (kind, argument) = value
+            .split_once(char::is_whitespace)
+            .map(|(kind, argument)| (kind, argument.trim()))
+            .with_context(|| format!("invalid schedule {:?}", value))?;
+
+        match kind {
+            "every" => {
+                let (number, minutes_per_unit) = if let Some(number) = argument.strip_suffix('h') {
+                    (number, 60)
+                } else if let Some(number) = argument.strip_suffix('m') {
+                    (number, 1)
+                } else {
+                    anyhow::bail!("interval {:?} has to end with h or m", argument);
+                };
+                let number: u32 = number
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid interval {:?}", argument))?;
+                if number == 0 {
+                    anyhow::bail!("interval has to be greater than 0");
+                }
+                let minutes = number
+                    .checked_mul(minutes_per_unit)
+                    .with_context(|| format!("interval {:?} is too long", argument))?;
+                Ok(Self::Interval(minutes))
+            }
+            "daily" => Ok(Self::Daily(
+                NaiveTime::parse_from_str(argument, "%H:%M")
+                    .with_context(|| format!("invalid time {:?}, expected HH:MM", argument))?,
+            )),
+            "cron" => Ok(Self::Cron(Box::new(
+                croner::Cron::new(argument)
+                    .parse()
+                    .with_context(|| format!("invalid cron expression {:?}", argument))?,
+            ))),
+            _ => anyhow::bail!("unknown schedule {:?}, expected every, daily or cron", kind),
+        }
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interval(minutes) if minutes % 60 == 0 => write!(f, "every {}h", minutes / 60),
+            Self::Interval(minutes) => write!(f, "every {}m", minutes),
+            Self::Daily(time) => write!(f, "daily {}", time.format("%H:%M")),
+            Self::Cron(cron) => write!(f, "cron {}", cron.pattern),
+        }
+    }
+}
+
+impl Schedule {
+    /// Returns the first point in time after `last_run` at which a sync is due.
+    pub fn next_after(&self, last_run: &DateTime) -> Option<DateTime> {
+        self.next_after_in(last_run, &Local)
+    }
+
+    /// Like [`Schedule::next_after`], with the times of the schedule in the given time zone.
+    fn next_after_in<Tz: TimeZone>(&self, last_run: &DateTime, zone: &Tz) -> Option<DateTime> {
+        match self {
+            Self::Interval(minutes) => {
+                Some(*last_run + chrono::Duration::minutes(i64::from(*minutes)))
+            }
+            Self::Daily(time) => {
+                let local_last_run = last_run.with_timezone(zone);
+                let mut date = local_last_run.date_naive();
+                if local_last_run.time() >= *time {
+                    date = date.succ_opt()?;
+                }
+                // a time skipped by switching to daylight saving time is due when the gap ends
+                let local = date.and_time(*time);
+                (0..=24 * 60).find_map(|minutes| {
+                    zone.from_local_datetime(&(local + chrono::Duration::minutes(minutes)))
+                        .earliest()
+                        .map(|next| next.with_timezone(&chrono::offset::Utc))
+                })
+            }
+            Self::Cron(cron) => cron
+                .find_next_occurrence(&last_run.with_timezone(zone), false)
+                .ok()
+                .map(|next| next.with_timezone(&chrono::offset::Utc)),
+        }
+    }
+}
+
+/// Reads the schedule from the settings.
+pub fn get_schedule(db: &AppSettings) -> Result<Option<Schedule>> {
+    match db.get_setting(SCHEDULE_SETTING)? {
+        Some(value) if !value.trim().is_empty() => Ok(Some(
+            value.parse().context("the stored schedule is invalid")?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Stores the schedule or removes it with `None`. Counting starts from now.
+pub fn set_schedule(db: &AppSettings, schedule: Option<&Schedule>) -> Result<()> {
+    match schedule {
+        Some(schedule) => {
+            db.set_setting(SCHEDULE_SETTING, &schedule.to_string())?;
+            mark_run(db, &chrono::offset::Utc::now())
+        }
+        None => {
+            db.del_setting(SCHEDULE_SETTING)?;
+            db.del_setting(SCHEDULE_LAST_RUN_SETTING)
+        }
+    }
+}
+
+/// Returns the point in time of the next scheduled sync. It is in the past if a sync was missed,
+/// e.g. because the machine was asleep.
+pub fn next_run(db: &AppSettings) -> Result<Option<DateTime>> {
+    let Some(schedule) = get_schedule(db)? else {
+        return Ok(None);
+    };
+    let last_run = match db.get_setting(SCHEDULE_LAST_RUN_SETTING)? {
+        Some(value) => chrono::DateTime::parse_from_rfc3339(&value)
+            .context("invalid time of the last scheduled sync")?
+            .with_timezone(&chrono::offset::Utc),
+        // the schedule was saved without starting the count, which happens in [`set_schedule`]
+        None => chrono::offset::Utc::now(),
+    };
+    Ok(schedule.next_after(&last_run))
+}
+
+/// Whether a scheduled sync is due at `now`. Missed syncs are only caught up once.
+pub fn is_due(db: &AppSettings, now: &DateTime) -> Result<bool> {
+    Ok(next_run(db)?.is_some_and(|next| next <= *now))
+}
+
+/// Records that a scheduled sync was started at `now`.
+pub fn mark_run(db: &AppSettings, now: &DateTime) -> Result<()> {
+    db.set_setting(SCHEDULE_LAST_RUN_SETTING, &now.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, MappedLocalTime, NaiveDate, NaiveDateTime, Utc};
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime {
+        Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(year, month, day)
+                    .unwrap()
+                    .and_hms_opt(hour, minute, 0)
+                    .unwrap(),
+            )
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse_schedules() {
+        assert!(matches!("every 6h".parse(), Ok(Schedule::Interval(360))));
+        assert!(matches!(" every  30m ".parse(), Ok(Schedule::Interval(30))));
+        assert!(matches!(
+            "daily 02:30".parse(),
+            Ok(Schedule::Daily(time)) if time == NaiveTime::from_hms_opt(2, 30, 0).unwrap()
+        ));
+        assert!(matches!("cron 0 3 * * 1-5".parse(), Ok(Schedule::Cron(_))));
+
+        for invalid in [
+            "",
+            "every",
+            "every 6",
+            "every 0h",
+            "every -1m",
+            "every 4294967295h",
+            "daily 25:00",
+            "cron not a cron",
+            "weekly 1",
+        ] {
+            assert!(
+                invalid.parse::<Schedule>().is_err(),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for schedule in ["every 6h", "every 90m", "daily 02:30", "cron 0 3 * * 1-5"] {
+            assert_eq!(schedule.parse::<Schedule>().unwrap().to_string(), schedule);
+        }
+    }
+
+    #[test]
+    fn next_after() {
+        let last_run = local(2025, 3, 10, 12, 0);
+        let every = "every 90m".parse::<Schedule>().unwrap();
+        assert_eq!(
+            every.next_after(&last_run),
+            Some(local(2025, 3, 10, 13, 30))
+        );
+
+        let daily = "daily 02:30".parse::<Schedule>().unwrap();
+        assert_eq!(daily.next_after(&last_run), Some(local(2025, 3, 11, 2, 30)));
+        assert_eq!(
+            daily.next_after(&local(2025, 3, 10, 1, 0)),
+            Some(local(2025, 3, 10, 2, 30))
+        );
+        // a run at exactly the time does not make the same time due again
+        assert_eq!(
+            daily.next_after(&local(2025, 3, 10, 2, 30)),
+            Some(local(2025, 3, 11, 2, 30))
+        );
+
+        // Monday, 10th of March 2025
+        let cron = "cron 0 3 * * 1-5".parse::<Schedule>().unwrap();
+        assert_eq!(cron.next_after(&last_run), Some(local(2025, 3, 11, 3, 0)));
+        assert_eq!(
+            cron.next_after(&local(2025, 3, 14, 4, 0)),
+            Some(local(2025, 3, 17, 3, 0))
+        );
+    }
+
+    /// Central European time in 2025, the clocks jump from 02:00 to 03:00 on the 30th of March
+    /// and back from 03:00 to 02:00 on the 26th of October.
+    #[derive(Debug, Clone, Copy)]
+    struct Cet2025;
+
+    impl Cet2025 {
+        fn at(&self, month: u32, day: u32, hour: u32, minute: u32) -> DateTime {
+            self.from_local_datetime(
+                &NaiveDate::from_ymd_opt(2025, month, day)
+                    .unwrap()
+                    .and_hms_opt(hour, minute, 0)
+                    .unwrap(),
+            )
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+        }
+    }
+
+    impl TimeZone for Cet2025 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Self
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(
+            &self,
+            local: &NaiveDateTime,
+        ) -> MappedLocalTime<FixedOffset> {
+            let offsets: Vec<FixedOffset> = [1, 2]
+                .into_iter()
+                .map(|hours| FixedOffset::east_opt(hours * 3600).unwrap())
+                .filter(|offset| {
+                    self.offset_from_utc_datetime(
+                        &(*local - chrono::Duration::seconds(offset.local_minus_utc().into())),
+                    ) == *offset
+                })
+                .collect();
+            match offsets[..] {
+                [offset] => MappedLocalTime::Single(offset),
+                // the earlier point in time still has the summer offset
+                [winter, summer] => MappedLocalTime::Ambiguous(summer, winter),
+                _ => MappedLocalTime::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let utc_at = |month, day| {
+                NaiveDate::from_ymd_opt(2025, month, day)
+                    .unwrap()
+                    .and_hms_opt(1, 0, 0)
+                    .unwrap()
+            };
+            let summer = (utc_at(3, 30)..utc_at(10, 26)).contains(utc);
+            FixedOffset::east_opt(if summer { 2 * 3600 } else { 3600 }).unwrap()
+        }
+    }
+
+    #[test]
+    fn daily_times_in_the_daylight_saving_gap_run_when_it_ends() {
+        let daily = "daily 02:30".parse::<Schedule>().unwrap();
+        let next = daily.next_after_in(&Cet2025.at(3, 29, 12, 0), &Cet2025);
+        assert_eq!(next, Some(Cet2025.at(3, 30, 3, 0)));
+        // the schedule goes on after the gap
+        let next = daily.next_after_in(&next.unwrap(), &Cet2025);
+        assert_eq!(next, Some(Cet2025.at(3, 31, 2, 30)));
+
+        // a time that exists twice is due the first time
+        let next = daily.next_after_in(&Cet2025.at(10, 25, 12, 0), &Cet2025);
+        assert_eq!(
+            next,
+            Some(Cet2025.at(10, 26, 0, 0) + chrono::Duration::minutes(150))
+        );
+        assert_eq!(
+            daily.next_after_in(&next.unwrap(), &Cet2025),
+            Some(Cet2025.at(10, 27, 2, 30))
+        );
+    }
+
+    #[test]
+    fn next_run_counts_from_the_last_run() {
+        let path =
+            std::env::temp_dir().join(format!("file-sync-rs-schedule-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = AppSettings::new(path.clone()).unwrap();
+        assert_eq!(next_run(&db).unwrap(), None);
+
+        let before = Utc::now();
+        set_schedule(&db, Some(&"every 1h".parse().unwrap())).unwrap();
+        let next = next_run(&db).unwrap().unwrap();
+        assert!(next >= before + chrono::Duration::hours(1));
+        assert!(!is_due(&db, &Utc::now()).unwrap());
+        assert!(is_due(&db, &(next + chrono::Duration::seconds(1))).unwrap());
+
+        let last_run = local(2025, 3, 10, 12, 0);
+        mark_run(&db, &last_run).unwrap();
+        assert_eq!(next_run(&db).unwrap(), Some(local(2025, 3, 10, 13, 0)));
+        // asking does not change anything
+        assert_eq!(next_run(&db).unwrap(), Some(local(2025, 3, 10, 13, 0)));
+
+        set_schedule(&db, None).unwrap();
+        assert_eq!(next_run(&db).unwrap(), None);
+        assert_eq!(db.get_setting(SCHEDULE_LAST_RUN_SETTING).unwrap(), None);
+        drop(db);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::{EntryKind, TargetBackend, TargetEntry, TargetMetadata};
+
+/// A target on the local filesystem, including mounted network shares.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalBackend;
+
+fn kind(file_type: std::fs::FileType) -> EntryKind {
+    if file_type.is_file() {
+        EntryKind::File
+    } else if file_type.is_dir() {
+        EntryKind::Directory
+    } else {
+        EntryKind::Other
+    }
+}
+
+impl TargetBackend for LocalBackend {
+    fn stat(&self, path: &Path) -> std::io::Result<Option<TargetMetadata>> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(Some(TargetMetadata {
+                kind: kind(metadata.file_type()),
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            })),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(std::fs::File::create(path)?))
+    }
+
+    fn mkdir(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn list(&self, path: &Path) -> std::io::Result<Vec<TargetEntry>> {
+        std::fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(TargetEntry {
+                    name: entry.file_name(),
+                    kind: kind(entry.file_type()?),
+                })
+            })
+            .collect()
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> std::io::Result<()> {
+        filetime::set_file_mtime(path, modified.into())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn files_are_written_and_renamed() {
+        let dir = std::env::temp_dir().join(format!("file-sync-rs-local-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let backend = LocalBackend;
+        backend.mkdir_all(&dir.join("dir")).unwrap();
+
+        let file = dir.join("dir").join("file");
+        let mut writer = backend.write(&file).unwrap();
+        writer.write_all(b"content").unwrap();
+        writer.flush().unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(10);
+        backend.set_modified(&file, modified).unwrap();
+        assert_eq!(
+            backend.stat(&file).unwrap(),
+            Some(TargetMetadata {
+                kind: EntryKind::File,
+                len: 7,
+                modified: Some(modified),
+            })
+        );
+
+        let renamed = dir.join("renamed");
+        backend.rename(&file, &renamed).unwrap();
+        let mut content = Vec::new();
+        backend
+            .read(&renamed)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"content");
+        let mut entries = backend.list(&dir).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            entries,
+            vec![
+                TargetEntry {
+                    name: "dir".into(),
+                    kind: EntryKind::Directory,
+                },
+                TargetEntry {
+                    name: "renamed".into(),
+                    kind: EntryKind::File,
+                },
+            ]
+        );
+
+        backend.remove_file(&renamed).unwrap();
+        backend.remove_dir(&dir.join("dir")).unwrap();
+        assert!(backend.list(&dir).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
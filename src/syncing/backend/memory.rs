@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::{EntryKind, TargetBackend, TargetEntry, TargetMetadata};
+
+#[derive(Debug, Clone)]
+enum Node {
+    Directory,
+    File(Arc<Mutex<Vec<u8>>>, SystemTime),
+}
+
+/// A target that only exists in memory, to test the sync without touching the disk.
+/// Only the root directory `/` exists at first.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    nodes: Arc<Mutex<BTreeMap<PathBuf, Node>>>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self {
+            nodes: Arc::new(Mutex::new(BTreeMap::from([(
+                PathBuf::from("/"),
+                Node::Directory,
+            )]))),
+        }
+    }
+}
+
+impl MemoryBackend {
+    /// The content of all files by their path.
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, node)| match node {
+                Node::File(content, _) => Some((path.clone(), content.lock().unwrap().clone())),
+                Node::Directory => None,
+            })
+            .collect()
+    }
+
+    pub fn directories(&self) -> Vec<PathBuf> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, node)| matches!(node, Node::Directory))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Fails if the parent of the path is not a directory.
+fn check_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> std::io::Result<()> {
+    match path.parent().map(|parent| nodes.get(parent)) {
+        Some(Some(Node::Directory)) => Ok(()),
+        _ => Err(Error::from(ErrorKind::NotFound)),
+    }
+}
+
+impl TargetBackend for MemoryBackend {
+    fn stat(&self, path: &Path) -> std::io::Result<Option<TargetMetadata>> {
+        Ok(self.nodes.lock().unwrap().get(path).map(|node| match node {
+            Node::Directory => TargetMetadata {
+                kind: EntryKind::Directory,
+                len: 0,
+                modified: None,
+            },
+            Node::File(content, modified) => TargetMetadata {
+                kind: EntryKind::File,
+                len: content.lock().unwrap().len() as u64,
+                modified: Some(*modified),
+            },
+        }))
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+        match self.nodes.lock().unwrap().get(path) {
+            Some(Node::File(content, _)) => {
+                Ok(Box::new(Cursor::new(content.lock().unwrap().clone())))
+            }
+            Some(Node::Directory) => Err(Error::from(ErrorKind::IsADirectory)),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+        let mut nodes = self.nodes.lock().unwrap();
+        check_parent(&nodes, path)?;
+        if let Some(Node::Directory) = nodes.get(path) {
+            return Err(Error::from(ErrorKind::IsADirectory));
+        }
+        let content = Arc::new(Mutex::new(Vec::new()));
+        nodes.insert(
+            path.to_path_buf(),
+            Node::File(content.clone(), SystemTime::now()),
+        );
+        Ok(Box::new(Writer(content)))
+    }
+
+    fn mkdir(&self, path: &Path) -> std::io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        check_parent(&nodes, path)?;
+        if nodes.contains_key(path) {
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+        nodes.insert(path.to_path_buf(), Node::Directory);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(Node::File(..)) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::Directory) => Err(Error::from(ErrorKind::IsADirectory)),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(Node::Directory) => {
+                if nodes.keys().any(|other| other.parent() == Some(path)) {
+                    return Err(Error::from(ErrorKind::DirectoryNotEmpty));
+                }
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::File(..)) => Err(Error::from(ErrorKind::NotADirectory)),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        check_parent(&nodes, to)?;
+        if !nodes.contains_key(from) {
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+        // the content of directories moves along
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = nodes.remove(&path).unwrap();
+            nodes.insert(to.join(path.strip_prefix(from).unwrap()), node);
+        }
+        Ok(())
+    }
+
+    fn list(&self, path: &Path) -> std::io::Result<Vec<TargetEntry>> {
+        let nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(Node::Directory) => {}
+            Some(Node::File(..)) => return Err(Error::from(ErrorKind::NotADirectory)),
+            None => return Err(Error::from(ErrorKind::NotFound)),
+        }
+        Ok(nodes
+            .iter()
+            .filter(|(other, _)| other.parent() == Some(path))
+            .map(|(other, node)| TargetEntry {
+                name: other.file_name().unwrap().to_owned(),
+                kind: match node {
+                    Node::Directory => EntryKind::Directory,
+                    Node::File(..) => EntryKind::File,
+                },
+            })
+            .collect())
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> std::io::Result<()> {
+        match self.nodes.lock().unwrap().get_mut(path) {
+            Some(Node::File(_, time)) => {
+                *time = modified;
+                Ok(())
+            }
+            Some(Node::Directory) => Err(Error::from(ErrorKind::IsADirectory)),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+}
+
+/// Writes into the content of a file of the [`MemoryBackend`].
+struct Writer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Writer {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::bandwidth::{self, RateLimiter};
+use crate::db::AppSettings;
+
+pub mod local;
+#[cfg(test)]
+pub mod memory;
+pub mod sftp;
+
+pub use local::LocalBackend;
+
+/// Creates the backend of the configured target, a local directory unless an
+/// [`sftp::SftpTarget`] is configured.
+pub fn load(db: &AppSettings) -> Result<Arc<dyn TargetBackend>> {
+    match sftp::SftpTarget::load(db)? {
+        Some(target) => target.backend(),
+        None => Ok(Arc::new(LocalBackend)),
+    }
+}
+
+/// What a path of a target is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// e.g. a symlink, which is never followed when listing
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetMetadata {
+    pub kind: EntryKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl TargetMetadata {
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+}
+
+/// An entry of a directory of a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetEntry {
+    pub name: OsString,
+    pub kind: EntryKind,
+}
+
+/// Where the files of a sync are written to.
+///
+/// Paths are the full paths in the target, like the target of the sync joined with the
+/// name of a source. Unless the backend [`TargetBackend::is_local`], the sync only uses these
+/// operations, so hard links, reflinks, sparse files, metadata and delta transfers are not
+/// supported. The lock, checkpoint and manifest are only written to local targets as well,
+/// so syncs to other targets can not mirror or be resumed. Without the lock and the last sync
+/// file, nothing keeps two syncs from writing to the same remote target at the same time and
+/// the target id is not checked, so a different target at the same path is synced to as well.
+pub trait TargetBackend: std::fmt::Debug + Send + Sync {
+    /// Returns `None` if nothing exists at the path, symlinks are followed.
+    fn stat(&self, path: &Path) -> std::io::Result<Option<TargetMetadata>>;
+
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>>;
+
+    /// Creates the file or truncates it. The content is complete once the writer is flushed.
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>>;
+
+    /// Creates a directory, its parent has to exist.
+    fn mkdir(&self, path: &Path) -> std::io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Removes an empty directory.
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Renames a file or directory, an existing file at `to` is replaced.
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+
+    fn list(&self, path: &Path) -> std::io::Result<Vec<TargetEntry>>;
+
+    /// Sets the modification time of a file, the sync compares it to skip unchanged files.
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> std::io::Result<()>;
+
+    /// Whether the paths are paths of the local filesystem.
+    /// Lets the sync use everything [`std::fs`] can do, like reflinks and hard links.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Creates the directory and its missing parents.
+    fn mkdir_all(&self, path: &Path) -> std::io::Result<()> {
+        match self.stat(path)? {
+            Some(metadata) if metadata.is_dir() => return Ok(()),
+            Some(_) => return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists)),
+            None => {}
+        }
+        if let Some(parent) = path.parent() {
+            self.mkdir_all(parent)?;
+        }
+        match self.mkdir(path) {
+            // created by another worker in the meantime
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Writes the source file to the target of the backend and returns the number of bytes written.
+pub(super) fn upload(
+    backend: &dyn TargetBackend,
+    source: &Path,
+    target: &Path,
+    limiter: &RateLimiter,
+) -> Result<u64> {
+    let mut source_file = std::fs::File::open(source)
+        .with_context(|| format!("Could not open source file {:?}", source))?;
+    let mut writer = backend
+        .write(target)
+        .with_context(|| format!("Could not create target file {:?}", target))?;
+    let mut buffer = vec![0; bandwidth::CHUNK_SIZE];
+    let mut bytes = 0;
+    loop {
+        let read = source_file
+            .read(&mut buffer)
+            .with_context(|| format!("Could not read file {:?}", source))?;
+        if read == 0 {
+            break;
+        }
+        bandwidth::write_all(&mut writer, &buffer[..read], limiter)
+            .with_context(|| format!("Could not write to file {:?}", target))?;
+        bytes += read as u64;
+    }
+    writer
+        .flush()
+        .with_context(|| format!("Could not write to file {:?}", target))?;
+    Ok(bytes)
+}
+
+/// Whether the file of the target has the same content as the source file.
+/// Both are read in chunks and the comparison stops at the first difference.
+pub(super) fn same_content(
+    backend: &dyn TargetBackend,
+    source: &Path,
+    target: &Path,
+) -> Result<bool> {
+    let mut source_file = std::fs::File::open(source)
+        .with_context(|| format!("Could not open source file {:?}", source))?;
+    let mut reader = backend
+        .read(target)
+        .with_context(|| format!("Could not open target file {:?}", target))?;
+    let mut source_buffer = vec![0; bandwidth::CHUNK_SIZE];
+    let mut target_buffer = vec![0; bandwidth::CHUNK_SIZE];
+    loop {
+        let read = fill(&mut source_file, &mut source_buffer)
+            .with_context(|| format!("Could not read file {:?}", source))?;
+        let target_read = fill(&mut reader, &mut target_buffer)
+            .with_context(|| format!("Could not read file {:?}", target))?;
+        if source_buffer[..read] != target_buffer[..target_read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads until the buffer is full or the end of the file, returns the number of bytes read.
+fn fill(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,231 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::TargetBackend;
+use crate::db::AppSettings;
+
+#[cfg(feature = "sftp")]
+mod client;
+
+#[cfg(feature = "sftp")]
+pub use client::SftpBackend;
+
+/// The setting keys of an [`SftpTarget`]. Without a host the target is a local directory.
+pub const SFTP_HOST_SETTING: &str = "sftp_host";
+pub const SFTP_PORT_SETTING: &str = "sftp_port";
+pub const SFTP_USER_SETTING: &str = "sftp_user";
+pub const SFTP_KEY_PATH_SETTING: &str = "sftp_key_path";
+pub const SFTP_KNOWN_HOSTS_SETTING: &str = "sftp_known_hosts";
+
+/// A target on an SSH server that is written to over SFTP.
+/// The configured target path is the path of the target directory on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpTarget {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    /// private key to log in with, it must not be protected by a passphrase
+    pub key_path: PathBuf,
+    /// the OpenSSH known hosts file the key of the server is verified with
+    pub known_hosts: PathBuf,
+}
+
+impl SftpTarget {
+    pub const DEFAULT_PORT: u16 = 22;
+
+    /// Parses `USER@HOST[:PORT]`, the known hosts of the user are used.
+    /// IPv6 addresses are written in brackets if they have a port, like `user@[::1]:2222`.
+    pub fn new(address: &str, key_path: PathBuf) -> Result<Self> {
+        let invalid = || format!("{:?} has to be of the form USER@HOST[:PORT]", address);
+        let (user, host) = address.split_once('@').with_context(invalid)?;
+        let (host, port) = if let Some(host) = host.strip_prefix('[') {
+            let (host, port) = host.split_once(']').with_context(invalid)?;
+            match port {
+                "" => (host, None),
+                port => (host, Some(port.strip_prefix(':').with_context(invalid)?)),
+            }
+        } else {
+            match host.rsplit_once(':') {
+                // the colons of IPv6 addresses are not a port
+                Some((host, port)) if !host.contains(':') => (host, Some(port)),
+                _ => (host, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("invalid port {:?}", port))?,
+            None => Self::DEFAULT_PORT,
+        };
+        if user.is_empty() || host.is_empty() {
+            anyhow::bail!(invalid());
+        }
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+            user: user.to_owned(),
+            key_path,
+            known_hosts: default_known_hosts()?,
+        })
+    }
+
+    /// Reads the target from the settings, `None` if the target is a local directory.
+    pub fn load(db: &AppSettings) -> Result<Option<Self>> {
+        let Some(host) = db.get_setting(SFTP_HOST_SETTING)? else {
+            return Ok(None);
+        };
+        let port = match db.get_setting(SFTP_PORT_SETTING)? {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("invalid SFTP port {:?}", port))?,
+            None => Self::DEFAULT_PORT,
+        };
+        let known_hosts = match db.get_setting(SFTP_KNOWN_HOSTS_SETTING)? {
+            Some(known_hosts) => known_hosts.into(),
+            None => default_known_hosts()?,
+        };
+        Ok(Some(Self {
+            host,
+            port,
+            user: db
+                .get_setting(SFTP_USER_SETTING)?
+                .context("no SFTP user is configured")?,
+            key_path: db
+                .get_setting(SFTP_KEY_PATH_SETTING)?
+                .context("no SSH key is configured for the SFTP target")?
+                .into(),
+            known_hosts,
+        }))
+    }
+
+    pub fn save(&self, db: &AppSettings) -> Result<()> {
+        db.set_setting(SFTP_HOST_SETTING, &self.host)?;
+        db.set_setting(SFTP_PORT_SETTING, &self.port.to_string())?;
+        db.set_setting(SFTP_USER_SETTING, &self.user)?;
+        db.set_setting(SFTP_KEY_PATH_SETTING, self.key_path.to_str().unwrap())?;
+        if self.known_hosts == default_known_hosts()? {
+            db.del_setting(SFTP_KNOWN_HOSTS_SETTING)
+        } else {
+            db.set_setting(SFTP_KNOWN_HOSTS_SETTING, self.known_hosts.to_str().unwrap())
+        }
+    }
+
+    /// Makes the target a local directory again.
+    pub fn remove(db: &AppSettings) -> Result<()> {
+        for key in [
+            SFTP_HOST_SETTING,
+            SFTP_PORT_SETTING,
+            SFTP_USER_SETTING,
+            SFTP_KEY_PATH_SETTING,
+            SFTP_KNOWN_HOSTS_SETTING,
+        ] {
+            db.del_setting(key)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "sftp")]
+    pub fn backend(&self) -> Result<Arc<dyn TargetBackend>> {
+        Ok(Arc::new(SftpBackend::new(self.clone())))
+    }
+
+    #[cfg(not(feature = "sftp"))]
+    pub fn backend(&self) -> Result<Arc<dyn TargetBackend>> {
+        anyhow::bail!(
+            "{} is an SFTP target, but file-sync-rs was built without the \"sftp\" feature",
+            self
+        )
+    }
+}
+
+impl std::fmt::Display for SftpTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "sftp://{}@[{}]:{}", self.user, self.host, self.port)
+        } else {
+            write!(f, "sftp://{}@{}:{}", self.user, self.host, self.port)
+        }
+    }
+}
+
+fn default_known_hosts() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .context("could not find the home directory for the known hosts file")?
+        .join(".ssh")
+        .join("known_hosts"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(address: &str) -> Result<(String, String, u16)> {
+        let target = SftpTarget::new(address, PathBuf::from("/key"))?;
+        Ok((target.user, target.host, target.port))
+    }
+
+    #[test]
+    fn addresses_are_parsed() {
+        let expected = |user: &str, host: &str, port| (user.to_owned(), host.to_owned(), port);
+        assert_eq!(
+            parse("user@example.com").unwrap(),
+            expected("user", "example.com", 22)
+        );
+        assert_eq!(
+            parse("user@example.com:2222").unwrap(),
+            expected("user", "example.com", 2222)
+        );
+        assert_eq!(parse("user@::1").unwrap(), expected("user", "::1", 22));
+        assert_eq!(parse("user@[::1]").unwrap(), expected("user", "::1", 22));
+        assert_eq!(
+            parse("user@[fe80::1]:2222").unwrap(),
+            expected("user", "fe80::1", 2222)
+        );
+        for invalid in [
+            "example.com",
+            "@example.com",
+            "user@",
+            "user@:22",
+            "user@example.com:port",
+            "user@example.com:70000",
+            "user@[::1",
+            "user@[::1]2222",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            SftpTarget::new("user@[::1]:2222", PathBuf::from("/key"))
+                .unwrap()
+                .to_string(),
+            "sftp://user@[::1]:2222"
+        );
+    }
+
+    #[test]
+    fn targets_are_saved_and_removed() {
+        let path =
+            std::env::temp_dir().join(format!("file-sync-rs-sftp-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = AppSettings::new(path.clone()).unwrap();
+        assert_eq!(SftpTarget::load(&db).unwrap(), None);
+
+        let mut target = SftpTarget::new("user@example.com:2222", PathBuf::from("/key")).unwrap();
+        target.save(&db).unwrap();
+        assert_eq!(SftpTarget::load(&db).unwrap(), Some(target.clone()));
+        // the default known hosts file is not stored
+        assert_eq!(db.get_setting(SFTP_KNOWN_HOSTS_SETTING).unwrap(), None);
+
+        target.known_hosts = PathBuf::from("/known_hosts");
+        target.save(&db).unwrap();
+        assert_eq!(SftpTarget::load(&db).unwrap(), Some(target));
+
+        db.del_setting(SFTP_USER_SETTING).unwrap();
+        assert!(SftpTarget::load(&db).is_err());
+
+        SftpTarget::remove(&db).unwrap();
+        assert_eq!(SftpTarget::load(&db).unwrap(), None);
+        assert_eq!(db.get_setting(SFTP_PORT_SETTING).unwrap(), None);
+        let _ = std::fs::remove_file(&path);
+    }
+}
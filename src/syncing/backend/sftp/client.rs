@@ -0,0 +1,555 @@
+use ssh2::{
+    CheckResult, ErrorCode, FileStat, KnownHostFileKind, OpenFlags, OpenType, Session, Sftp,
+};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::SftpTarget;
+use crate::syncing::backend::{EntryKind, TargetBackend, TargetEntry, TargetMetadata};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long an operation waits for the server before the connection counts as lost.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// status codes of SFTP version 3
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+const FX_NO_SUCH_PATH: i32 = 10;
+const FX_FILE_ALREADY_EXISTS: i32 = 11;
+const FX_DIR_NOT_EMPTY: i32 = 18;
+
+/// Writes to an [`SftpTarget`] over a single SSH connection that all workers share.
+///
+/// The connection is opened by the first operation and the key of the server has to be in
+/// the known hosts file. If the connection is lost, the operation fails with
+/// [`ErrorKind::ConnectionAborted`] and the next operation connects again.
+#[derive(Debug, Clone)]
+pub struct SftpBackend(Arc<Shared>);
+
+#[derive(Debug)]
+struct Shared {
+    target: SftpTarget,
+    connection: Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    // the SFTP channel only works as long as its session exists
+    _session: Session,
+    sftp: Arc<Sftp>,
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Connection")
+    }
+}
+
+impl SftpBackend {
+    pub fn new(target: SftpTarget) -> Self {
+        Self(Arc::new(Shared {
+            target,
+            connection: Mutex::new(None),
+        }))
+    }
+
+    fn run<T>(
+        &self,
+        operation: impl FnOnce(&Sftp) -> Result<T, ssh2::Error>,
+    ) -> std::io::Result<T> {
+        let sftp = self.0.sftp()?;
+        operation(&sftp).map_err(|error| self.0.error(error))
+    }
+}
+
+impl Shared {
+    /// Returns the SFTP channel of the connection, connects first if there is none.
+    fn sftp(&self) -> std::io::Result<Arc<Sftp>> {
+        let mut connection = self.connection.lock().unwrap();
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.sftp.clone());
+        }
+        let (session, sftp) = self.connect().map_err(|error| {
+            Error::new(
+                ErrorKind::NotConnected,
+                format!("Could not connect to {}: {}", self.target, error),
+            )
+        })?;
+        let sftp = Arc::new(sftp);
+        *connection = Some(Connection {
+            _session: session,
+            sftp: sftp.clone(),
+        });
+        Ok(sftp)
+    }
+
+    fn connect(&self) -> Result<(Session, Sftp), String> {
+        let stream = (self.target.host.as_str(), self.target.port)
+            .to_socket_addrs()
+            .map_err(|error| error.to_string())?
+            .map(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT))
+            .reduce(|first, next| first.or(next))
+            .unwrap_or_else(|| Err(Error::from(ErrorKind::AddrNotAvailable)))
+            .map_err(|error| error.to_string())?;
+
+        let mut session = Session::new().map_err(|error| error.to_string())?;
+        session.set_tcp_stream(stream);
+        session.set_timeout(TIMEOUT.as_millis() as u32);
+        session.handshake().map_err(|error| error.to_string())?;
+        self.verify_host_key(&session)?;
+        session
+            .userauth_pubkey_file(&self.target.user, None, &self.target.key_path, None)
+            .map_err(|error| {
+                format!(
+                    "login as {:?} with the key {:?} failed: {}",
+                    self.target.user, self.target.key_path, error
+                )
+            })?;
+        let sftp = session.sftp().map_err(|error| error.to_string())?;
+        Ok((session, sftp))
+    }
+
+    /// Refuses servers whose key is missing from the known hosts or differs from it.
+    fn verify_host_key(&self, session: &Session) -> Result<(), String> {
+        let (key, _) = session
+            .host_key()
+            .ok_or("the server did not send a host key")?;
+        let mut known_hosts = session.known_hosts().map_err(|error| error.to_string())?;
+        known_hosts
+            .read_file(&self.target.known_hosts, KnownHostFileKind::OpenSSH)
+            .map_err(|error| {
+                format!(
+                    "could not read the known hosts file {:?}: {}",
+                    self.target.known_hosts, error
+                )
+            })?;
+        match known_hosts.check_port(&self.target.host, self.target.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(format!(
+                "the host key of {} is not in {:?}, connect once with ssh to verify and add it",
+                self.target.host, self.target.known_hosts
+            )),
+            CheckResult::Mismatch => Err(format!(
+                "the host key of {} does not match the key in {:?}, it was either replaced \
+                 or someone is intercepting the connection",
+                self.target.host, self.target.known_hosts
+            )),
+            CheckResult::Failure => Err(format!(
+                "the host key of {} could not be checked",
+                self.target.host
+            )),
+        }
+    }
+
+    /// Converts the error of an operation. Errors of the session mean the connection is lost.
+    fn error(&self, error: ssh2::Error) -> Error {
+        let kind = match error.code() {
+            ErrorCode::SFTP(FX_NO_SUCH_FILE | FX_NO_SUCH_PATH) => ErrorKind::NotFound,
+            ErrorCode::SFTP(FX_PERMISSION_DENIED) => ErrorKind::PermissionDenied,
+            ErrorCode::SFTP(FX_FILE_ALREADY_EXISTS) => ErrorKind::AlreadyExists,
+            ErrorCode::SFTP(FX_DIR_NOT_EMPTY) => ErrorKind::DirectoryNotEmpty,
+            ErrorCode::SFTP(_) => ErrorKind::Other,
+            ErrorCode::Session(_) => return self.disconnected(error.message()),
+        };
+        Error::new(kind, error.message().to_owned())
+    }
+
+    /// Drops the connection so the next operation connects again.
+    fn disconnected(&self, message: &str) -> Error {
+        *self.connection.lock().unwrap() = None;
+        Error::new(
+            ErrorKind::ConnectionAborted,
+            format!("Lost the connection to {}: {}", self.target, message),
+        )
+    }
+
+    /// Reading and writing files only returns messages, a request shows whether the connection is lost.
+    fn check_connection(&self, sftp: &Sftp, error: Error) -> Error {
+        match sftp.realpath(Path::new(".")) {
+            Err(probe) if matches!(probe.code(), ErrorCode::Session(_)) => {
+                self.disconnected(&error.to_string())
+            }
+            _ => error,
+        }
+    }
+}
+
+fn metadata(stat: &ssh2::FileStat) -> TargetMetadata {
+    let file_type = stat.file_type();
+    TargetMetadata {
+        kind: if file_type.is_file() {
+            EntryKind::File
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else {
+            EntryKind::Other
+        },
+        len: stat.size.unwrap_or(0),
+        modified: stat
+            .mtime
+            .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+    }
+}
+
+impl TargetBackend for SftpBackend {
+    fn stat(&self, path: &Path) -> std::io::Result<Option<TargetMetadata>> {
+        match self.run(|sftp| sftp.stat(path)) {
+            Ok(stat) => Ok(Some(metadata(&stat))),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+        let sftp = self.0.sftp()?;
+        let file = sftp.open(path).map_err(|error| self.0.error(error))?;
+        Ok(Box::new(RemoteFile {
+            shared: self.0.clone(),
+            sftp,
+            file: Some(file),
+        }))
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+        let sftp = self.0.sftp()?;
+        let file = sftp
+            .open_mode(
+                path,
+                OpenFlags::WRITE | OpenFlags::TRUNCATE,
+                0o644,
+                OpenType::File,
+            )
+            .map_err(|error| self.0.error(error))?;
+        Ok(Box::new(RemoteFile {
+            shared: self.0.clone(),
+            sftp,
+            file: Some(file),
+        }))
+    }
+
+    fn mkdir(&self, path: &Path) -> std::io::Result<()> {
+        match self.run(|sftp| sftp.mkdir(path, 0o755)) {
+            // OpenSSH does not tell why creating a directory failed
+            Err(error) if error.kind() == ErrorKind::Other && self.stat(path)?.is_some() => {
+                Err(Error::new(ErrorKind::AlreadyExists, error))
+            }
+            result => result,
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        self.run(|sftp| sftp.unlink(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.run(|sftp| sftp.rmdir(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        match self.run(|sftp| sftp.rename(from, to, None)) {
+            // servers of SFTP version 3 do not replace files when renaming
+            Err(error)
+                if error.kind() != ErrorKind::ConnectionAborted
+                    && self.stat(to)?.is_some_and(|to| to.is_file()) =>
+            {
+                self.remove_file(to)?;
+                self.run(|sftp| sftp.rename(from, to, None))
+            }
+            result => result,
+        }
+    }
+
+    fn list(&self, path: &Path) -> std::io::Result<Vec<TargetEntry>> {
+        Ok(self
+            .run(|sftp| sftp.readdir(path))?
+            .into_iter()
+            .map(|(entry, stat)| TargetEntry {
+                name: entry.file_name().unwrap_or_default().to_owned(),
+                kind: metadata(&stat).kind,
+            })
+            .collect())
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> std::io::Result<()> {
+        // the protocol only knows whole seconds
+        let seconds = modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        self.run(|sftp| {
+            sftp.setstat(
+                path,
+                FileStat {
+                    size: None,
+                    uid: None,
+                    gid: None,
+                    perm: None,
+                    atime: Some(seconds),
+                    mtime: Some(seconds),
+                },
+            )
+        })
+    }
+}
+
+/// A file on the server that is read or written, see [`TargetBackend::read`].
+struct RemoteFile {
+    shared: Arc<Shared>,
+    sftp: Arc<Sftp>,
+    /// taken once the file is closed by flushing it
+    file: Option<ssh2::File>,
+}
+
+impl RemoteFile {
+    fn file(&mut self) -> std::io::Result<&mut ssh2::File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| Error::other("the file was already closed"))
+    }
+}
+
+impl Read for RemoteFile {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.file()?
+            .read(buffer)
+            .map_err(|error| self.shared.check_connection(&self.sftp, error))
+    }
+}
+
+impl Write for RemoteFile {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.file()?
+            .write(buffer)
+            .map_err(|error| self.shared.check_connection(&self.sftp, error))
+    }
+
+    /// Closes the file, the server reports errors of writing the file at the latest now.
+    fn flush(&mut self) -> std::io::Result<()> {
+        let Some(mut file) = self.file.take() else {
+            return Ok(());
+        };
+        file.close().map_err(|error| self.shared.error(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+
+    /// An OpenSSH server on localhost with fresh keys, only reachable with [`Server::target`].
+    /// `sshd` is taken from the `FILE_SYNC_RS_SSHD` environment variable or `/usr/sbin/sshd`.
+    struct Server {
+        dir: PathBuf,
+        port: u16,
+        process: Child,
+    }
+
+    fn keygen(path: &Path) {
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    impl Server {
+        /// With `single_connection` the server exits when the first connection is closed.
+        fn start(name: &str, single_connection: bool) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "file-sync-rs-sftp-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("root")).unwrap();
+            keygen(&dir.join("host_key"));
+            keygen(&dir.join("user_key"));
+            std::fs::copy(dir.join("user_key.pub"), dir.join("authorized_keys")).unwrap();
+
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let host_key = std::fs::read_to_string(dir.join("host_key.pub")).unwrap();
+            std::fs::write(
+                dir.join("known_hosts"),
+                format!("[127.0.0.1]:{} {}", port, host_key),
+            )
+            .unwrap();
+            std::fs::write(
+                dir.join("sshd_config"),
+                format!(
+                    "ListenAddress 127.0.0.1\nPort {}\nHostKey {}\nAuthorizedKeysFile {}\n\
+                     PidFile none\nStrictModes no\nUsePAM no\nPasswordAuthentication no\n\
+                     Subsystem sftp internal-sftp\n",
+                    port,
+                    dir.join("host_key").display(),
+                    dir.join("authorized_keys").display(),
+                ),
+            )
+            .unwrap();
+
+            let process = spawn(&dir, port, single_connection);
+            Self { dir, port, process }
+        }
+
+        fn target(&self) -> SftpTarget {
+            SftpTarget {
+                host: "127.0.0.1".to_owned(),
+                port: self.port,
+                user: whoami(),
+                key_path: self.dir.join("user_key"),
+                known_hosts: self.dir.join("known_hosts"),
+            }
+        }
+
+        fn root(&self) -> PathBuf {
+            self.dir.join("root")
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Runs `sshd` in the foreground and waits until it accepts connections.
+    /// With `single_connection` it serves one connection and exits.
+    fn spawn(dir: &Path, port: u16, single_connection: bool) -> Child {
+        let sshd = std::env::var("FILE_SYNC_RS_SSHD").unwrap_or("/usr/sbin/sshd".to_owned());
+        let process = Command::new(sshd)
+            .arg(if single_connection { "-d" } else { "-D" })
+            .arg("-f")
+            .arg(dir.join("sshd_config"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("sshd is needed for the SFTP tests");
+        if single_connection {
+            // connecting to check would use up the only connection
+            std::thread::sleep(Duration::from_secs(1));
+            return process;
+        }
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        process
+    }
+
+    fn whoami() -> String {
+        let output = Command::new("id").arg("-un").output().unwrap();
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    }
+
+    fn read(backend: &SftpBackend, path: &Path) -> Vec<u8> {
+        let mut content = Vec::new();
+        backend
+            .read(path)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn write(backend: &SftpBackend, path: &Path, content: &[u8]) {
+        let mut writer = backend.write(path).unwrap();
+        writer.write_all(content).unwrap();
+        writer.flush().unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs OpenSSH's sshd, run with --features sftp -- --ignored"]
+    fn files_are_written_over_sftp() {
+        let server = Server::start("files", false);
+        let backend = SftpBackend::new(server.target());
+        let dir = server.root().join("a").join("b");
+
+        backend.mkdir_all(&dir).unwrap();
+        assert!(backend.stat(&dir).unwrap().unwrap().is_dir());
+        assert_eq!(
+            backend.mkdir(&dir).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+
+        write(&backend, &dir.join("file"), b"content");
+        write(&backend, &dir.join("other"), b"other content");
+        let stat = backend.stat(&dir.join("file")).unwrap().unwrap();
+        assert!(stat.is_file());
+        assert_eq!(stat.len, 7);
+        assert_eq!(read(&backend, &dir.join("file")), b"content");
+        assert_eq!(std::fs::read(dir.join("file")).unwrap(), b"content");
+
+        backend
+            .rename(&dir.join("other"), &dir.join("file"))
+            .unwrap();
+        assert_eq!(read(&backend, &dir.join("file")), b"other content");
+        assert_eq!(
+            backend.list(&dir).unwrap(),
+            vec![TargetEntry {
+                name: "file".into(),
+                kind: EntryKind::File
+            }]
+        );
+
+        backend.remove_file(&dir.join("file")).unwrap();
+        backend.remove_dir(&dir).unwrap();
+        assert_eq!(backend.stat(&dir).unwrap(), None);
+    }
+
+    #[test]
+    #[ignore = "needs OpenSSH's sshd, run with --features sftp -- --ignored"]
+    fn unknown_and_changed_host_keys_are_refused() {
+        let server = Server::start("host-keys", false);
+
+        let mut target = server.target();
+        target.known_hosts = server.dir.join("empty_known_hosts");
+        std::fs::write(&target.known_hosts, "").unwrap();
+        let error = SftpBackend::new(target.clone())
+            .stat(&server.root())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotConnected);
+        assert!(error.to_string().contains("is not in"), "{}", error);
+
+        keygen(&server.dir.join("other_key"));
+        let other_key = std::fs::read_to_string(server.dir.join("other_key.pub")).unwrap();
+        std::fs::write(
+            &target.known_hosts,
+            format!("[127.0.0.1]:{} {}", server.port, other_key),
+        )
+        .unwrap();
+        let error = SftpBackend::new(target).stat(&server.root()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotConnected);
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    #[test]
+    #[ignore = "needs OpenSSH's sshd, run with --features sftp -- --ignored"]
+    fn lost_connections_fail_and_reconnect() {
+        let mut server = Server::start("disconnect", true);
+        let backend = SftpBackend::new(server.target());
+        assert!(backend.stat(&server.root()).unwrap().is_some());
+
+        server.process.kill().unwrap();
+        server.process.wait().unwrap();
+        let error = backend.stat(&server.root()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(
+            backend.stat(&server.root()).unwrap_err().kind(),
+            ErrorKind::NotConnected
+        );
+
+        server.process = spawn(&server.dir, server.port, true);
+        assert!(backend.stat(&server.root()).unwrap().is_some());
+    }
+}
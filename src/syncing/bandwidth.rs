@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::db::AppSettings;
+
+/// The setting key for the bandwidth limit in bytes per second. Unlimited if it is not set.
+pub const BANDWIDTH_LIMIT_SETTING: &str = "bandwidth_limit";
+/// The setting key for the limits by time of day, see [`LimitWindow`].
+pub const BANDWIDTH_SCHEDULE_SETTING: &str = "bandwidth_schedule";
+
+/// How many bytes are written between two checks of the limit.
+pub(super) const CHUNK_SIZE: usize = 64 * 1024;
+/// How long a writer sleeps at most before it checks the limit again.
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// A limit that applies between two times of the day instead of the bandwidth limit.
+///
+/// Written as `08:00-18:00=1000000`, the limit is in bytes per second and `0` means unlimited.
+/// Windows where the end is before the start go over midnight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitWindow {
+    from: NaiveTime,
+    to: NaiveTime,
+    limit: Option<u64>,
+}
+
+impl LimitWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            self.from <= time || time < self.to
+        }
+    }
+}
+
+impl std::str::FromStr for LimitWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (times, limit) = s
+            .split_once('=')
+            .with_context(|| format!("expected FROM-TO=BYTES, got {:?}", s))?;
+        let (from, to) = times
+            .split_once('-')
+            .with_context(|| format!("expected FROM-TO=BYTES, got {:?}", s))?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .with_context(|| format!("invalid time of day {:?}, expected HH:MM", time.trim()))
+        };
+        let limit = limit
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("invalid bandwidth limit {:?}", limit.trim()))?;
+        Ok(Self {
+            from: parse_time(from)?,
+            to: parse_time(to)?,
+            limit: (limit > 0).then_some(limit),
+        })
+    }
+}
+
+impl std::fmt::Display for LimitWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}={}",
+            self.from.format("%H:%M"),
+            self.to.format("%H:%M"),
+            self.limit.unwrap_or(0)
+        )
+    }
+}
+
+/// Parses windows separated by commas, see [`LimitWindow`].
+pub fn parse_schedule(value: &str) -> Result<Vec<LimitWindow>> {
+    value
+        .split(',')
+        .filter(|window| !window.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Reads the bandwidth limit from the settings.
+pub fn get_limit(db: &AppSettings) -> Result<Option<u64>> {
+    match db.get_setting(BANDWIDTH_LIMIT_SETTING)? {
+        None => Ok(None),
+        Some(value) => parse_limit(&value),
+    }
+}
+
+/// Parses a bandwidth limit in bytes per second, `0` is unlimited.
+pub fn parse_limit(value: &str) -> Result<Option<u64>> {
+    let limit = value
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("invalid bandwidth limit {:?}", value))?;
+    Ok((limit > 0).then_some(limit))
+}
+
+/// Stores the bandwidth limit in the settings, `None` removes it.
+pub fn set_limit(db: &AppSettings, limit: Option<u64>) -> Result<()> {
+    match limit {
+        Some(limit) => db.set_setting(BANDWIDTH_LIMIT_SETTING, &limit.to_string()),
+        None => db.del_setting(BANDWIDTH_LIMIT_SETTING),
+    }
+}
+
+pub fn get_schedule(db: &AppSettings) -> Result<Vec<LimitWindow>> {
+    match db.get_setting(BANDWIDTH_SCHEDULE_SETTING)? {
+        Some(value) => parse_schedule(&value).context("invalid bandwidth schedule"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Limits the bytes written by all workers of a sync together.
+///
+/// Clones share the limit, so it can be changed while the sync is running.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    limit: Option<u64>,
+    schedule: Vec<LimitWindow>,
+    /// bytes that can be written without waiting, negative if the writers are ahead
+    available: f64,
+    updated: Option<Instant>,
+}
+
+impl Bucket {
+    fn limit_at(&self, time: NaiveTime) -> Option<u64> {
+        match self.schedule.iter().find(|window| window.contains(time)) {
+            Some(window) => window.limit,
+            None => self.limit,
+        }
+    }
+
+    /// Takes `bytes` from the bucket and returns how long the writer has to wait for them.
+    fn take(&mut self, bytes: usize, limit: u64, now: Instant) -> Duration {
+        let limit = limit as f64;
+        let elapsed = self
+            .updated
+            .map_or(0.0, |updated| (now - updated).as_secs_f64());
+        // allow bursts of at most one second
+        self.available = (self.available + elapsed * limit).min(limit);
+        self.available -= bytes as f64;
+        self.updated = Some(now);
+        if self.available < 0.0 {
+            Duration::from_secs_f64(-self.available / limit)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u64>, schedule: Vec<LimitWindow>) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                limit,
+                schedule,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Changes the limit outside of the scheduled windows.
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.bucket.lock().unwrap().limit = limit;
+    }
+
+    pub fn set_schedule(&self, schedule: Vec<LimitWindow>) {
+        self.bucket.lock().unwrap().schedule = schedule;
+    }
+
+    /// Whether writes have to be split up to be throttled.
+    pub(super) fn is_limited(&self) -> bool {
+        let bucket = self.bucket.lock().unwrap();
+        bucket.limit.is_some() || bucket.schedule.iter().any(|window| window.limit.is_some())
+    }
+
+    /// Blocks until `bytes` can be written without exceeding the current limit.
+    ///
+    /// Sleeps in slices of at most [`MAX_SLEEP`] and looks at the limit again after each of
+    /// them, so a changed limit also applies to writers that are already waiting.
+    pub(super) fn throttle(&self, bytes: usize) {
+        let mut bytes = bytes;
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let Some(limit) = bucket.limit_at(Local::now().time()) else {
+                    bucket.available = 0.0;
+                    bucket.updated = None;
+                    return;
+                };
+                bucket.take(bytes, limit, Instant::now())
+            };
+            if wait.is_zero() {
+                return;
+            }
+            // the bytes are taken, later rounds only wait for the bucket to refill
+            bytes = 0;
+            std::thread::sleep(wait.min(MAX_SLEEP));
+        }
+    }
+}
+
+/// Writes the whole buffer in chunks, each one waits for the limiter.
+pub(super) fn write_all(
+    file: &mut impl Write,
+    buffer: &[u8],
+    limiter: &RateLimiter,
+) -> std::io::Result<()> {
+    for chunk in buffer.chunks(CHUNK_SIZE) {
+        limiter.throttle(chunk.len());
+        file.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_windows() {
+        let schedule = parse_schedule("08:00-18:00=1000, 22:00-06:00=0,").unwrap();
+        assert_eq!(
+            schedule,
+            vec![
+                LimitWindow {
+                    from: time(8, 0),
+                    to: time(18, 0),
+                    limit: Some(1000),
+                },
+                LimitWindow {
+                    from: time(22, 0),
+                    to: time(6, 0),
+                    limit: None,
+                },
+            ]
+        );
+        assert_eq!(schedule[1].to_string(), "22:00-06:00=0");
+        for invalid in ["08:00=1000", "08:00-18:00", "8-18=1000", "08:00-18:00=-1"] {
+            assert!(
+                parse_schedule(invalid).is_err(),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn windows_apply_instead_of_the_limit() {
+        let bucket = Bucket {
+            limit: Some(500),
+            schedule: parse_schedule("08:00-18:00=1000,22:00-06:00=0").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(bucket.limit_at(time(8, 0)), Some(1000));
+        assert_eq!(bucket.limit_at(time(18, 0)), Some(500));
+        assert_eq!(bucket.limit_at(time(23, 0)), None);
+        assert_eq!(bucket.limit_at(time(5, 59)), None);
+        assert_eq!(bucket.limit_at(time(6, 0)), Some(500));
+    }
+
+    #[test]
+    fn token_math() {
+        let mut bucket = Bucket::default();
+        let start = Instant::now();
+        // the first write waits for its bytes
+        assert_eq!(bucket.take(500, 1000, start), Duration::from_millis(500));
+        // the writer slept and the bucket refilled
+        assert_eq!(
+            bucket.take(1000, 1000, start + Duration::from_millis(500)),
+            Duration::from_secs(1)
+        );
+        // writing ahead of the limit adds up
+        assert_eq!(
+            bucket.take(1000, 1000, start + Duration::from_millis(500)),
+            Duration::from_secs(2)
+        );
+
+        // after a pause, bursts are limited to one second
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.take(1000, 1000, later), Duration::ZERO);
+        assert_eq!(bucket.take(500, 1000, later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn unlimited_writes_do_not_wait() {
+        let limiter = RateLimiter::default();
+        assert!(!limiter.is_limited());
+        limiter.set_schedule(parse_schedule("00:00-00:00=0").unwrap());
+        assert!(!limiter.is_limited());
+        limiter.set_limit(Some(1));
+        assert!(limiter.is_limited());
+        limiter.set_limit(None);
+
+        let start = Instant::now();
+        limiter.throttle(1 << 30);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn waiting_writers_see_changed_limits() {
+        for change in [None, Some(1 << 30)] {
+            let limiter = RateLimiter::new(Some(1000), Vec::new());
+            let writer = {
+                let limiter = limiter.clone();
+                std::thread::spawn(move || {
+                    let start = Instant::now();
+                    // would wait for ten seconds at the old limit
+                    limiter.throttle(10_000);
+                    start.elapsed()
+                })
+            };
+            std::thread::sleep(Duration::from_millis(200));
+            limiter.set_limit(change);
+            assert!(writer.join().unwrap() < Duration::from_secs(2));
+        }
+    }
+}
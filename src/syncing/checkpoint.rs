@@ -0,0 +1,287 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::DateTime;
+
+const CHECKPOINT_FILENAME: &str = "file_sync_rs.checkpoint";
+
+/// How often completed files are written to the checkpoint.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The first line of the checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    started: DateTime,
+    sources: Vec<PathBuf>,
+}
+
+/// A completed file, every line after the header is one.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    fn new(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Progress of an interrupted sync, read from the target.
+#[derive(Debug)]
+pub struct Checkpoint {
+    started: DateTime,
+    /// length and modification time of the completed source files when they were synced
+    completed: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+impl Checkpoint {
+    /// Reads the checkpoint of an interrupted sync of the `sources` to `target`.
+    /// Returns `None` if there is none or it belongs to different sources.
+    pub fn read(target: &Path, sources: &[PathBuf]) -> Result<Option<Self>> {
+        let path = target.join(CHECKPOINT_FILENAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open checkpoint {:?}", path))?;
+        let mut lines = std::io::BufReader::new(file).lines();
+
+        let Some(Ok(header)) = lines.next() else {
+            return Ok(None);
+        };
+        let Ok(header) = serde_json::from_str::<Header>(&header) else {
+            return Ok(None);
+        };
+        if header.sources != sources {
+            return Ok(None);
+        }
+
+        let mut completed = HashMap::new();
+        for line in lines {
+            let line = line.with_context(|| format!("failed to read checkpoint {:?}", path))?;
+            // the last line is incomplete if the app crashed while writing it
+            if let Ok(entry) = serde_json::from_str::<Entry>(&line) {
+                completed.insert(entry.path, (entry.len, entry.modified));
+            }
+        }
+        Ok(Some(Self {
+            started: header.started,
+            completed,
+        }))
+    }
+
+    /// When the interrupted sync was started.
+    pub fn started(&self) -> &DateTime {
+        &self.started
+    }
+
+    /// The number of files that were completed.
+    pub fn completed_files(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Returns the size of the source file if it was synced and has not changed since.
+    pub fn completed_size(&self, path: &Path) -> Option<u64> {
+        let (len, modified) = self.completed.get(path)?;
+        let metadata = std::fs::metadata(path).ok()?;
+        (metadata.len() == *len && modified.is_some() && metadata.modified().ok() == *modified)
+            .then_some(*len)
+    }
+
+    /// Removes the checkpoint after the sync completed.
+    pub fn remove(target: &Path) -> Result<()> {
+        match std::fs::remove_file(target.join(CHECKPOINT_FILENAME)) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).context("failed to remove checkpoint")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn ends_with_newline(path: &Path) -> Result<bool> {
+    use std::io::{Read, Seek};
+
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("failed to open checkpoint {:?}", path))?;
+    if file.metadata().map(|metadata| metadata.len()).unwrap_or(0) == 0 {
+        return Ok(true);
+    }
+    let mut last = [0];
+    file.seek(std::io::SeekFrom::End(-1))
+        .and_then(|_| file.read_exact(&mut last))
+        .with_context(|| format!("failed to read checkpoint {:?}", path))?;
+    Ok(last[0] == b'\n')
+}
+
+/// Writes completed files of a running sync to the checkpoint in the target.
+#[derive(Debug)]
+pub struct CheckpointWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    written: Instant,
+}
+
+impl CheckpointWriter {
+    /// Starts a new checkpoint or continues the existing one if `resume` is set.
+    pub fn create(
+        target: &Path,
+        started: DateTime,
+        sources: &[PathBuf],
+        resume: bool,
+    ) -> Result<Self> {
+        let path = target.join(CHECKPOINT_FILENAME);
+        let file = if resume {
+            std::fs::OpenOptions::new().append(true).open(&path)
+        } else {
+            std::fs::File::create(&path)
+        }
+        .with_context(|| format!("failed to open checkpoint {:?}", path))?;
+
+        let mut writer = Self {
+            file: std::io::BufWriter::new(file),
+            written: Instant::now(),
+        };
+        if resume && !ends_with_newline(&path)? {
+            // the last line is incomplete, new entries must not be appended to it
+            writeln!(writer.file).context("failed to write checkpoint")?;
+        }
+        if !resume {
+            let header = Header {
+                started,
+                sources: sources.to_vec(),
+            };
+            writer.write_line(&header)?;
+            writer.flush()?;
+        }
+        Ok(writer)
+    }
+
+    /// Records the completed source file. It is written with the next [`CheckpointWriter::flush_if_due`].
+    pub fn record(&mut self, path: &Path) -> Result<()> {
+        match Entry::new(path) {
+            Some(entry) => self.write_line(&entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes recorded files to the target if the last write was a while ago.
+    pub fn flush_if_due(&mut self) -> Result<()> {
+        if self.written.elapsed() < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush().context("failed to write checkpoint")?;
+        self.written = Instant::now();
+        Ok(())
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<()> {
+        // paths that are not valid unicode can not be stored and are synced again
+        let Ok(line) = serde_json::to_string(value) else {
+            return Ok(());
+        };
+        writeln!(self.file, "{}", line).context("failed to write checkpoint")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sync-rs-checkpoint-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("source")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("source").join("a"), "aaa").unwrap();
+        std::fs::write(dir.join("source").join("b"), "bb").unwrap();
+        (dir.join("source"), dir.join("target"))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (source, target) = fixture("load");
+        let sources = vec![source.clone()];
+        let started = chrono::offset::Utc::now();
+        assert!(Checkpoint::read(&target, &sources).unwrap().is_none());
+
+        let mut writer = CheckpointWriter::create(&target, started, &sources, false).unwrap();
+        writer.record(&source.join("a")).unwrap();
+        // vanished files are not recorded
+        writer.record(&source.join("missing")).unwrap();
+        writer.flush().unwrap();
+
+        let checkpoint = Checkpoint::read(&target, &sources).unwrap().unwrap();
+        assert_eq!(*checkpoint.started(), started);
+        assert_eq!(checkpoint.completed_files(), 1);
+        assert_eq!(checkpoint.completed_size(&source.join("a")), Some(3));
+        assert_eq!(checkpoint.completed_size(&source.join("b")), None);
+
+        // a changed file has to be synced again
+        std::fs::write(source.join("a"), "changed").unwrap();
+        assert_eq!(checkpoint.completed_size(&source.join("a")), None);
+
+        // the checkpoint of other sources does not apply
+        assert!(
+            Checkpoint::read(&target, &[source.join("a")])
+                .unwrap()
+                .is_none()
+        );
+
+        Checkpoint::remove(&target).unwrap();
+        assert!(Checkpoint::read(&target, &sources).unwrap().is_none());
+        Checkpoint::remove(&target).unwrap();
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn resume_appends_and_skips_incomplete_lines() {
+        let (source, target) = fixture("resume");
+        let sources = vec![source.clone()];
+        let started = chrono::offset::Utc::now();
+        let mut writer = CheckpointWriter::create(&target, started, &sources, false).unwrap();
+        writer.record(&source.join("a")).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // the app crashed while writing a line
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(target.join(CHECKPOINT_FILENAME))
+            .unwrap()
+            .write_all(b"{\"path\":\"/so")
+            .unwrap();
+
+        let checkpoint = Checkpoint::read(&target, &sources).unwrap().unwrap();
+        assert_eq!(checkpoint.completed_files(), 1);
+
+        let mut writer = CheckpointWriter::create(&target, started, &sources, true).unwrap();
+        writer.record(&source.join("b")).unwrap();
+        writer.flush().unwrap();
+
+        let checkpoint = Checkpoint::read(&target, &sources).unwrap().unwrap();
+        assert_eq!(*checkpoint.started(), started);
+        assert_eq!(checkpoint.completed_files(), 2);
+        assert_eq!(checkpoint.completed_size(&source.join("a")), Some(3));
+        assert_eq!(checkpoint.completed_size(&source.join("b")), Some(2));
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+}
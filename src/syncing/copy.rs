@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+
+use super::backend::{self, LocalBackend};
+use super::bandwidth::{self, RateLimiter};
+use super::sparse;
+
+/// How a new file was copied to the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CopyMethod {
+    /// The content was read and written.
+    Bytes,
+    /// The target shares the blocks of the source until one of them changes (FICLONE).
+    Reflink,
+    /// The kernel or the filesystem copied the content without reading it (copy_file_range).
+    Offload,
+}
+
+/// Copies the file with the fastest method the filesystems support and returns the bytes copied.
+///
+/// Reflinks are tried first, then sparse files are copied without their holes,
+/// then the copy is offloaded and finally the content is read and written.
+pub(super) fn copy(
+    source: &Path,
+    target: &Path,
+    limiter: &RateLimiter,
+) -> Result<(u64, CopyMethod)> {
+    {
+        let source_file = File::open(source)
+            .with_context(|| format!("Could not open source file {:?}", source))?;
+        let target_file = File::create(target)
+            .with_context(|| format!("Could not create target file {:?}", target))?;
+        if reflink(&source_file, &target_file) {
+            let len = source_file
+                .metadata()
+                .with_context(|| format!("Could not query metadata of {:?}", source))?
+                .len();
+            return Ok((len, CopyMethod::Reflink));
+        }
+    }
+
+    if let Some(bytes) = sparse::copy(source, target, limiter)? {
+        return Ok((bytes, CopyMethod::Bytes));
+    }
+
+    {
+        let source_file = File::open(source)
+            .with_context(|| format!("Could not open source file {:?}", source))?;
+        let target_file = File::create(target)
+            .with_context(|| format!("Could not create target file {:?}", target))?;
+        if let Some(bytes) = offload(&source_file, &target_file, limiter)
+            .with_context(|| format!("Could not copy file {:?} to {:?}", source, target))?
+        {
+            return Ok((bytes, CopyMethod::Offload));
+        }
+    }
+
+    let bytes = backend::upload(&LocalBackend, source, target, limiter)?;
+    Ok((bytes, CopyMethod::Bytes))
+}
+
+/// Lets the empty target share the blocks of the source. Returns `false` if that is not supported.
+#[cfg(target_os = "linux")]
+fn reflink(source: &File, target: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    // only changes the target, which is owned by this process
+    unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &File, _target: &File) -> bool {
+    false
+}
+
+/// Copies the content with `copy_file_range`, in chunks if the limiter has a limit.
+/// Returns `None` if the filesystems do not support it.
+#[cfg(target_os = "linux")]
+fn offload(source: &File, target: &File, limiter: &RateLimiter) -> std::io::Result<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = source.metadata()?.len();
+    offload_chunks(len, limiter, |size| {
+        // copies between the current positions of both files and moves them
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                std::ptr::null_mut(),
+                target.as_raw_fd(),
+                std::ptr::null_mut(),
+                size,
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(result as u64)
+    })
+}
+
+/// Calls `copy_range` with the size of each chunk until `len` bytes are copied.
+/// Returns `None` if nothing could be copied, so the caller can fall back to another method.
+#[cfg(target_os = "linux")]
+fn offload_chunks(
+    len: u64,
+    limiter: &RateLimiter,
+    mut copy_range: impl FnMut(usize) -> std::io::Result<u64>,
+) -> std::io::Result<Option<u64>> {
+    const UNLIMITED_CHUNK_SIZE: u64 = 1024 * 1024 * 1024;
+
+    let chunk_size = if limiter.is_limited() {
+        bandwidth::CHUNK_SIZE as u64
+    } else {
+        UNLIMITED_CHUNK_SIZE
+    };
+    let mut copied = 0;
+    while copied < len {
+        let size = (len - copied).min(chunk_size) as usize;
+        limiter.throttle(size);
+        let result = match copy_range(size) {
+            Ok(result) => result,
+            Err(error) => {
+                let unsupported = [libc::EXDEV, libc::ENOSYS, libc::EOPNOTSUPP, libc::EINVAL]
+                    .into_iter()
+                    .any(|code| error.raw_os_error() == Some(code));
+                if copied == 0 && unsupported {
+                    return Ok(None);
+                }
+                return Err(error);
+            }
+        };
+        if result == 0 {
+            // some filesystems, e.g. sysfs, report a length but copy nothing
+            if copied == 0 {
+                return Ok(None);
+            }
+            // the source got shorter
+            break;
+        }
+        copied += result;
+    }
+    Ok(Some(copied))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn offload(_source: &File, _target: &File, _limiter: &RateLimiter) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_falls_back_without_reflinks() {
+        let dir = std::env::temp_dir().join(format!("file-sync-rs-copy-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = vec![7; 3 * bandwidth::CHUNK_SIZE + 5];
+        std::fs::write(dir.join("source"), &content).unwrap();
+
+        let reflinks = reflink(
+            &File::open(dir.join("source")).unwrap(),
+            &File::create(dir.join("probe")).unwrap(),
+        );
+        let (bytes, method) = copy(
+            &dir.join("source"),
+            &dir.join("target"),
+            &RateLimiter::default(),
+        )
+        .unwrap();
+        assert_eq!(bytes, content.len() as u64);
+        assert_eq!(method == CopyMethod::Reflink, reflinks);
+        assert!(std::fs::read(dir.join("target")).unwrap() == content);
+
+        // a limit makes the copy go through the limiter, which can not throttle reflinks
+        let limiter = RateLimiter::new(Some(u64::MAX / 2), Vec::new());
+        let (bytes, _) = copy(&dir.join("source"), &dir.join("limited"), &limiter).unwrap();
+        assert_eq!(bytes, content.len() as u64);
+        assert!(std::fs::read(dir.join("limited")).unwrap() == content);
+
+        // an existing target is replaced
+        std::fs::write(dir.join("target"), vec![1; content.len() * 2]).unwrap();
+        copy(
+            &dir.join("source"),
+            &dir.join("target"),
+            &RateLimiter::default(),
+        )
+        .unwrap();
+        assert!(std::fs::read(dir.join("target")).unwrap() == content);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn offload_falls_back_if_nothing_is_copied() {
+        let limiter = RateLimiter::default();
+        // the length is reported, but the filesystem does not copy anything
+        assert_eq!(offload_chunks(10, &limiter, |_| Ok(0)).unwrap(), None);
+        assert_eq!(
+            offload_chunks(10, &limiter, |_| Err(std::io::Error::from_raw_os_error(
+                libc::EXDEV
+            )))
+            .unwrap(),
+            None
+        );
+        // a source that got shorter is copied as far as it goes
+        let mut results = [4, 0].into_iter();
+        assert_eq!(
+            offload_chunks(10, &limiter, |_| Ok(results.next().unwrap())).unwrap(),
+            Some(4)
+        );
+        let mut calls = 0;
+        assert!(
+            offload_chunks(10, &limiter, |_| {
+                calls += 1;
+                if calls == 1 {
+                    Ok(4)
+                } else {
+                    Err(std::io::Error::from_raw_os_error(libc::EXDEV))
+                }
+            })
+            .is_err()
+        );
+        assert_eq!(
+            offload_chunks(0, &limiter, |_| unreachable!()).unwrap(),
+            Some(0)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::bandwidth::{self, RateLimiter};
+
+/// The setting key for [`super::options::SyncOptions::delta_transfer`].
+pub const DELTA_TRANSFER_SETTING: &str = "delta_transfer";
+
+/// How many bytes are read from the source at once.
+const READ_SIZE: usize = 1024 * 1024;
+/// Data that does not match the target is split into operations of at most this size.
+const MAX_DATA_SIZE: usize = 1024 * 1024;
+
+/// How a [`super::sync::Job`] compares a changed file with its target and writes it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransferStrategy {
+    /// Reads both files and rewrites the target if they differ.
+    #[default]
+    Whole,
+    /// Finds the blocks of the target in the source with a rolling checksum like rsync
+    /// and only writes what changed, see [`transfer`].
+    Delta,
+}
+
+/// The checksums of the blocks of the target.
+#[derive(Debug)]
+struct Signature {
+    block_size: usize,
+    /// indices of the full blocks by their weak checksum
+    blocks: HashMap<u32, Vec<(u64, [u8; 20])>>,
+    /// the last block if it is shorter, it can only match the end of the source
+    tail: Option<(u64, [u8; 20], usize)>,
+}
+
+impl Signature {
+    fn read(target: &mut File, len: u64) -> std::io::Result<Self> {
+        // like rsync, larger files have fewer but larger blocks
+        let block_size = ((len as f64).sqrt() as usize).clamp(2 * 1024, 128 * 1024);
+        let mut signature = Self {
+            block_size,
+            blocks: HashMap::new(),
+            tail: None,
+        };
+
+        target.seek(SeekFrom::Start(0))?;
+        let mut buffer = vec![0; block_size];
+        let mut index = 0;
+        loop {
+            let read = read_full(target, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            let block = &buffer[..read];
+            if read < block_size {
+                signature.tail = Some((index, strong(block), read));
+                break;
+            }
+            signature
+                .blocks
+                .entry(Rolling::new(block).digest())
+                .or_default()
+                .push((index, strong(block)));
+            index += 1;
+        }
+        Ok(signature)
+    }
+
+    /// Returns the index of the full block with the same content as `window`.
+    fn find(&self, weak: u32, window: &[u8]) -> Option<u64> {
+        let candidates = self.blocks.get(&weak)?;
+        let strong = strong(window);
+        candidates
+            .iter()
+            .find(|(_, candidate)| *candidate == strong)
+            .map(|(index, _)| *index)
+    }
+}
+
+/// Builds the source from the target.
+#[derive(Debug, PartialEq, Eq)]
+enum Operation {
+    /// Bytes of the target starting at the offset.
+    Copy { from: u64, len: u64 },
+    /// Bytes that are not in the target.
+    Data(Vec<u8>),
+}
+
+/// The weak checksum of rsync, it can be moved forward by one byte.
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(block: &[u8]) -> Self {
+        let len = block.len() as u32;
+        let mut a: u32 = 0;
+        let mut b: u32 = 0;
+        for (i, byte) in block.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(*byte as u32));
+        }
+        Self { a, b, len }
+    }
+
+    /// Removes `out` from the start of the window and adds `next` to its end.
+    fn roll(&mut self, out: u8, next: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(next as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+fn strong(block: &[u8]) -> [u8; 20] {
+    Sha1::digest(block).into()
+}
+
+/// Reads until the buffer is full or the end of the file is reached.
+fn read_full(file: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Adds a copy, or extends the last one if it ends where this one starts.
+fn push_copy(operations: &mut Vec<Operation>, from: u64, len: u64) {
+    if let Some(Operation::Copy {
+        from: last_from,
+        len: last_len,
+    }) = operations.last_mut()
+        && *last_from + *last_len == from
+    {
+        *last_len += len;
+    } else {
+        operations.push(Operation::Copy { from, len });
+    }
+}
+
+/// Finds the operations that build the source from the target of the `signature`.
+fn diff(source: &mut impl Read, signature: &Signature) -> std::io::Result<Vec<Operation>> {
+    let block_size = signature.block_size;
+    let mut operations = Vec::new();
+
+    // read but not yet processed bytes, the window starts at `start`
+    let mut buffer = Vec::new();
+    let mut start = 0;
+    // bytes before the window that are not in the target
+    let mut data_start = 0;
+    let mut rolling: Option<Rolling> = None;
+    let mut end_of_file = false;
+    loop {
+        if !end_of_file && buffer.len() < start + block_size + 1 {
+            buffer.drain(..data_start);
+            start -= data_start;
+            data_start = 0;
+            let len = buffer.len();
+            buffer.resize(len + READ_SIZE, 0);
+            let read = read_full(source, &mut buffer[len..])?;
+            buffer.truncate(len + read);
+            end_of_file = read == 0;
+            continue;
+        }
+        if buffer.len() < start + block_size {
+            break;
+        }
+
+        let window = &buffer[start..start + block_size];
+        let weak = rolling.get_or_insert_with(|| Rolling::new(window)).digest();
+        if let Some(index) = signature.find(weak, window) {
+            if data_start < start {
+                operations.push(Operation::Data(buffer[data_start..start].to_vec()));
+            }
+            push_copy(
+                &mut operations,
+                index * block_size as u64,
+                block_size as u64,
+            );
+            start += block_size;
+            data_start = start;
+            rolling = None;
+            continue;
+        }
+
+        if start + block_size == buffer.len() {
+            // the last window, no byte is left to move forward
+            break;
+        }
+        rolling
+            .as_mut()
+            .unwrap()
+            .roll(buffer[start], buffer[start + block_size]);
+        start += 1;
+        if start - data_start >= MAX_DATA_SIZE {
+            operations.push(Operation::Data(buffer[data_start..start].to_vec()));
+            data_start = start;
+        }
+    }
+
+    // the shorter last block of the target can only be at the end of the source
+    if let Some((index, tail_strong, len)) = signature.tail {
+        let rest = &buffer[data_start..];
+        if rest.len() >= len && strong(&rest[rest.len() - len..]) == tail_strong {
+            let data_end = buffer.len() - len;
+            if data_start < data_end {
+                operations.push(Operation::Data(buffer[data_start..data_end].to_vec()));
+            }
+            push_copy(&mut operations, index * block_size as u64, len as u64);
+            return Ok(operations);
+        }
+    }
+    if data_start < buffer.len() {
+        operations.push(Operation::Data(buffer[data_start..].to_vec()));
+    }
+    Ok(operations)
+}
+
+/// Updates the target to the content of the source and only writes what changed.
+/// Returns the number of bytes written or `None` if the files are equal.
+///
+/// The target is patched in place, so it keeps its owner, extended attributes, ACLs and hard links.
+/// Blocks that moved towards the start are copied within the target. Blocks that moved towards
+/// the end would be overwritten before they are read, they are read from the source instead.
+pub(super) fn transfer(
+    source_file: &mut File,
+    target_file: &mut File,
+    limiter: &RateLimiter,
+) -> std::io::Result<Option<u64>> {
+    let source_len = source_file.metadata()?.len();
+    let target_len = target_file.metadata()?.len();
+    let signature = Signature::read(target_file, target_len)?;
+    source_file.seek(SeekFrom::Start(0))?;
+    let operations = diff(&mut std::io::BufReader::new(&mut *source_file), &signature)?;
+
+    let unchanged = |operations: &[Operation]| {
+        let mut offset = 0;
+        operations.iter().all(|operation| match operation {
+            Operation::Copy { from, len } => {
+                let unmoved = *from == offset;
+                offset += len;
+                unmoved
+            }
+            Operation::Data(_) => false,
+        })
+    };
+    if source_len == target_len && unchanged(&operations) {
+        return Ok(None);
+    }
+
+    let written = patch(source_file, target_file, &operations, limiter)?;
+    target_file.set_len(source_len)?;
+    target_file.flush()?;
+    Ok(Some(written))
+}
+
+/// Applies the operations to the target in order and returns the number of bytes written.
+///
+/// Everything before the current offset was written already, everything after it is unchanged.
+fn patch(
+    source_file: &mut File,
+    target_file: &mut File,
+    operations: &[Operation],
+    limiter: &RateLimiter,
+) -> std::io::Result<u64> {
+    let mut buffer = Vec::new();
+    let mut written = 0;
+    let mut offset = 0;
+    for operation in operations {
+        match operation {
+            Operation::Copy { from, len } if *from == offset => offset += len,
+            Operation::Copy { from, len } => {
+                let from_target = *from > offset;
+                let mut done = 0;
+                while done < *len {
+                    buffer.resize((len - done).min(READ_SIZE as u64) as usize, 0);
+                    // the source has the same content at the offset the block is written to
+                    let (input, start) = if from_target {
+                        (&mut *target_file, *from)
+                    } else {
+                        (&mut *source_file, offset)
+                    };
+                    input.seek(SeekFrom::Start(start + done))?;
+                    input.read_exact(&mut buffer)?;
+                    target_file.seek(SeekFrom::Start(offset + done))?;
+                    bandwidth::write_all(target_file, &buffer, limiter)?;
+                    done += buffer.len() as u64;
+                }
+                offset += len;
+                written += len;
+            }
+            Operation::Data(data) => {
+                target_file.seek(SeekFrom::Start(offset))?;
+                bandwidth::write_all(target_file, data, limiter)?;
+                offset += data.len() as u64;
+                written += data.len() as u64;
+            }
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature_of(target: &[u8]) -> Signature {
+        let path = std::env::temp_dir().join(format!(
+            "file-sync-rs-delta-{}-{}",
+            std::process::id(),
+            target.len()
+        ));
+        std::fs::write(&path, target).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let signature = Signature::read(&mut file, target.len() as u64).unwrap();
+        let _ = std::fs::remove_file(&path);
+        signature
+    }
+
+    fn apply(target: &[u8], operations: &[Operation]) -> Vec<u8> {
+        let mut result = Vec::new();
+        for operation in operations {
+            match operation {
+                Operation::Copy { from, len } => {
+                    result.extend_from_slice(&target[*from as usize..(*from + *len) as usize])
+                }
+                Operation::Data(data) => result.extend_from_slice(data),
+            }
+        }
+        result
+    }
+
+    fn content(len: usize) -> Vec<u8> {
+        // deterministic bytes without repeating blocks
+        let mut state: u32 = 12345;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn equal_files_are_copied_in_place() {
+        let target = content(100_000);
+        let operations = diff(&mut target.as_slice(), &signature_of(&target)).unwrap();
+        assert_eq!(
+            operations,
+            vec![Operation::Copy {
+                from: 0,
+                len: target.len() as u64
+            }]
+        );
+    }
+
+    #[test]
+    fn inserted_and_changed_bytes_are_data() {
+        let target = content(300_000);
+        let mut source = b"inserted".to_vec();
+        source.extend_from_slice(&target);
+        source[150_000] ^= 0xff;
+
+        let operations = diff(&mut source.as_slice(), &signature_of(&target)).unwrap();
+        assert_eq!(apply(&target, &operations), source);
+        let data: usize = operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Data(data) => data.len(),
+                Operation::Copy { .. } => 0,
+            })
+            .sum();
+        assert!(data < 2 * signature_of(&target).block_size + 8);
+    }
+
+    /// Transfers the source to a target with the given content and returns the written bytes.
+    fn transfer_files(name: &str, source: &[u8], target: &[u8]) -> Option<u64> {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sync-rs-delta-transfer-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("source"), source).unwrap();
+        std::fs::write(dir.join("target"), target).unwrap();
+        #[cfg(unix)]
+        std::fs::hard_link(dir.join("target"), dir.join("link")).unwrap();
+
+        let written = transfer(
+            &mut File::open(dir.join("source")).unwrap(),
+            &mut std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(dir.join("target"))
+                .unwrap(),
+            &RateLimiter::default(),
+        )
+        .unwrap();
+        assert!(std::fs::read(dir.join("target")).unwrap() == source);
+        // the file was patched, not replaced
+        #[cfg(unix)]
+        assert!(std::fs::read(dir.join("link")).unwrap() == source);
+        let _ = std::fs::remove_dir_all(dir);
+        written
+    }
+
+    #[test]
+    fn transfer_patches_the_target_in_place() {
+        let target = content(500_000);
+        assert_eq!(transfer_files("equal", &target, &target), None);
+
+        // changed bytes and an appended end
+        let mut source = target.clone();
+        source[1000] ^= 0xff;
+        source.extend_from_slice(b"appended");
+        let written = transfer_files("changed", &source, &target).unwrap();
+        assert!(written < 2 * signature_of(&target).block_size as u64 + 8);
+
+        // blocks move towards the end, they are read from the source
+        let mut source = content(10_000)
+            .iter()
+            .map(|byte| byte ^ 0x55)
+            .collect::<Vec<u8>>();
+        source.extend_from_slice(&target);
+        transfer_files("inserted", &source, &target).unwrap();
+
+        // blocks move towards the start and the file gets shorter
+        transfer_files("removed", &target[123_456..], &target).unwrap();
+
+        // the halves are swapped
+        let mut source = target[250_000..].to_vec();
+        source.extend_from_slice(&target[..250_000]);
+        transfer_files("swapped", &source, &target).unwrap();
+    }
+}
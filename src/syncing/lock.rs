@@ -0,0 +1,421 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, SystemTime};
+
+use super::DateTime;
+
+const LOCK_FILENAME: &str = "file_sync_rs.lock";
+
+/// A lock that was not refreshed for this long belongs to a sync that was killed
+/// or lost its connection to the target.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// How often the modification time of a held lock is updated.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who holds the lock, written into the lock file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub host: String,
+    pub pid: u32,
+    pub started: DateTime,
+}
+
+/// Another sync is running on the target.
+#[derive(Debug)]
+pub struct TargetLocked {
+    pub path: PathBuf,
+    /// `None` if the lock file could not be read
+    pub info: Option<LockInfo>,
+}
+
+impl std::fmt::Display for TargetLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.info {
+            Some(info) => write!(
+                f,
+                "The target is locked by a sync on {} (process {}) that started at {}.",
+                info.host,
+                info.pid,
+                info.started
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            )?,
+            None => write!(f, "The target is locked by another sync.")?,
+        }
+        write!(
+            f,
+            " If no sync is running, the lock is removed after {} minutes or can be deleted at {:?}.",
+            STALE_LOCK_AGE.as_secs() / 60,
+            self.path
+        )
+    }
+}
+
+impl std::error::Error for TargetLocked {}
+
+/// An exclusive lock on a target directory, released when dropped.
+/// While it is held, a background thread keeps it from becoming stale.
+#[derive(Debug)]
+pub struct TargetLock {
+    path: PathBuf,
+    info: LockInfo,
+    /// dropping the sender stops the refresh thread
+    refresher: Option<(mpsc::Sender<()>, std::thread::JoinHandle<()>)>,
+    /// why the refresh thread stopped, e.g. because another sync took over the lock
+    lost: Arc<Mutex<Option<anyhow::Error>>>,
+}
+
+impl TargetLock {
+    /// Creates the lock file in `target`. Stale locks are replaced,
+    /// if the target is locked by a running sync [`TargetLocked`] is returned.
+    pub fn acquire(target: &Path, started: DateTime) -> Result<Self> {
+        let path = target.join(LOCK_FILENAME);
+        let info = LockInfo {
+            host: hostname(),
+            pid: std::process::id(),
+            started,
+        };
+        let content = serde_json::to_string(&info).context("Converting to json failed.")?;
+
+        match create(&path, &content) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                let existing = read_lock(&path);
+                if !is_stale(&path, existing.as_ref()) {
+                    return Err(TargetLocked {
+                        path,
+                        info: existing,
+                    }
+                    .into());
+                }
+                take_over(target, &path, &content)?;
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to create lock file {:?}", path));
+            }
+        }
+
+        // another sync may have replaced the same stale lock at the same time
+        let holder = read_lock(&path);
+        if holder.as_ref() != Some(&info) {
+            return Err(TargetLocked { path, info: holder }.into());
+        }
+
+        let lost = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel();
+        let refresher = {
+            let path = path.clone();
+            let info = info.clone();
+            let lost = lost.clone();
+            std::thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(REFRESH_INTERVAL)
+                {
+                    if let Err(error) = refresh(&path, &info) {
+                        *lost.lock().unwrap() = Some(error);
+                        break;
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            path,
+            info,
+            refresher: Some((stop, refresher)),
+            lost,
+        })
+    }
+
+    /// Returns an error if the lock could not be kept, the sync has to stop then.
+    pub fn check(&self) -> Result<()> {
+        match self.lost.lock().unwrap().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TargetLock {
+    fn drop(&mut self) {
+        if let Some((stop, refresher)) = self.refresher.take() {
+            drop(stop);
+            let _ = refresher.join();
+        }
+        // a lock that was taken over belongs to the other sync
+        if read_lock(&self.path).as_ref() == Some(&self.info) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Creates the lock file, fails with [`std::io::ErrorKind::AlreadyExists`] if there is one.
+fn create(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(content.as_bytes()).inspect_err(|_| {
+        let _ = std::fs::remove_file(path);
+    })
+}
+
+/// Replaces the stale lock at `path`.
+///
+/// Only the sync that creates the takeover marker removes the lock, after checking again that
+/// it is stale. A second sync that found the same stale lock either fails to create the marker
+/// or finds the new lock, which is not stale.
+fn take_over(target: &Path, path: &Path, content: &str) -> Result<()> {
+    let marker = target.join(format!("{}.takeover", LOCK_FILENAME));
+    match create(&marker, "") {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+            // left behind by a sync that was killed while taking over
+            if is_stale(&marker, None) {
+                let _ = std::fs::remove_file(&marker);
+            }
+            return Err(TargetLocked {
+                path: path.to_path_buf(),
+                info: read_lock(path),
+            }
+            .into());
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to create lock file {:?}", marker));
+        }
+    }
+
+    let existing = read_lock(path);
+    let result = if !is_stale(path, existing.as_ref()) {
+        Err(TargetLocked {
+            path: path.to_path_buf(),
+            info: existing,
+        }
+        .into())
+    } else {
+        match std::fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).context("failed to remove stale lock file")
+            }
+            // a sync that did not need to take over may have created the lock in the meantime
+            _ => create(path, content).map_err(|error| match error.kind() {
+                std::io::ErrorKind::AlreadyExists => TargetLocked {
+                    path: path.to_path_buf(),
+                    info: read_lock(path),
+                }
+                .into(),
+                _ => anyhow::Error::new(error)
+                    .context(format!("failed to create lock file {:?}", path)),
+            }),
+        }
+    };
+    let _ = std::fs::remove_file(&marker);
+    result
+}
+
+/// Updates the modification time of the lock file, so it is not considered stale.
+fn refresh(path: &Path, info: &LockInfo) -> Result<()> {
+    if read_lock(path).as_ref() != Some(info) {
+        anyhow::bail!(
+            "the lock file {:?} was removed or taken over by another sync",
+            path
+        );
+    }
+    filetime::set_file_mtime(path, filetime::FileTime::now())
+        .with_context(|| format!("failed to refresh lock file {:?}", path))
+}
+
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// A lock is stale if it was not refreshed in time or its process on this host is not running.
+fn is_stale(path: &Path, info: Option<&LockInfo>) -> bool {
+    let age = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    if age.is_some_and(|age| age > STALE_LOCK_AGE) {
+        return true;
+    }
+    info.is_some_and(|info| info.host == hostname() && !is_process_running(info.pid))
+}
+
+#[cfg(target_os = "linux")]
+fn is_process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Without a way to check, only the age of the lock is used.
+#[cfg(not(target_os = "linux"))]
+fn is_process_running(_pid: u32) -> bool {
+    true
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| name.trim().to_owned())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("file-sync-rs-lock-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn make_stale(dir: &Path) {
+        let old = SystemTime::now() - STALE_LOCK_AGE - Duration::from_secs(60);
+        filetime::set_file_mtime(dir.join(LOCK_FILENAME), old.into()).unwrap();
+    }
+
+    #[test]
+    fn lock_is_exclusive_and_released() {
+        let dir = target("exclusive");
+        let lock = TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap();
+        lock.check().unwrap();
+        let error = TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap_err();
+        let locked = error.downcast_ref::<TargetLocked>().unwrap();
+        assert_eq!(locked.info.as_ref(), Some(&lock.info));
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILENAME).exists());
+        drop(TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stale_locks_are_detected() {
+        let dir = target("stale");
+        let path = dir.join(LOCK_FILENAME);
+        let running = LockInfo {
+            host: hostname(),
+            pid: std::process::id(),
+            started: chrono::offset::Utc::now(),
+        };
+        std::fs::write(&path, serde_json::to_string(&running).unwrap()).unwrap();
+        assert!(!is_stale(&path, Some(&running)));
+        // an unreadable lock is only stale by its age
+        assert!(!is_stale(&path, None));
+
+        #[cfg(target_os = "linux")]
+        {
+            let exited = LockInfo {
+                pid: u32::MAX,
+                ..running.clone()
+            };
+            assert!(is_stale(&path, Some(&exited)));
+        }
+        let other_host = LockInfo {
+            host: "another host".to_owned(),
+            pid: u32::MAX,
+            ..running.clone()
+        };
+        assert!(!is_stale(&path, Some(&other_host)));
+
+        make_stale(&dir);
+        assert!(is_stale(&path, Some(&running)));
+        assert!(is_stale(&path, None));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stale_lock_is_taken_over() {
+        let dir = target("takeover");
+        let first = TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap();
+        make_stale(&dir);
+        let second = TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap();
+        assert_eq!(
+            read_lock(&dir.join(LOCK_FILENAME)),
+            Some(second.info.clone())
+        );
+        assert!(!dir.join(format!("{}.takeover", LOCK_FILENAME)).exists());
+
+        // the first sync notices that it lost the lock and leaves the new one alone
+        assert!(refresh(&first.path, &first.info).is_err());
+        drop(first);
+        assert!(dir.join(LOCK_FILENAME).exists());
+        drop(second);
+        assert!(!dir.join(LOCK_FILENAME).exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn only_one_of_two_racing_takeovers_wins() {
+        let dir = target("race");
+        for _ in 0..50 {
+            std::fs::write(
+                dir.join(LOCK_FILENAME),
+                serde_json::to_string(&LockInfo {
+                    host: "another host".to_owned(),
+                    pid: 1,
+                    started: chrono::offset::Utc::now(),
+                })
+                .unwrap(),
+            )
+            .unwrap();
+            make_stale(&dir);
+
+            let barrier = std::sync::Barrier::new(2);
+            let locks: Vec<Result<TargetLock>> = std::thread::scope(|scope| {
+                let racers: Vec<_> = (0..2)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            barrier.wait();
+                            TargetLock::acquire(&dir, chrono::offset::Utc::now())
+                        })
+                    })
+                    .collect();
+                racers
+                    .into_iter()
+                    .map(|racer| racer.join().unwrap())
+                    .collect()
+            });
+            let winners: Vec<&TargetLock> =
+                locks.iter().filter_map(|lock| lock.as_ref().ok()).collect();
+            assert_eq!(winners.len(), 1);
+            assert_eq!(
+                read_lock(&dir.join(LOCK_FILENAME)).as_ref(),
+                Some(&winners[0].info)
+            );
+            for lock in &locks {
+                if let Err(error) = lock {
+                    assert!(
+                        error.downcast_ref::<TargetLocked>().is_some(),
+                        "{:?}",
+                        error
+                    );
+                }
+            }
+            drop(locks);
+            assert!(!dir.join(format!("{}.takeover", LOCK_FILENAME)).exists());
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refresh_keeps_the_lock_fresh() {
+        let dir = target("refresh");
+        let lock = TargetLock::acquire(&dir, chrono::offset::Utc::now()).unwrap();
+        make_stale(&dir);
+        refresh(&lock.path, &lock.info).unwrap();
+        assert!(!is_stale(&lock.path, Some(&lock.info)));
+        drop(lock);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
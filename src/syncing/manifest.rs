@@ -0,0 +1,283 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const MANIFEST_FILENAME: &str = "file_sync_rs.manifest";
+/// The manifest of the running sync, it replaces the manifest once the sync is done.
+const NEW_MANIFEST_FILENAME: &str = "file_sync_rs.manifest.new";
+
+/// What identifies a source file without reading it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// device and inode, which stay the same when the file is renamed or moved
+    inode: Option<(u64, u64)>,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    pub fn new(metadata: &std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            Some((metadata.dev(), metadata.ino()))
+        };
+        #[cfg(not(unix))]
+        let inode = None;
+
+        Self {
+            inode,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Whether the content is most likely the same, the inode is not compared.
+    fn same_content(&self, other: &Self) -> bool {
+        self.len == other.len && self.modified.is_some() && self.modified == other.modified
+    }
+}
+
+/// A synced file, every line of the manifest is one.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    source: PathBuf,
+    target: PathBuf,
+    fingerprint: Fingerprint,
+}
+
+/// The files of the last completed full sync, read from the target.
+///
+/// Finds the files of the target that were moved or renamed in the sources,
+/// so they can be renamed in the target instead of being copied again.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    /// source and target by the device and inode of the source
+    by_inode: HashMap<(u64, u64), (PathBuf, PathBuf, Fingerprint)>,
+    /// source and target by file name, for moves between filesystems that change the inode
+    by_name: HashMap<OsString, Vec<(PathBuf, PathBuf, Fingerprint)>>,
+}
+
+impl Manifest {
+    /// Reads the manifest of the `target`. Returns `None` if there is none.
+    pub fn read(target: &Path) -> Result<Option<Self>> {
+        let path = target.join(MANIFEST_FILENAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open manifest {:?}", path))?;
+
+        let mut manifest = Self::default();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("failed to read manifest {:?}", path))?;
+            let Ok(entry) = serde_json::from_str::<Entry>(&line) else {
+                continue;
+            };
+            if let Some(inode) = entry.fingerprint.inode {
+                manifest.by_inode.insert(
+                    inode,
+                    (
+                        entry.source.clone(),
+                        entry.target.clone(),
+                        entry.fingerprint.clone(),
+                    ),
+                );
+            }
+            if let Some(name) = entry.source.file_name() {
+                manifest.by_name.entry(name.to_owned()).or_default().push((
+                    entry.source,
+                    entry.target,
+                    entry.fingerprint,
+                ));
+            }
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Returns the target of the file the `source` was moved or renamed from.
+    ///
+    /// It has to have the same inode or, if there is only one, the same name, and the same
+    /// size and modification time. Its source must not exist anymore and its target has to.
+    /// Each target is only returned once.
+    pub fn take_moved(&mut self, source: &Path, fingerprint: &Fingerprint) -> Option<PathBuf> {
+        let vanished = |(old_source, old_target, old): &(PathBuf, PathBuf, Fingerprint)| {
+            old_source != source
+                && old.same_content(fingerprint)
+                && std::fs::symlink_metadata(old_source).is_err()
+                && old_target.is_file()
+        };
+
+        let by_inode = fingerprint
+            .inode
+            .and_then(|inode| self.by_inode.get(&inode))
+            .filter(|entry| vanished(entry))
+            .map(|(_, target, _)| target.clone());
+        let target =
+            by_inode.or_else(|| match self.by_name.get(source.file_name()?)?.as_slice() {
+                [entry] if vanished(entry) => Some(entry.1.clone()),
+                _ => None,
+            })?;
+
+        self.by_inode
+            .retain(|_, (_, old_target, _)| *old_target != target);
+        for entries in self.by_name.values_mut() {
+            entries.retain(|(_, old_target, _)| *old_target != target);
+        }
+        Some(target)
+    }
+}
+
+/// Writes the synced files of a full sync to a new manifest in the target.
+#[derive(Debug)]
+pub struct ManifestWriter {
+    target: PathBuf,
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+impl ManifestWriter {
+    pub fn create(target: &Path) -> Result<Self> {
+        let path = target.join(NEW_MANIFEST_FILENAME);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("failed to create manifest {:?}", path))?;
+        Ok(Self {
+            target: target.to_path_buf(),
+            file: std::io::BufWriter::new(file),
+        })
+    }
+
+    /// Records the synced file with the current fingerprint of its source.
+    pub fn record(&mut self, source: &Path, target: &Path) -> Result<()> {
+        let Ok(metadata) = std::fs::metadata(source) else {
+            return Ok(());
+        };
+        // paths that are not valid unicode can not be stored, they are copied when moved
+        let Ok(line) = serde_json::to_string(&Entry {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            fingerprint: Fingerprint::new(&metadata),
+        }) else {
+            return Ok(());
+        };
+        writeln!(self.file, "{}", line).context("failed to write manifest")
+    }
+
+    /// Replaces the manifest of the last sync, once every file is recorded.
+    pub fn finish(mut self) -> Result<()> {
+        self.file.flush().context("failed to write manifest")?;
+        std::fs::rename(
+            self.target.join(NEW_MANIFEST_FILENAME),
+            self.target.join(MANIFEST_FILENAME),
+        )
+        .context("failed to replace manifest")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sources and their copies in the target, recorded in the manifest.
+    fn fixture(name: &str, files: &[&str]) -> (PathBuf, PathBuf, Manifest) {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sync-rs-manifest-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        std::fs::create_dir_all(&target).unwrap();
+        let mut writer = ManifestWriter::create(&target).unwrap();
+        for file in files {
+            for root in [&source, &target] {
+                std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+                std::fs::write(root.join(file), file.as_bytes()).unwrap();
+            }
+            writer
+                .record(&source.join(file), &target.join(file))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let manifest = Manifest::read(&target).unwrap().unwrap();
+        (source, target, manifest)
+    }
+
+    fn fingerprint(path: &Path) -> Fingerprint {
+        Fingerprint::new(&std::fs::metadata(path).unwrap())
+    }
+
+    #[test]
+    fn moved_files_are_found_by_inode() {
+        let (source, target, mut manifest) = fixture("inode", &["a/file", "b/other"]);
+        std::fs::create_dir_all(source.join("c")).unwrap();
+        std::fs::rename(source.join("a/file"), source.join("c/renamed")).unwrap();
+
+        let moved = source.join("c/renamed");
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                manifest.take_moved(&moved, &fingerprint(&moved)),
+                Some(target.join("a/file"))
+            );
+            // each target is only returned once
+            assert_eq!(manifest.take_moved(&moved, &fingerprint(&moved)), None);
+        }
+        // files that still exist in the source were not moved
+        let other = source.join("b/other");
+        assert_eq!(manifest.take_moved(&other, &fingerprint(&other)), None);
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn moved_files_are_found_by_unique_name() {
+        let (source, target, mut manifest) = fixture(
+            "name",
+            &["a/file", "a/twice", "b/twice", "a/changed", "a/gone"],
+        );
+        // a copy to another filesystem has a new inode, but the same modification time
+        let move_file = |from: &str, to: &str| {
+            let modified = filetime::FileTime::from_last_modification_time(
+                &std::fs::metadata(source.join(from)).unwrap(),
+            );
+            std::fs::create_dir_all(source.join(to).parent().unwrap()).unwrap();
+            std::fs::copy(source.join(from), source.join(to)).unwrap();
+            std::fs::remove_file(source.join(from)).unwrap();
+            filetime::set_file_mtime(source.join(to), modified).unwrap();
+            source.join(to)
+        };
+
+        let moved = move_file("a/file", "c/file");
+        assert_eq!(
+            manifest.take_moved(&moved, &fingerprint(&moved)),
+            Some(target.join("a/file"))
+        );
+        // the name is not unique
+        let moved = move_file("a/twice", "c/twice");
+        assert_eq!(manifest.take_moved(&moved, &fingerprint(&moved)), None);
+        // the content changed
+        let moved = move_file("a/changed", "c/changed");
+        std::fs::write(&moved, b"other content").unwrap();
+        assert_eq!(manifest.take_moved(&moved, &fingerprint(&moved)), None);
+        // the target is gone
+        let moved = move_file("a/gone", "c/gone");
+        std::fs::remove_file(target.join("a/gone")).unwrap();
+        assert_eq!(manifest.take_moved(&moved, &fingerprint(&moved)), None);
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn no_manifest_without_a_full_sync() {
+        let dir =
+            std::env::temp_dir().join(format!("file-sync-rs-manifest-none-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(Manifest::read(&dir).unwrap().is_none());
+        // an unfinished manifest does not replace the last one
+        drop(ManifestWriter::create(&dir).unwrap());
+        assert!(Manifest::read(&dir).unwrap().is_none());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// The setting key for [`super::options::SyncOptions::preserve_metadata`].
+pub const PRESERVE_METADATA_SETTING: &str = "preserve_metadata";
+/// The setting key for [`super::options::SyncOptions::preserve_acls`].
+pub const PRESERVE_ACLS_SETTING: &str = "preserve_acls";
+
+/// The extended attributes POSIX ACLs are stored in, the default ACL only exists on directories.
+#[cfg(target_os = "linux")]
+const ACL_XATTRS: [&std::ffi::CStr; 2] = [c"system.posix_acl_access", c"system.posix_acl_default"];
+
+/// Gives the target the owner, exact mode and extended attributes of the source.
+///
+/// The owner is only changed when running as root. Whatever the target filesystem
+/// can not store, e.g. on FAT or exFAT, is left as it is.
+/// Returns `false` if the target did not permit to change some of it.
+#[cfg(unix)]
+pub(super) fn preserve(source: &Path, target: &Path) -> Result<bool> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let metadata = std::fs::symlink_metadata(source)
+        .with_context(|| format!("Could not query metadata of {:?}", source))?;
+
+    let mut permitted = true;
+    // before the mode, changing the owner clears the setuid and setgid bits
+    if unsafe { libc::geteuid() } == 0 {
+        permitted &= ignore_unsupported(std::os::unix::fs::lchown(
+            target,
+            Some(metadata.uid()),
+            Some(metadata.gid()),
+        ))
+        .with_context(|| format!("Could not change the owner of {:?}", target))?;
+    }
+    permitted &= ignore_unsupported(std::fs::set_permissions(
+        target,
+        std::fs::Permissions::from_mode(metadata.mode() & 0o7777),
+    ))
+    .with_context(|| format!("Could not set permissions for {:?}", target))?;
+
+    Ok(copy_xattrs(source, target)? && permitted)
+}
+
+#[cfg(not(unix))]
+pub(super) fn preserve(_source: &Path, _target: &Path) -> Result<bool> {
+    Ok(true)
+}
+
+/// Copies the POSIX ACLs of the source and removes the ones it does not have.
+/// Returns `false` if the source has ACLs the target filesystem can not store.
+#[cfg(target_os = "linux")]
+pub(super) fn preserve_acls(source: &Path, target: &Path) -> Result<bool> {
+    let missing = |error: &std::io::Error| error.raw_os_error() == Some(libc::ENODATA);
+    let not_supported = |error: &std::io::Error| {
+        error.raw_os_error() == Some(libc::ENOTSUP)
+            || error.raw_os_error() == Some(libc::EOPNOTSUPP)
+    };
+
+    let mut supported = true;
+    for name in ACL_XATTRS {
+        let value = match xattr::get(source, name) {
+            Ok(value) => value,
+            Err(error) if missing(&error) || not_supported(&error) => {
+                match xattr::remove(target, name) {
+                    Err(error) if !missing(&error) && !not_supported(&error) => {
+                        return Err(error).with_context(|| {
+                            format!("Could not remove ACL {:?} of {:?}", name, target)
+                        });
+                    }
+                    _ => continue,
+                }
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Could not read ACL {:?} of {:?}", name, source));
+            }
+        };
+        if xattr::get(target, name).is_ok_and(|current| current == value) {
+            continue;
+        }
+        match xattr::set(target, name, &value) {
+            Ok(()) => {}
+            Err(error) if not_supported(&error) => supported = false,
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Could not set ACL {:?} of {:?}", name, target));
+            }
+        }
+    }
+    Ok(supported)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn preserve_acls(_source: &Path, _target: &Path) -> Result<bool> {
+    Ok(true)
+}
+
+/// Treats errors of filesystems that do not support an operation as success.
+/// Returns `false` instead of the error if the operation was not permitted,
+/// e.g. because FAT can not store the owner or the namespace of an attribute is protected.
+#[cfg(unix)]
+fn ignore_unsupported(result: std::io::Result<()>) -> std::io::Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(error) if is_unsupported(&error) => Ok(true),
+        Err(error) if error.raw_os_error() == Some(libc::EPERM) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(unix)]
+fn is_unsupported(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(libc::ENOTSUP)
+        || error.raw_os_error() == Some(libc::EOPNOTSUPP)
+        || error.raw_os_error() == Some(libc::ENOSYS)
+}
+
+/// Copies the extended attributes and removes the ones the source does not have.
+/// Attributes in the `system` namespace, like ACLs, are not copied.
+/// Returns `false` if the target did not permit to change some of them.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(source: &Path, target: &Path) -> Result<bool> {
+    let copied = |name: &std::ffi::CStr| !name.to_bytes().starts_with(b"system.");
+
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        Err(error) if is_unsupported(&error) => return Ok(true),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Could not read extended attributes of {:?}", source));
+        }
+    };
+    let target_names = match xattr::list(target) {
+        Ok(names) => names,
+        // the target can not store any
+        Err(error) if is_unsupported(&error) => return Ok(true),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Could not read extended attributes of {:?}", target));
+        }
+    };
+
+    let mut permitted = true;
+    for name in target_names
+        .iter()
+        .filter(|name| copied(name) && !names.contains(name))
+    {
+        permitted &= ignore_unsupported(xattr::remove(target, name)).with_context(|| {
+            format!(
+                "Could not remove extended attribute {:?} of {:?}",
+                name, target
+            )
+        })?;
+    }
+    for name in names.iter().filter(|name| copied(name)) {
+        let value = xattr::get(source, name).with_context(|| {
+            format!(
+                "Could not read extended attribute {:?} of {:?}",
+                name, source
+            )
+        })?;
+        if xattr::get(target, name).is_ok_and(|current| current == value) {
+            continue;
+        }
+        permitted &= ignore_unsupported(xattr::set(target, name, &value)).with_context(|| {
+            format!(
+                "Could not set extended attribute {:?} of {:?}",
+                name, target
+            )
+        })?;
+    }
+    Ok(permitted)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn copy_xattrs(_source: &Path, _target: &Path) -> Result<bool> {
+    Ok(true)
+}
+
+/// Extended attributes of paths, symlinks are not followed.
+#[cfg(target_os = "linux")]
+pub(super) mod xattr {
+    use std::ffi::{CStr, CString};
+    use std::io::{Error, Result};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    fn c_path(path: &Path) -> Result<CString> {
+        CString::new(path.as_os_str().as_bytes()).map_err(Error::other)
+    }
+
+    /// Calls `f` with a buffer of the size `f` returns for an empty buffer.
+    fn read_sized(f: impl Fn(*mut libc::c_void, usize) -> libc::ssize_t) -> Result<Vec<u8>> {
+        let size = f(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(Error::last_os_error());
+        }
+        let mut buffer = vec![0_u8; size as usize];
+        let size = f(buffer.as_mut_ptr().cast(), buffer.len());
+        if size < 0 {
+            return Err(Error::last_os_error());
+        }
+        buffer.truncate(size as usize);
+        Ok(buffer)
+    }
+
+    pub fn list(path: &Path) -> Result<Vec<CString>> {
+        let path = c_path(path)?;
+        // the list is written to the buffer, at most its length
+        let names = read_sized(|buffer, size| unsafe {
+            libc::llistxattr(path.as_ptr(), buffer.cast(), size)
+        })?;
+        Ok(names
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| CString::new(name).ok())
+            .collect())
+    }
+
+    pub fn get(path: &Path, name: &CStr) -> Result<Vec<u8>> {
+        let path = c_path(path)?;
+        // the value is written to the buffer, at most its length
+        read_sized(|buffer, size| unsafe {
+            libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer, size)
+        })
+    }
+
+    pub fn set(path: &Path, name: &CStr, value: &[u8]) -> Result<()> {
+        let path = c_path(path)?;
+        // only reads the value
+        let result = unsafe {
+            libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn remove(path: &Path, name: &CStr) -> Result<()> {
+        let path = c_path(path)?;
+        if unsafe { libc::lremovexattr(path.as_ptr(), name.as_ptr()) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_unsupported_operations_are_ignored() {
+        let error = |code| Err(std::io::Error::from_raw_os_error(code));
+        assert!(ignore_unsupported(Ok(())).unwrap());
+        assert!(ignore_unsupported(error(libc::ENOTSUP)).unwrap());
+        assert!(ignore_unsupported(error(libc::EOPNOTSUPP)).unwrap());
+        assert!(ignore_unsupported(error(libc::ENOSYS)).unwrap());
+        assert!(!ignore_unsupported(error(libc::EPERM)).unwrap());
+        assert!(ignore_unsupported(error(libc::EACCES)).is_err());
+        assert!(ignore_unsupported(error(libc::EIO)).is_err());
+    }
+
+    #[test]
+    fn preserve_mode_and_xattrs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("file-sync-rs-metadata-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        std::fs::write(&source, "content").unwrap();
+        std::fs::write(&target, "content").unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        #[cfg(target_os = "linux")]
+        let xattrs_supported = xattr::set(&source, c"user.kept", b"value").is_ok()
+            && xattr::set(&target, c"user.removed", b"value").is_ok();
+
+        assert!(preserve(&source, &target).unwrap());
+        assert_eq!(
+            std::fs::metadata(&target).unwrap().permissions().mode() & 0o7777,
+            0o640
+        );
+        #[cfg(target_os = "linux")]
+        if xattrs_supported {
+            assert_eq!(xattr::get(&target, c"user.kept").unwrap(), b"value");
+            assert!(xattr::get(&target, c"user.removed").is_err());
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use super::backend::{EntryKind, TargetBackend};
+
+/// The setting key for [`super::options::SyncOptions::mirror`].
+pub const MIRROR_SETTING: &str = "mirror";
+
+/// Removes everything from `target` that does not exist in `source` anymore.
+///
+/// The content of a directory is removed before the directory. The source and target
+/// of every removed file and directory are added to `removed`, the first error stops the removal.
+pub(super) fn remove_vanished(
+    backend: &dyn TargetBackend,
+    source: &Path,
+    target: &Path,
+    removed: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<()> {
+    let entries = match backend.list(target) {
+        Ok(entries) => entries,
+        // not created, e.g. because it would have been empty
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Could not read directory {:?}", target));
+        }
+    };
+    for entry in entries {
+        let entry_source = source.join(&entry.name);
+        let entry_target = target.join(&entry.name);
+        let is_dir = entry.kind == EntryKind::Directory;
+        match std::fs::symlink_metadata(&entry_source) {
+            Ok(metadata) => {
+                if is_dir && metadata.is_dir() {
+                    remove_vanished(backend, &entry_source, &entry_target, removed)?;
+                }
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                remove(backend, &entry_source, &entry_target, is_dir, removed)?;
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Could not query metadata of {:?}", entry_source));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the target, the content of directories first.
+fn remove(
+    backend: &dyn TargetBackend,
+    source: &Path,
+    target: &Path,
+    is_dir: bool,
+    removed: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<()> {
+    if is_dir {
+        // its content can not be removed from a read only directory
+        if backend.is_local() {
+            let mut permissions = std::fs::metadata(target)
+                .with_context(|| format!("Could not query metadata of {:?}", target))?
+                .permissions();
+            if permissions.readonly() {
+                #[allow(clippy::permissions_set_readonly_false)]
+                permissions.set_readonly(false);
+                std::fs::set_permissions(target, permissions)
+                    .with_context(|| format!("Could not set permissions for {:?}", target))?;
+            }
+        }
+
+        for entry in backend
+            .list(target)
+            .with_context(|| format!("Could not read directory {:?}", target))?
+        {
+            remove(
+                backend,
+                &source.join(&entry.name),
+                &target.join(&entry.name),
+                entry.kind == EntryKind::Directory,
+                removed,
+            )?;
+        }
+        backend
+            .remove_dir(target)
+            .with_context(|| format!("Could not remove directory {:?}", target))?;
+    } else {
+        backend
+            .remove_file(target)
+            .with_context(|| format!("Could not remove file {:?}", target))?;
+    }
+    removed.push((source.to_path_buf(), target.to_path_buf()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syncing::backend::local::LocalBackend;
+
+    #[test]
+    fn vanished_entries_are_removed() {
+        let dir = std::env::temp_dir().join(format!("file-sync-rs-mirror-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        for root in [&source, &target] {
+            std::fs::create_dir_all(root.join("kept").join("inner")).unwrap();
+            std::fs::write(root.join("file"), b"").unwrap();
+            std::fs::write(root.join("kept").join("inner").join("file"), b"").unwrap();
+        }
+        std::fs::write(target.join("vanished"), b"").unwrap();
+        std::fs::write(target.join("kept").join("vanished"), b"").unwrap();
+        std::fs::create_dir_all(target.join("gone").join("deep")).unwrap();
+        std::fs::write(target.join("gone").join("deep").join("file"), b"").unwrap();
+        // a directory that became a file in the source is left to the file job
+        std::fs::create_dir_all(target.join("became file")).unwrap();
+        std::fs::write(source.join("became file"), b"").unwrap();
+
+        let mut removed = Vec::new();
+        remove_vanished(&LocalBackend, &source, &target, &mut removed).unwrap();
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![
+                (source.join("gone"), target.join("gone")),
+                (
+                    source.join("gone").join("deep"),
+                    target.join("gone").join("deep")
+                ),
+                (
+                    source.join("gone").join("deep").join("file"),
+                    target.join("gone").join("deep").join("file")
+                ),
+                (
+                    source.join("kept").join("vanished"),
+                    target.join("kept").join("vanished")
+                ),
+                (source.join("vanished"), target.join("vanished")),
+            ]
+        );
+        assert!(!target.join("gone").exists());
+        assert!(target.join("kept").join("inner").join("file").exists());
+        assert!(target.join("became file").is_dir());
+
+        // the content of a directory is removed before the directory
+        std::fs::create_dir_all(target.join("gone").join("deep")).unwrap();
+        let mut removed = Vec::new();
+        remove_vanished(&LocalBackend, &source, &target, &mut removed).unwrap();
+        assert_eq!(
+            removed
+                .into_iter()
+                .map(|(_, target)| target)
+                .collect::<Vec<_>>(),
+            vec![target.join("gone").join("deep"), target.join("gone")]
+        );
+
+        // a target that was not created has nothing to remove
+        let mut removed = Vec::new();
+        remove_vanished(&LocalBackend, &source, &dir.join("missing"), &mut removed).unwrap();
+        assert!(removed.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -2,10 +2,29 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+pub mod backend;
+pub mod bandwidth;
+pub mod checkpoint;
+mod copy;
+pub mod delta;
+pub mod lock;
+mod manifest;
+pub mod metadata;
+pub mod mirror;
+pub mod mount;
+pub mod options;
+pub mod report;
+pub mod space;
+mod sparse;
 pub mod sync;
+mod walk;
+pub mod watch;
 
 const LAST_SYNC_FILENAME: &str = "last_file_sync_rs.json";
 
+/// The setting key under which the id of the configured target is stored.
+pub const TARGET_ID_SETTING: &str = "target_id";
+
 /// Takes a path to a target directory.
 /// Will look if a file with stats of the last sync exist and returns the data as [`LastSync`].
 pub fn get_last_sync(path: PathBuf) -> Result<Option<LastSync>> {
@@ -50,16 +69,29 @@ pub struct LastSync {
     sources: Vec<String>,
     target: String,
     version: String,
+    /// unique id of the target, created when the target is synced to for the first time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_id: Option<String>,
+    /// filesystem UUID of the target drive, used to recognise it when it is mounted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume_id: Option<String>,
 }
 
 impl LastSync {
-    pub fn new(timestamp: DateTime, sources: Vec<PathBuf>, target: PathBuf) -> Self {
+    pub fn new(
+        timestamp: DateTime,
+        sources: Vec<PathBuf>,
+        target: PathBuf,
+        target_id: String,
+    ) -> Self {
         Self {
+            target_id: Some(target_id),
             timestamp,
             sources: sources
                 .iter()
                 .map(|source| source.to_str().unwrap().to_owned())
                 .collect(),
+            volume_id: mount::volume_id_of(&target),
             target: target.to_str().unwrap().to_owned(),
             version: String::from("0.0.1"),
         }
@@ -83,4 +115,39 @@ impl LastSync {
     pub fn version(&self) -> &String {
         &self.version
     }
+
+    pub fn volume_id(&self) -> Option<&String> {
+        self.volume_id.as_ref()
+    }
+
+    pub fn target_id(&self) -> Option<&String> {
+        self.target_id.as_ref()
+    }
+}
+
+/// The target directory does not contain the id stored for the configured target,
+/// e.g. because a different drive is mounted at the target path.
+#[derive(Debug)]
+pub struct TargetMismatch {
+    pub expected: String,
+    pub found: Option<String>,
+}
+
+impl std::fmt::Display for TargetMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.found {
+            Some(found) => write!(
+                f,
+                "The target directory belongs to a different target (id {} instead of {}).",
+                found, self.expected
+            ),
+            None => write!(
+                f,
+                "The target directory was never synced to, expected target {}.",
+                self.expected
+            ),
+        }
+    }
 }
+
+impl std::error::Error for TargetMismatch {}
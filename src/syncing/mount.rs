@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The setting key that enables syncing when the target drive is mounted.
+pub const SYNC_ON_MOUNT_SETTING: &str = "sync_on_mount";
+/// The setting key for what happens after a sync that was started by mounting the target drive.
+pub const AFTER_MOUNT_SYNC_SETTING: &str = "after_mount_sync";
+
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+const UUID_DIR: &str = "/dev/disk/by-uuid";
+
+/// A mounted filesystem as listed in `/proc/self/mountinfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    /// Unique id of the mount, a new mount of the same device gets a new id.
+    pub id: u64,
+    pub mount_point: PathBuf,
+    /// The mounted device, e.g. `/dev/sdb1`.
+    pub source: String,
+}
+
+/// What happens after a sync that was started by mounting the target drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AfterMountSync {
+    #[default]
+    Nothing,
+    /// Tell the user that the drive can be removed.
+    Notify,
+    /// Unmount the drive.
+    Unmount,
+}
+
+impl AfterMountSync {
+    pub const ALL: [AfterMountSync; 3] = [Self::Nothing, Self::Notify, Self::Unmount];
+}
+
+impl From<&str> for AfterMountSync {
+    fn from(value: &str) -> Self {
+        match value {
+            "notify" => Self::Notify,
+            "unmount" => Self::Unmount,
+            _ => Self::Nothing,
+        }
+    }
+}
+
+impl From<&AfterMountSync> for &str {
+    fn from(value: &AfterMountSync) -> Self {
+        match value {
+            AfterMountSync::Nothing => "nothing",
+            AfterMountSync::Notify => "notify",
+            AfterMountSync::Unmount => "unmount",
+        }
+    }
+}
+
+/// Reads the currently mounted filesystems. Only supported on Linux.
+pub fn read_mounts() -> Result<Vec<Mount>> {
+    let content = std::fs::read_to_string(MOUNTINFO_PATH)
+        .with_context(|| format!("failed to read {}", MOUNTINFO_PATH))?;
+    Ok(parse_mountinfo(&content))
+}
+
+/// Parses lines like `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`.
+fn parse_mountinfo(content: &str) -> Vec<Mount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (fields, filesystem) = line.split_once(" - ")?;
+            let mut fields = fields.split(' ');
+            let id = fields.next()?.parse().ok()?;
+            let mount_point = fields.nth(3)?;
+            let source = filesystem.split(' ').nth(1)?;
+            Some(Mount {
+                id,
+                mount_point: PathBuf::from(unescape(mount_point)),
+                source: unescape(source),
+            })
+        })
+        .collect()
+}
+
+/// Spaces, tabs, newlines and backslashes are escaped as octal sequences like `\040`.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(code) = value
+                .get(i + 1..i + 4)
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            result.push(code);
+            i += 4;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Returns the mount that contains `path`.
+pub fn mount_of<'a>(path: &Path, mounts: &'a [Mount]) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        // later mounts hide earlier ones on the same mount point
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// Returns the filesystem UUID of the mounted device, if it has one.
+pub fn volume_id(mount: &Mount) -> Option<String> {
+    let device = std::fs::canonicalize(&mount.source).ok()?;
+    std::fs::read_dir(UUID_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| std::fs::canonicalize(entry.path()).is_ok_and(|path| path == device))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+/// Returns the filesystem UUID of the drive `path` is located on.
+pub fn volume_id_of(path: &Path) -> Option<String> {
+    let mounts = read_mounts().ok()?;
+    volume_id(mount_of(path, &mounts)?)
+}
+
+/// Unmounts the drive with udisks, which does not require root for removable drives,
+/// and falls back to `umount`.
+pub fn unmount(mount: &Mount) -> Result<()> {
+    let udisks = std::process::Command::new("udisksctl")
+        .args(["unmount", "--block-device", &mount.source])
+        .output();
+    if udisks.is_ok_and(|output| output.status.success()) {
+        return Ok(());
+    }
+
+    let output = std::process::Command::new("umount")
+        .arg(&mount.mount_point)
+        .output()
+        .context("failed to run umount")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "failed to unmount {:?}: {}",
+            mount.mount_point,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Polls the mounted filesystems and reports when the target drive is mounted.
+pub struct MountWatcher {
+    known: BTreeSet<u64>,
+}
+
+impl MountWatcher {
+    /// Drives that are already mounted are not reported.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            known: read_mounts()?.iter().map(|mount| mount.id).collect(),
+        })
+    }
+
+    /// Returns the mount of `target` if it was mounted since the last poll and is the drive
+    /// the target was synced to before. Drives are recognised by the volume id in the [`super::LastSync`].
+    pub fn poll(&mut self, target: &Path) -> Result<Option<Mount>> {
+        let mounts = read_mounts()?;
+        let known = std::mem::replace(
+            &mut self.known,
+            mounts.iter().map(|mount| mount.id).collect(),
+        );
+
+        let Some(mount) = mount_of(target, &mounts) else {
+            return Ok(None);
+        };
+        if known.contains(&mount.id) || !target.is_dir() {
+            return Ok(None);
+        }
+
+        let Some(last_sync) = super::get_last_sync(target.to_path_buf())? else {
+            return Ok(None);
+        };
+        match (last_sync.volume_id(), volume_id(mount)) {
+            (Some(expected), Some(actual)) if *expected == actual => Ok(Some(mount.clone())),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+36 22 8:17 / /media/user/My\\040Backup rw,nosuid shared:5 master:1 - vfat /dev/sdb1 rw,uid=1000
+37 22 0:5 / /proc rw - proc proc rw
+not a mount
+41 22 8:17 / /media/user/My\\040Backup rw - ext4 /dev/sdc1 rw
+";
+
+    #[test]
+    fn parse_mountinfo_lines() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(
+            mounts,
+            vec![
+                Mount {
+                    id: 22,
+                    mount_point: "/".into(),
+                    source: "/dev/sda2".to_owned(),
+                },
+                Mount {
+                    id: 36,
+                    mount_point: "/media/user/My Backup".into(),
+                    source: "/dev/sdb1".to_owned(),
+                },
+                Mount {
+                    id: 37,
+                    mount_point: "/proc".into(),
+                    source: "proc".to_owned(),
+                },
+                Mount {
+                    id: 41,
+                    mount_point: "/media/user/My Backup".into(),
+                    source: "/dev/sdc1".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unescape_octal_sequences() {
+        assert_eq!(unescape("a\\040b\\011c\\012d\\134e"), "a b\tc\nd\\e");
+        // incomplete or invalid sequences are kept
+        assert_eq!(unescape("a\\04"), "a\\04");
+        assert_eq!(unescape("a\\999b"), "a\\999b");
+        assert_eq!(unescape("ä\\040ö"), "ä ö");
+    }
+
+    #[test]
+    fn mount_of_finds_the_innermost_and_latest_mount() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(
+            mount_of(Path::new("/media/user/My Backup/files"), &mounts).map(|mount| mount.id),
+            Some(41)
+        );
+        assert_eq!(
+            mount_of(Path::new("/media/user/My Backups"), &mounts).map(|mount| mount.id),
+            Some(22)
+        );
+        assert_eq!(mount_of(Path::new("relative"), &mounts), None);
+    }
+}
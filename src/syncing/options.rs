@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+
+use super::bandwidth::{self, LimitWindow};
+use super::delta::DELTA_TRANSFER_SETTING;
+use super::metadata::{PRESERVE_ACLS_SETTING, PRESERVE_METADATA_SETTING};
+use super::mirror::MIRROR_SETTING;
+use super::space::{SPACE_CHECK_SETTING, SpaceCheck};
+use crate::db::AppSettings;
+
+/// The setting key for [`SyncOptions::workers`].
+pub const WORKERS_SETTING: &str = "workers";
+/// The setting key for [`SyncOptions::large_file_workers`].
+pub const LARGE_FILE_WORKERS_SETTING: &str = "large_file_workers";
+/// The setting key for [`SyncOptions::large_file_threshold`] in MiB.
+pub const LARGE_FILE_THRESHOLD_SETTING: &str = "large_file_threshold_mib";
+/// The setting key for [`SyncOptions::low_io_priority`].
+pub const LOW_IO_PRIORITY_SETTING: &str = "low_io_priority";
+/// The setting key for [`SyncOptions::skip_empty_dirs`].
+pub const SKIP_EMPTY_DIRS_SETTING: &str = "skip_empty_dirs";
+
+/// How a [`super::sync::Syncer`] works, configured in the settings.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// how many directories and small files are synced at once
+    pub workers: usize,
+    /// how many large files are synced at once, in addition to the other workers
+    pub large_file_workers: usize,
+    /// files with at least this many bytes are large
+    pub large_file_threshold: u64,
+    /// lets the workers only use the disk when no other program does, so the machine stays usable
+    pub low_io_priority: bool,
+    /// only writes the changed parts of large files on local targets,
+    /// see [`super::delta::TransferStrategy::Delta`]
+    pub delta_transfer: bool,
+    /// bytes per second all workers may write together, `None` is unlimited
+    pub bandwidth_limit: Option<u64>,
+    /// limits by time of day that replace the bandwidth limit
+    pub bandwidth_schedule: Vec<LimitWindow>,
+    /// whether the free space of the target is checked before anything is written
+    pub space_check: SpaceCheck,
+    /// gives the targets the owner, exact mode and extended attributes of the sources
+    pub preserve_metadata: bool,
+    /// copies the POSIX ACLs of files and directories, only on Linux
+    pub preserve_acls: bool,
+    /// only creates the directories of the target that files are written to
+    pub skip_empty_dirs: bool,
+    /// removes files and directories from the target that were removed from the sources and
+    /// renames the files that were moved instead of copying them again, only done by full syncs
+    pub mirror: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            workers: 10,
+            large_file_workers: 2,
+            large_file_threshold: 64 * 1024 * 1024,
+            low_io_priority: false,
+            delta_transfer: false,
+            bandwidth_limit: None,
+            bandwidth_schedule: Vec::new(),
+            space_check: SpaceCheck::default(),
+            preserve_metadata: false,
+            preserve_acls: false,
+            skip_empty_dirs: false,
+            mirror: false,
+        }
+    }
+}
+
+impl SyncOptions {
+    /// Reads the options from the settings, missing ones are set to their default.
+    pub fn load(db: &AppSettings) -> Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            workers: parse_count(db, WORKERS_SETTING)?.unwrap_or(default.workers),
+            large_file_workers: parse_count(db, LARGE_FILE_WORKERS_SETTING)?
+                .unwrap_or(default.large_file_workers),
+            large_file_threshold: parse_count(db, LARGE_FILE_THRESHOLD_SETTING)?
+                .map(|mib| mib as u64 * 1024 * 1024)
+                .unwrap_or(default.large_file_threshold),
+            low_io_priority: db.get_setting(LOW_IO_PRIORITY_SETTING)?.as_deref() == Some("true"),
+            delta_transfer: db.get_setting(DELTA_TRANSFER_SETTING)?.as_deref() == Some("true"),
+            bandwidth_limit: bandwidth::get_limit(db)?,
+            bandwidth_schedule: bandwidth::get_schedule(db)?,
+            space_check: db
+                .get_setting(SPACE_CHECK_SETTING)?
+                .map(|value| SpaceCheck::from(value.as_str()))
+                .unwrap_or_default(),
+            preserve_metadata: db.get_setting(PRESERVE_METADATA_SETTING)?.as_deref()
+                == Some("true"),
+            preserve_acls: db.get_setting(PRESERVE_ACLS_SETTING)?.as_deref() == Some("true"),
+            skip_empty_dirs: db.get_setting(SKIP_EMPTY_DIRS_SETTING)?.as_deref() == Some("true"),
+            mirror: db.get_setting(MIRROR_SETTING)?.as_deref() == Some("true"),
+        })
+    }
+}
+
+/// Parses a setting that has to be a number greater than 0.
+pub fn parse_count(db: &AppSettings, key: &str) -> Result<Option<usize>> {
+    db.get_setting(key)?
+        .map(|value| parse_count_value(key, &value))
+        .transpose()
+}
+
+/// Parses the value of a setting that has to be a number greater than 0.
+pub fn parse_count_value(key: &str, value: &str) -> Result<usize> {
+    match value.trim().parse() {
+        Ok(0) => anyhow::bail!("setting {:?} has to be greater than 0", key),
+        result => result.with_context(|| format!("setting {:?} is not a number: {:?}", key, value)),
+    }
+}
+
+/// Sets the I/O priority of the calling thread to idle, or back to the default.
+#[cfg(target_os = "linux")]
+pub(super) fn set_io_priority(low: bool) {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let priority = if low {
+        IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT
+    } else {
+        0
+    };
+    // a pid of 0 is the calling thread, failing only means the priority stays as it is
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn set_io_priority(_low: bool) {}
@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::DateTime;
+use super::sync::{Statistics, SyncError};
+
+/// How a sync run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SyncOutcome {
+    Success,
+    Failed,
+}
+
+impl From<&str> for SyncOutcome {
+    fn from(value: &str) -> Self {
+        match value {
+            "Success" => Self::Success,
+            _ => Self::Failed,
+        }
+    }
+}
+
+impl From<&SyncOutcome> for String {
+    fn from(value: &SyncOutcome) -> Self {
+        match value {
+            SyncOutcome::Success => "Success",
+            SyncOutcome::Failed => "Failed",
+        }
+        .to_owned()
+    }
+}
+
+/// What happened to a single file during a sync run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    /// The file did not exist in the target and was created.
+    Created,
+    /// The file existed in the target and was rewritten.
+    Updated,
+    /// The file in the target was already up to date.
+    Unchanged,
+    /// The file was created as a hard link to another file in the target.
+    Linked,
+    /// The file was moved or renamed in the sources and renamed in the target.
+    Moved,
+    /// The file or directory was removed from the sources and the target, see [`super::options::SyncOptions::mirror`].
+    Removed,
+    /// The file was not synced, e.g. because it is a symlink.
+    Skipped,
+    /// Syncing the file failed.
+    Failed,
+}
+
+impl FileAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Unchanged => "unchanged",
+            Self::Linked => "linked",
+            Self::Moved => "moved",
+            Self::Removed => "removed",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// The entry of a single file in a [`Report`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub action: FileAction,
+    /// size of the source file in bytes, if it is known
+    pub size: Option<u64>,
+    /// time spent on the file in milliseconds
+    pub duration_ms: f64,
+    pub error: Option<String>,
+}
+
+/// Everything a [`super::sync::Syncer`] did during a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub started: DateTime,
+    pub finished: DateTime,
+    pub sources: Vec<PathBuf>,
+    pub target: PathBuf,
+    pub outcome: SyncOutcome,
+    pub statistics: Statistics,
+    pub files: Vec<FileReport>,
+    pub errors: Vec<SyncError>,
+    pub version: String,
+    /// id of the target, `None` if the sync failed before the target was checked
+    pub target_id: Option<String>,
+}
+
+/// The file formats a [`Report`] can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+impl Report {
+    /// Whether the run wrote anything to the target or failed.
+    pub fn has_changes(&self) -> bool {
+        self.outcome == SyncOutcome::Failed
+            || self.statistics.copied > 0
+            || self.statistics.updated > 0
+            || self.statistics.linked > 0
+            || self.statistics.moved > 0
+            || self.statistics.removed > 0
+            || self.statistics.failed > 0
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Converting report to json failed.")
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>file-sync-rs report</title>\n<style>\n\
+            body { font-family: sans-serif; margin: 2em; }\n\
+            table { border-collapse: collapse; width: 100%; }\n\
+            th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n\
+            th { background: #eee; }\n\
+            .created { color: #2a7d2a; }\n\
+            .updated { color: #1f5fa8; }\n\
+            .linked, .moved { color: #6a4fa8; }\n\
+            .unchanged, .skipped { color: #777; }\n\
+            .removed { color: #a8741f; }\n\
+            .failed { color: #b74f4f; font-weight: bold; }\n\
+            </style>\n</head>\n<body>\n<h1>file-sync-rs report</h1>\n",
+        );
+
+        html += "<table>\n";
+        for (key, value) in [
+            ("Started", self.started.to_rfc3339()),
+            ("Finished", self.finished.to_rfc3339()),
+            (
+                "Sources",
+                self.sources
+                    .iter()
+                    .map(|source| source.to_string_lossy().into_owned())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+            ("Target", self.target.to_string_lossy().into_owned()),
+            ("Outcome", String::from(&self.outcome)),
+            ("Created", self.statistics.copied.to_string()),
+            ("Updated", self.statistics.updated.to_string()),
+            ("Skipped", self.statistics.skipped.to_string()),
+            ("Linked", self.statistics.linked.to_string()),
+            ("Reflinked", self.statistics.reflinked.to_string()),
+            ("Offloaded", self.statistics.offloaded.to_string()),
+            ("Moved", self.statistics.moved.to_string()),
+            ("Removed", self.statistics.removed.to_string()),
+            ("Failed", self.statistics.failed.to_string()),
+            ("Bytes written", self.statistics.bytes.to_string()),
+            ("Version", self.version.clone()),
+        ] {
+            html += &format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                key,
+                escape_html(&value)
+            );
+        }
+        html += "</table>\n";
+
+        if !self.errors.is_empty() {
+            html += "<h2>Errors</h2>\n<table>\n<tr><th>Path</th><th>Error</th></tr>\n";
+            for error in &self.errors {
+                html += &format!(
+                    "<tr><td>{}</td><td class=\"failed\">{}</td></tr>\n",
+                    escape_html(&error.path().to_string_lossy()),
+                    escape_html(error.message())
+                );
+            }
+            html += "</table>\n";
+        }
+
+        html += "<h2>Files</h2>\n<table>\n<tr><th>Source</th><th>Target</th><th>Action</th><th>Size (bytes)</th><th>Duration (ms)</th><th>Error</th></tr>\n";
+        for file in &self.files {
+            html += &format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"{action}\">{action}</td><td>{}</td><td>{:.2}</td><td>{}</td></tr>\n",
+                escape_html(&file.source.to_string_lossy()),
+                escape_html(&file.target.to_string_lossy()),
+                file.size.map(|size| size.to_string()).unwrap_or_default(),
+                file.duration_ms,
+                escape_html(file.error.as_deref().unwrap_or_default()),
+                action = file.action.as_str(),
+            );
+        }
+        html += "</table>\n</body>\n</html>\n";
+
+        html
+    }
+
+    /// Writes the report in the given format to `path`.
+    pub fn write(&self, path: &Path, format: ReportFormat) -> Result<()> {
+        let content = match format {
+            ReportFormat::Json => self.to_json()?,
+            ReportFormat::Html => self.to_html(),
+        };
+        std::fs::write(path, content)
+            .with_context(|| format!("Writing report to {:?} failed.", path))
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&#39;",
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn report() -> Report {
+        let started = chrono::Utc.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap();
+        let file = |name: &str, action| FileReport {
+            source: PathBuf::from("/source").join(name),
+            target: PathBuf::from("/target/source").join(name),
+            action,
+            size: Some(7),
+            duration_ms: 1.5,
+            error: None,
+        };
+        Report {
+            started,
+            finished: started + chrono::Duration::minutes(5),
+            sources: vec![PathBuf::from("/source")],
+            target: PathBuf::from("/target"),
+            outcome: SyncOutcome::Failed,
+            statistics: Statistics {
+                copied: 1,
+                moved: 1,
+                removed: 1,
+                failed: 1,
+                bytes: 7,
+                ..Statistics::default()
+            },
+            files: vec![
+                file("<new> & \"quoted\"", FileAction::Created),
+                file("moved", FileAction::Moved),
+                file("removed", FileAction::Removed),
+                FileReport {
+                    error: Some(String::from("permission denied")),
+                    ..file("locked", FileAction::Failed)
+                },
+            ],
+            errors: vec![SyncError::from_parts(
+                PathBuf::from("/source/locked"),
+                String::from("permission <denied>"),
+            )],
+            version: String::from("1.0.0"),
+            target_id: None,
+        }
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain"), "plain");
+    }
+
+    #[test]
+    fn json_contains_the_files_and_statistics() {
+        let json: serde_json::Value = serde_json::from_str(&report().to_json().unwrap()).unwrap();
+        assert_eq!(json["outcome"], "Failed");
+        assert_eq!(json["statistics"]["moved"], 1);
+        assert_eq!(json["files"][0]["source"], "/source/<new> & \"quoted\"");
+        assert_eq!(json["files"][1]["action"], "moved");
+        assert_eq!(json["files"][3]["error"], "permission denied");
+        assert_eq!(json["errors"][0]["message"], "permission <denied>");
+        assert_eq!(json["started"], "2025-01-01T10:00:00Z");
+    }
+
+    #[test]
+    fn html_escapes_paths_and_styles_every_action() {
+        let html = report().to_html();
+        assert!(html.contains("/source/&lt;new&gt; &amp; &quot;quoted&quot;"));
+        assert!(!html.contains("<new>"));
+        assert!(html.contains("permission &lt;denied&gt;"));
+        for action in [
+            FileAction::Created,
+            FileAction::Updated,
+            FileAction::Unchanged,
+            FileAction::Linked,
+            FileAction::Moved,
+            FileAction::Removed,
+            FileAction::Skipped,
+            FileAction::Failed,
+        ] {
+            assert!(
+                html.contains(&format!(".{}", action.as_str())),
+                "no style for {:?}",
+                action
+            );
+        }
+        assert!(html.contains("<td class=\"moved\">moved</td>"));
+        assert!(html.contains("<td class=\"removed\">removed</td>"));
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// The setting key for [`SpaceCheck`].
+pub const SPACE_CHECK_SETTING: &str = "space_check";
+
+/// What happens if the files to write do not fit on the target.
+///
+/// The free space is measured before anything is written. While the sources are still searched,
+/// files are only synced as long as they fit into it, the rest waits for the check.
+/// Platforms without a way to measure the free space skip the check.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpaceCheck {
+    /// Fail the sync with [`InsufficientSpace`].
+    #[default]
+    Refuse,
+    /// Record an error in the report and sync anyway.
+    Warn,
+    Off,
+}
+
+impl SpaceCheck {
+    pub const ALL: [SpaceCheck; 3] = [Self::Refuse, Self::Warn, Self::Off];
+}
+
+impl From<&str> for SpaceCheck {
+    fn from(value: &str) -> Self {
+        match value {
+            "warn" => Self::Warn,
+            "off" => Self::Off,
+            _ => Self::Refuse,
+        }
+    }
+}
+
+impl From<&SpaceCheck> for &str {
+    fn from(value: &SpaceCheck) -> Self {
+        match value {
+            SpaceCheck::Refuse => "refuse",
+            SpaceCheck::Warn => "warn",
+            SpaceCheck::Off => "off",
+        }
+    }
+}
+
+/// The bytes a sync writes compared to the space of the target filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpaceEstimate {
+    /// bytes of new files plus the growth of existing ones
+    pub needed: u64,
+    /// bytes that can be written to the target filesystem
+    pub available: u64,
+    /// total size of the target filesystem
+    pub capacity: u64,
+}
+
+impl SpaceEstimate {
+    pub fn fits(&self) -> bool {
+        self.needed <= self.available
+    }
+}
+
+/// The sync was refused because the files do not fit on the target.
+#[derive(Debug, Clone, Copy)]
+pub struct InsufficientSpace(pub SpaceEstimate);
+
+impl std::fmt::Display for InsufficientSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the sync needs {} bytes, but only {} of {} bytes are free on the target",
+            self.0.needed, self.0.available, self.0.capacity
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSpace {}
+
+/// Returns the available and total bytes of the filesystem containing `path`,
+/// `None` if they can not be measured on this platform.
+#[cfg(unix)]
+// the types of the statvfs fields differ between platforms
+#[allow(clippy::unnecessary_cast)]
+pub fn filesystem_space(path: &Path) -> Result<Option<(u64, u64)>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("invalid path {:?}", path))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // statvfs only writes to the given struct
+    if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("failed to query free space of {:?}", path));
+    }
+    let stat = unsafe { stat.assume_init() };
+    let block_size = stat.f_frsize as u64;
+    Ok(Some((
+        stat.f_bavail as u64 * block_size,
+        stat.f_blocks as u64 * block_size,
+    )))
+}
+
+#[cfg(windows)]
+pub fn filesystem_space(path: &Path) -> Result<Option<(u64, u64)>> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut available = 0;
+    let mut capacity = 0;
+    // the path is null terminated and the function only writes to the given numbers
+    if unsafe {
+        GetDiskFreeSpaceExW(
+            wide_path.as_ptr(),
+            &mut available,
+            &mut capacity,
+            std::ptr::null_mut(),
+        )
+    } == 0
+    {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("failed to query free space of {:?}", path));
+    }
+    Ok(Some((available, capacity)))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn filesystem_space(_path: &Path) -> Result<Option<(u64, u64)>> {
+    Ok(None)
+}
+
+/// The bytes that syncing a file of `len` bytes adds to the target,
+/// `target_len` is the length of the file it replaces.
+pub(super) fn needed_bytes(len: u64, target_len: Option<u64>) -> u64 {
+    len.saturating_sub(target_len.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needed_bytes_of_files() {
+        assert_eq!(needed_bytes(100, None), 100);
+        assert_eq!(needed_bytes(100, Some(30)), 70);
+        // shrinking files free space, which is not counted
+        assert_eq!(needed_bytes(30, Some(100)), 0);
+    }
+
+    #[test]
+    fn estimate_fits() {
+        let estimate = SpaceEstimate {
+            needed: 10,
+            available: 10,
+            capacity: 100,
+        };
+        assert!(estimate.fits());
+        assert!(
+            !SpaceEstimate {
+                needed: 11,
+                ..estimate
+            }
+            .fits()
+        );
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn measure_filesystem_space() {
+        let (available, capacity) = filesystem_space(&std::env::temp_dir()).unwrap().unwrap();
+        assert!(capacity > 0 && available <= capacity);
+        assert!(filesystem_space(Path::new("/does/not/exist")).is_err());
+    }
+}
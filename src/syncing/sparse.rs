@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::bandwidth::{self, RateLimiter};
+
+/// Returns the ranges of the file that contain data as start and end offsets.
+/// Returns `None` if the file has no holes or the filesystem can not tell.
+///
+/// Moves the position of the file.
+#[cfg(target_os = "linux")]
+pub(super) fn data_ranges(file: &File) -> std::io::Result<Option<Vec<(u64, u64)>>> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = file.metadata()?.len();
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        // lseek only moves the position of the open file
+        let data = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let error = std::io::Error::last_os_error();
+            match error.raw_os_error() {
+                // only a hole is left
+                Some(libc::ENXIO) => break,
+                Some(libc::EINVAL) => return Ok(None),
+                _ => return Err(error),
+            }
+        }
+        let hole = unsafe { libc::lseek(file.as_raw_fd(), data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        ranges.push((data as u64, (hole as u64).min(len)));
+        offset = hole as u64;
+    }
+
+    if ranges.len() == 1 && ranges[0] == (0, len) || len == 0 {
+        return Ok(None);
+    }
+    Ok(Some(ranges))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn data_ranges(_file: &File) -> std::io::Result<Option<Vec<(u64, u64)>>> {
+    Ok(None)
+}
+
+/// Copies a sparse file, only its data is written so the holes stay holes in the target.
+/// Returns the number of bytes written or `None` if the source is not sparse.
+pub(super) fn copy(source: &Path, target: &Path, limiter: &RateLimiter) -> Result<Option<u64>> {
+    let mut source_file =
+        File::open(source).with_context(|| format!("Could not open source file {:?}", source))?;
+    let Some(ranges) = data_ranges(&source_file)
+        .with_context(|| format!("Could not find the holes of {:?}", source))?
+    else {
+        return Ok(None);
+    };
+
+    let mut target_file = File::create(target)
+        .with_context(|| format!("Could not create target file {:?}", target))?;
+    target_file
+        .set_len(source_file.metadata()?.len())
+        .with_context(|| format!("Could not set target file length for {:?}", target))?;
+
+    let mut buffer = vec![0; bandwidth::CHUNK_SIZE];
+    let mut bytes = 0;
+    for (start, end) in ranges {
+        source_file.seek(SeekFrom::Start(start))?;
+        target_file.seek(SeekFrom::Start(start))?;
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let size = remaining.min(buffer.len() as u64) as usize;
+            let read = source_file
+                .read(&mut buffer[..size])
+                .with_context(|| format!("Could not read file {:?}", source))?;
+            // the source got shorter
+            if read == 0 {
+                break;
+            }
+            bandwidth::write_all(&mut target_file, &buffer[..read], limiter)
+                .with_context(|| format!("Could not write to file {:?}", target))?;
+            remaining -= read as u64;
+            bytes += read as u64;
+        }
+    }
+    Ok(Some(bytes))
+}
+
+/// Rewrites the target with the content of the sparse source, only the data ranges are written.
+/// Returns the number of bytes written.
+pub(super) fn rewrite(
+    target_file: &mut File,
+    content: &[u8],
+    ranges: &[(u64, u64)],
+    limiter: &RateLimiter,
+) -> std::io::Result<u64> {
+    // truncating frees the old blocks, everything that is not written again is a hole
+    target_file.set_len(0)?;
+    target_file.set_len(content.len() as u64)?;
+    let mut bytes = 0;
+    for (start, end) in ranges {
+        let range = &content[*start as usize..(*end as usize).min(content.len())];
+        target_file.seek(SeekFrom::Start(*start))?;
+        bandwidth::write_all(target_file, range, limiter)?;
+        bytes += range.len() as u64;
+    }
+    target_file.flush()?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sync-rs-sparse-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A file of 4 MiB with 1 MiB of data at 1 MiB and 8 bytes at the end, the rest are holes.
+    fn sparse_file(path: &Path) {
+        let mut file = File::create(path).unwrap();
+        file.set_len(4 * MIB).unwrap();
+        file.seek(SeekFrom::Start(MIB)).unwrap();
+        file.write_all(&vec![1; MIB as usize]).unwrap();
+        file.seek(SeekFrom::Start(4 * MIB - 8)).unwrap();
+        file.write_all(b"the end!").unwrap();
+    }
+
+    #[test]
+    fn find_data_ranges() {
+        let dir = fixture("ranges");
+        std::fs::write(dir.join("dense"), vec![1; 100_000]).unwrap();
+        std::fs::write(dir.join("empty"), b"").unwrap();
+        sparse_file(&dir.join("sparse"));
+
+        let ranges = |name| data_ranges(&File::open(dir.join(name)).unwrap()).unwrap();
+        assert_eq!(ranges("dense"), None);
+        assert_eq!(ranges("empty"), None);
+        // filesystems without holes report the whole file as data
+        if let Some(ranges) = ranges("sparse") {
+            // filesystems allocate in blocks, the ranges cover at least the data
+            assert_eq!(ranges.len(), 2);
+            assert!(ranges[0].0 <= MIB && ranges[0].1 >= 2 * MIB && ranges[0].1 < 4 * MIB);
+            assert_eq!(ranges[1].1, 4 * MIB);
+            assert!(ranges[1].0 <= 4 * MIB - 8 && ranges[1].0 > 2 * MIB);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn copy_keeps_holes() {
+        let dir = fixture("copy");
+        sparse_file(&dir.join("sparse"));
+        std::fs::write(dir.join("dense"), b"content").unwrap();
+
+        assert_eq!(
+            copy(
+                &dir.join("dense"),
+                &dir.join("dense copy"),
+                &RateLimiter::default()
+            )
+            .unwrap(),
+            None
+        );
+        assert!(!dir.join("dense copy").exists());
+
+        let Some(bytes) = copy(
+            &dir.join("sparse"),
+            &dir.join("copy"),
+            &RateLimiter::default(),
+        )
+        .unwrap() else {
+            // the filesystem does not report holes
+            return;
+        };
+        assert!((MIB + 8..4 * MIB).contains(&bytes));
+        assert!(
+            std::fs::read(dir.join("copy")).unwrap() == std::fs::read(dir.join("sparse")).unwrap()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let blocks = |name| std::fs::metadata(dir.join(name)).unwrap().blocks();
+            assert!(blocks("copy") <= blocks("sparse"));
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,14 +1,47 @@
 use anyhow::{Context, Result};
-use futures::stream::StreamExt;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
 use std::io::Read;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::{JoinError, JoinHandle};
+
+use super::backend::{self, LocalBackend, TargetBackend};
+use super::bandwidth::{self, RateLimiter};
+use super::checkpoint::{Checkpoint, CheckpointWriter};
+use super::copy::{self, CopyMethod};
+use super::delta::{self, TransferStrategy};
+use super::lock::TargetLock;
+use super::manifest::{Fingerprint, Manifest, ManifestWriter};
+use super::metadata;
+use super::mirror;
+use super::options::SyncOptions;
+use super::report::{FileAction, FileReport, Report, SyncOutcome};
+use super::space::{self, InsufficientSpace, SpaceCheck, SpaceEstimate};
+use super::sparse;
+use super::walk::{self, Found, Walker};
+
+/// The result of a job, the job itself and how long it took.
+type Finished = (Result<Action>, Job, Duration);
+
+/// How often the state is reported while only the indexing progresses.
+const INDEXING_STATE_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Clone)]
 pub struct State {
     current_work: Vec<PathBuf>,
     total: usize,
     done: usize,
+    /// whether further files are being searched
+    indexing: bool,
+    indexed_dirs: usize,
+    indexed_files: usize,
+    /// set once the free space of the target was checked
+    space: Option<SpaceEstimate>,
 }
 
 impl State {
@@ -23,37 +56,349 @@ impl State {
     pub fn done(&self) -> usize {
         self.done
     }
+
+    pub fn indexing(&self) -> bool {
+        self.indexing
+    }
+
+    /// The number of directories found so far.
+    pub fn indexed_dirs(&self) -> usize {
+        self.indexed_dirs
+    }
+
+    /// The number of files found so far.
+    pub fn indexed_files(&self) -> usize {
+        self.indexed_files
+    }
+
+    pub fn space(&self) -> Option<SpaceEstimate> {
+        self.space
+    }
+}
+
+/// Counters of what a [`Syncer`] did during a run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Statistics {
+    /// files that did not exist in the target and were copied
+    pub copied: usize,
+    /// files that existed in the target and were rewritten
+    pub updated: usize,
+    /// files that were already up to date or could not be synced (e.g. symlinks)
+    pub skipped: usize,
+    /// files whose job failed
+    pub failed: usize,
+    /// files that were created as hard links to other files in the target
+    pub linked: usize,
+    /// copied files that share their blocks with the source
+    pub reflinked: usize,
+    /// copied files whose content was copied by the kernel or the filesystem
+    pub offloaded: usize,
+    /// files and directories removed from the target because they were removed from the sources
+    pub removed: usize,
+    /// files that were moved or renamed in the sources and renamed in the target instead of copied
+    pub moved: usize,
+    /// bytes written to the target
+    pub bytes: u64,
+}
+
+impl Statistics {
+    fn record(&mut self, action: &Action) {
+        match action {
+            Action::Copied(bytes, method) => {
+                self.copied += 1;
+                self.bytes += bytes;
+                match method {
+                    CopyMethod::Reflink => self.reflinked += 1,
+                    CopyMethod::Offload => self.offloaded += 1,
+                    CopyMethod::Bytes => {}
+                }
+            }
+            Action::Updated(bytes) => {
+                self.updated += 1;
+                self.bytes += bytes;
+            }
+            Action::Unchanged(_) => self.skipped += 1,
+            Action::Linked(_) => self.linked += 1,
+            Action::Moved(_) => self.moved += 1,
+            Action::Directory => {}
+        }
+    }
+}
+
+/// An error that occurred while syncing a specific path.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncError {
+    path: PathBuf,
+    message: String,
+}
+
+impl SyncError {
+    pub fn new(path: PathBuf, error: &anyhow::Error) -> Self {
+        Self {
+            path,
+            message: format!("{:#}", error),
+        }
+    }
+
+    pub fn from_parts(path: PathBuf, message: String) -> Self {
+        Self { path, message }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+}
+
+/// What a [`Job`] did to its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// The file was copied to the target. Contains the number of bytes copied and how.
+    Copied(u64, CopyMethod),
+    /// The file in the target was rewritten. Contains the number of bytes written.
+    Updated(u64),
+    /// The file in the target was already up to date. Contains the size of the file.
+    Unchanged(u64),
+    /// The target was linked to the target of another hard link. Contains the size of the file.
+    Linked(u64),
+    /// The target was renamed from the target of the file the source was moved from.
+    /// Contains the size of the file.
+    Moved(u64),
+    /// The job was a directory.
+    Directory,
+}
+
+impl Action {
+    /// Returns the [`FileAction`] and size for the report or `None` for directories.
+    fn file_action(&self) -> Option<(FileAction, u64)> {
+        match *self {
+            Self::Copied(bytes, _) => Some((FileAction::Created, bytes)),
+            Self::Updated(bytes) => Some((FileAction::Updated, bytes)),
+            Self::Unchanged(size) => Some((FileAction::Unchanged, size)),
+            Self::Linked(size) => Some((FileAction::Linked, size)),
+            Self::Moved(size) => Some((FileAction::Moved, size)),
+            Self::Directory => None,
+        }
+    }
+}
+
+/// Whether both paths are the same file, i.e. hard links to the same inode.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// What the workers need besides their [`Job`].
+#[derive(Debug, Clone)]
+struct WorkContext {
+    /// where the targets are written to
+    backend: Arc<dyn TargetBackend>,
+    limiter: RateLimiter,
+    /// see [`SyncOptions::preserve_metadata`]
+    preserve_metadata: bool,
+    /// see [`SyncOptions::preserve_acls`]
+    preserve_acls: bool,
+    /// see [`SyncOptions::skip_empty_dirs`]
+    skip_empty_dirs: bool,
+    /// set by the workers if the target can not store the ACLs of a source
+    acls_unsupported: Arc<AtomicBool>,
+    /// set by the workers if the target did not permit to preserve some metadata
+    metadata_denied: Arc<AtomicBool>,
+}
+
+impl Default for WorkContext {
+    fn default() -> Self {
+        Self {
+            backend: Arc::new(LocalBackend),
+            limiter: RateLimiter::default(),
+            preserve_metadata: false,
+            preserve_acls: false,
+            skip_empty_dirs: false,
+            acls_unsupported: Arc::default(),
+            metadata_denied: Arc::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Job {
     source: PathBuf,
     target: PathBuf,
+    /// whether the content of a directory is synced as well
+    recursive: bool,
+    /// source and target of the first found hard link to the same file, the target is linked to it
+    link_to: Option<(PathBuf, PathBuf)>,
+    /// target of the file the source was moved or renamed from, see [`Manifest::take_moved`]
+    move_from: Option<PathBuf>,
+    /// how the file is written if the target exists already
+    transfer: TransferStrategy,
+    /// length of the source file, `None` for directories
+    len: Option<u64>,
+    /// length of the existing target file, only read while the free space is checked
+    target_len: Option<u64>,
 }
 
 impl Job {
-    fn work(&self) -> Result<()> {
-        if self.source.is_file() {
-            if self.target.is_file() {
-                self.file_work().context("failed to do file work")?;
-            } else {
-                std::fs::copy(&self.source, &self.target).with_context(|| {
-                    format!("Could not copy file {:?} to {:?}", self.source, self.target)
-                })?;
+    /// The bytes the job adds to the target.
+    fn needed_bytes(&self) -> u64 {
+        // links and moved files do not need space for the content
+        if self.link_to.is_some() || self.move_from.is_some() {
+            return 0;
+        }
+        self.len
+            .map_or(0, |len| space::needed_bytes(len, self.target_len))
+    }
+
+    fn is_large(&self, threshold: u64) -> bool {
+        self.len.is_some_and(|len| len >= threshold)
+    }
+
+    fn work(&self, context: &WorkContext) -> Result<Action> {
+        let backend = context.backend.as_ref();
+        // without the directory jobs creating them, files create their directories
+        if context.skip_empty_dirs
+            && self.source.is_file()
+            && let Some(parent) = self.target.parent()
+        {
+            backend
+                .mkdir_all(parent)
+                .context(format!("Could not create directory {:?}", parent))?;
+        }
+        let target = backend
+            .stat(&self.target)
+            .context(format!("Could not query metadata of {:?}", self.target))?;
+
+        let linked = match &self.link_to {
+            Some((_, first)) if self.source.is_file() && backend.is_local() => {
+                self.link_work(first)?
+            }
+            _ => None,
+        };
+        let action = if let Some(action) = linked {
+            action
+        } else if self.source.is_file() {
+            if let Some(from) = &self.move_from
+                && target.is_none()
+                && backend.stat(from)?.is_some_and(|from| from.is_file())
+            {
+                backend
+                    .rename(from, &self.target)
+                    .context(format!("Could not rename {:?} to {:?}", from, self.target))?;
+                // the fingerprint only makes it likely that the content is the same
+                match self.file_work(context).context("failed to do file work")? {
+                    Action::Unchanged(size) => Action::Moved(size),
+                    action => action,
+                }
+            } else if target.as_ref().is_some_and(|target| target.is_file()) {
+                self.file_work(context).context("failed to do file work")?
+            } else if backend.is_local() {
+                let (bytes, method) = copy::copy(&self.source, &self.target, &context.limiter)?;
                 std::fs::set_permissions(
                     &self.target,
                     std::fs::metadata(&self.source)?.permissions(),
                 )
                 .context(format!("Could not set permissions for {:?}", self.target))?;
+                Action::Copied(bytes, method)
+            } else {
+                Action::Copied(self.upload(context)?, CopyMethod::Bytes)
             }
-        } else if !self.target.is_dir() {
-            std::fs::create_dir(&self.target)
-                .context(format!("Could not create directory {:?}", self.target))?;
+        } else {
+            if target.as_ref().is_some_and(|target| target.is_dir()) {
+                // the content has to be writable, the mode is set again by finish_directory
+                if context.preserve_metadata && backend.is_local() {
+                    let mut permissions = std::fs::metadata(&self.target)?.permissions();
+                    if permissions.readonly() {
+                        #[allow(clippy::permissions_set_readonly_false)]
+                        permissions.set_readonly(false);
+                        std::fs::set_permissions(&self.target, permissions)
+                            .context(format!("Could not set permissions for {:?}", self.target))?;
+                    }
+                }
+            } else if !context.skip_empty_dirs {
+                backend
+                    .mkdir(&self.target)
+                    .context(format!("Could not create directory {:?}", self.target))?;
+            }
+            Action::Directory
+        };
+        if action != Action::Directory {
+            self.preserve_metadata(context)?;
+        }
+        Ok(action)
+    }
+
+    /// Gives the directory the metadata of the source once its content is synced,
+    /// so e.g. a read only mode does not prevent writing the content.
+    fn finish_directory(&self, context: &WorkContext) -> Result<()> {
+        // not created because it would have been empty
+        if !context
+            .backend
+            .stat(&self.target)
+            .context(format!("Could not query metadata of {:?}", self.target))?
+            .is_some_and(|target| target.is_dir())
+        {
+            return Ok(());
+        }
+        self.preserve_metadata(context)
+    }
+
+    fn preserve_metadata(&self, context: &WorkContext) -> Result<()> {
+        if !context.backend.is_local() {
+            return Ok(());
+        }
+        if context.preserve_metadata && !metadata::preserve(&self.source, &self.target)? {
+            context.metadata_denied.store(true, Ordering::Relaxed);
+        }
+        // after the mode, setting the mode changes the ACL
+        if context.preserve_acls && !metadata::preserve_acls(&self.source, &self.target)? {
+            context.acls_unsupported.store(true, Ordering::Relaxed);
         }
         Ok(())
     }
 
-    fn file_work(&self) -> Result<()> {
+    /// Links the target to the target of the first hard link to the same file.
+    /// Returns `None` if the target filesystem can not link, the file is copied instead.
+    fn link_work(&self, first: &Path) -> Result<Option<Action>> {
+        let size = std::fs::metadata(&self.source)
+            .context(format!("Could not query metadata of {:?}", self.source))?
+            .len();
+        if same_file(first, &self.target) {
+            return Ok(Some(Action::Unchanged(size)));
+        }
+        // linked next to the target and renamed over it, so it stays if linking fails
+        let mut name = self.target.file_name().unwrap_or_default().to_os_string();
+        name.push(".file_sync_rs.link");
+        let link = self.target.with_file_name(name);
+        let _ = std::fs::remove_file(&link);
+        if std::fs::hard_link(first, &link).is_err() {
+            return Ok(None);
+        }
+        if let Err(error) = std::fs::rename(&link, &self.target) {
+            let _ = std::fs::remove_file(&link);
+            return Err(error).context(format!("Could not replace file {:?}", self.target));
+        }
+        Ok(Some(Action::Linked(size)))
+    }
+
+    fn file_work(&self, context: &WorkContext) -> Result<Action> {
+        if !context.backend.is_local() {
+            return self.backend_file_work(context);
+        }
+        let limiter = &context.limiter;
+
         if std::fs::metadata(&self.target)?.permissions().readonly() {
             let mut perms = std::fs::metadata(&self.target)?.permissions();
             #[allow(clippy::permissions_set_readonly_false)]
@@ -92,6 +437,15 @@ impl Job {
             ))?;
         }
 
+        if self.transfer == TransferStrategy::Delta {
+            return match delta::transfer(&mut source_file, &mut target_file, limiter).context(
+                format!("Could not transfer the changes to {:?}", self.target),
+            )? {
+                Some(bytes) => Ok(Action::Updated(bytes)),
+                None => Ok(Action::Unchanged(source_file_metadata.len())),
+            };
+        }
+
         // change length of the file if differ
         if source_file_metadata.len() != target_file_metadata.len() {
             target_file
@@ -114,23 +468,137 @@ impl Job {
 
         // return if files are equal
         if source_file_content == target_file_content {
-            return Ok(());
+            return Ok(Action::Unchanged(source_file_content.len() as u64));
+        }
+        // only the data of sparse files is written, so their holes stay holes
+        if let Some(ranges) = sparse::data_ranges(&source_file)
+            .context(format!("Could not find the holes of {:?}", self.source))?
+        {
+            let bytes = sparse::rewrite(&mut target_file, &source_file_content, &ranges, limiter)
+                .context(format!("Could not write to file {:?}", self.target))?;
+            return Ok(Action::Updated(bytes));
         }
-        // write all file content
+        // write all file content from the start, reading moved the cursor to the end
         target_file
-            .write_all(&source_file_content)
+            .seek(SeekFrom::Start(0))
+            .context(format!("Could not seek in file {:?}", self.target))?;
+        bandwidth::write_all(&mut target_file, &source_file_content, limiter)
             .context(format!("Could not write to file {:?}", self.target))?;
-        Ok(())
+        Ok(Action::Updated(source_file_content.len() as u64))
+    }
+
+    /// Compares the file with its target and rewrites it, only with the operations of the backend.
+    ///
+    /// Every upload gives the target the modification time of the source, so a target with the
+    /// same size and time is unchanged without reading it. Delta transfers are not used.
+    fn backend_file_work(&self, context: &WorkContext) -> Result<Action> {
+        let backend = context.backend.as_ref();
+        let source = std::fs::metadata(&self.source)
+            .context(format!("Could not query metadata of {:?}", self.source))?;
+        let modified = source
+            .modified()
+            .context(format!("Could not query metadata of {:?}", self.source))?;
+        let target = backend
+            .stat(&self.target)
+            .context(format!("Could not query metadata of {:?}", self.target))?;
+        if let Some(target) = target
+            && target.len == source.len()
+        {
+            if target
+                .modified
+                .is_some_and(|target| unix_seconds(target) == unix_seconds(modified))
+            {
+                return Ok(Action::Unchanged(source.len()));
+            }
+            if backend::same_content(backend, &self.source, &self.target)? {
+                backend
+                    .set_modified(&self.target, modified)
+                    .context(format!("Could not set the times of {:?}", self.target))?;
+                return Ok(Action::Unchanged(source.len()));
+            }
+        }
+        Ok(Action::Updated(self.upload(context)?))
+    }
+
+    /// Writes the file to the backend with the modification time of the source.
+    fn upload(&self, context: &WorkContext) -> Result<u64> {
+        let backend = context.backend.as_ref();
+        let bytes = backend::upload(backend, &self.source, &self.target, &context.limiter)?;
+        let modified = std::fs::metadata(&self.source)
+            .and_then(|metadata| metadata.modified())
+            .context(format!("Could not query metadata of {:?}", self.source))?;
+        backend
+            .set_modified(&self.target, modified)
+            .context(format!("Could not set the times of {:?}", self.target))?;
+        Ok(bytes)
     }
 }
 
-#[derive(Debug, Clone)]
+/// Backends like SFTP only keep whole seconds of modification times.
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[derive(Debug)]
 pub struct Syncer {
+    sources: Vec<PathBuf>,
     target_root: PathBuf,
-    /// jobs that are not done
-    jobs_todo: Vec<Job>,
+    /// jobs that can be started
+    jobs_todo: VecDeque<Job>,
     /// jobs  that are done
     jobs_done: Vec<Job>,
+    started: super::DateTime,
+    statistics: Statistics,
+    errors: Vec<SyncError>,
+    files: Vec<FileReport>,
+    /// id of the target, expected before and checked or created in [`Syncer::prepare`]
+    target_id: Option<String>,
+    /// held from [`Syncer::prepare`] until the sync is done or the syncer is dropped
+    lock: Option<TargetLock>,
+    /// whether only some paths are synced, see [`Syncer::new_partial`]
+    partial: bool,
+    /// the checkpoint of the interrupted sync that is resumed
+    resume_from: Option<Checkpoint>,
+    checkpoint: Option<CheckpointWriter>,
+    options: SyncOptions,
+    /// shared with the workers
+    context: WorkContext,
+    /// the jobs the workers are working on
+    running: FuturesUnordered<JoinHandle<Finished>>,
+    /// sources of the running jobs and whether they are large files
+    running_sources: Vec<(PathBuf, bool)>,
+    /// large files that wait for a free large file worker
+    large_todo: VecDeque<Job>,
+    /// finds the content of the directories while the sync is running
+    walker: Option<Walker>,
+    /// the files of the last full sync, only read in mirror mode to find moved files
+    manifest: Option<Manifest>,
+    /// the files of this sync, only written by full syncs
+    manifest_writer: Option<ManifestWriter>,
+    /// directory jobs that are done, see [`Syncer::finish_directories`]
+    directories: Vec<Job>,
+    /// sources of the jobs other jobs wait for and whether they are done,
+    /// these are directories and the first found hard links
+    awaited: HashMap<PathBuf, bool>,
+    /// jobs that wait for another job, by the source of the awaited job
+    waiting: HashMap<PathBuf, Vec<Job>>,
+    /// source and target of the first found hard link by device and inode
+    inodes: HashMap<(u64, u64), (PathBuf, PathBuf)>,
+    indexed_dirs: usize,
+    indexed_files: usize,
+    last_state: Instant,
+    /// set once the free space of the target was checked, see [`SyncOptions::space_check`]
+    space: Option<SpaceEstimate>,
+    /// available and total bytes of the target filesystem before anything was written,
+    /// only measured if the space is checked
+    free_space: Option<(u64, u64)>,
+    /// bytes of the jobs that were started before the free space was checked
+    started_bytes: u64,
+    /// whether the error that the target does not support ACLs was recorded
+    acls_reported: bool,
+    /// whether the error that the target denied preserving metadata was recorded
+    metadata_denied_reported: bool,
 }
 
 impl Syncer {
@@ -142,136 +610,795 @@ impl Syncer {
                 .map(|source| Job {
                     source: source.clone(),
                     target: target.join(source.file_name().unwrap()),
+                    recursive: true,
+                    link_to: None,
+                    move_from: None,
+                    transfer: TransferStrategy::Whole,
+                    len: None,
+                    target_len: None,
                 })
                 .collect(),
             jobs_done: Vec::new(),
+            sources,
             target_root: target,
+            started: chrono::offset::Utc::now(),
+            statistics: Statistics::default(),
+            errors: Vec::new(),
+            files: Vec::new(),
+            target_id: None,
+            lock: None,
+            partial: false,
+            resume_from: None,
+            checkpoint: None,
+            options: SyncOptions::default(),
+            context: WorkContext::default(),
+            running: FuturesUnordered::new(),
+            running_sources: Vec::new(),
+            large_todo: VecDeque::new(),
+            walker: None,
+            manifest: None,
+            manifest_writer: None,
+            directories: Vec::new(),
+            awaited: HashMap::new(),
+            waiting: HashMap::new(),
+            inodes: HashMap::new(),
+            indexed_dirs: 0,
+            indexed_files: 0,
+            last_state: Instant::now(),
+            space: None,
+            free_space: None,
+            started_bytes: 0,
+            acls_reported: false,
+            metadata_denied_reported: false,
         })
     }
 
-    fn resolve_dir(&mut self, job: &Job) -> Result<()> {
-        self.jobs_todo.push(job.clone());
-        for i in std::fs::read_dir(&job.source)? {
-            let i = i?;
-            if !i.file_type()?.is_file() && !i.file_type()?.is_dir() {
+    /// Sets the id the target has to have, see [`super::TargetMismatch`].
+    /// Without an id, the id found in the target is used or a new one is created.
+    pub fn with_target_id(mut self, target_id: Option<String>) -> Self {
+        self.target_id = target_id;
+        self
+    }
+
+    /// Creates a syncer that only syncs the given `paths`, which have to be located in the `sources`.
+    /// Paths that do not exist anymore are ignored. Missing parent directories in the target are created.
+    pub fn new_partial(
+        sources: Vec<PathBuf>,
+        target: PathBuf,
+        paths: &[PathBuf],
+    ) -> Result<Self, InvalidSyncerParameters> {
+        let mut syncer = Self::new(sources, target)?;
+        syncer.jobs_todo.clear();
+        syncer.partial = true;
+
+        let mut queued_dirs = std::collections::HashSet::new();
+        for path in paths {
+            let Some(source) = syncer
+                .sources
+                .iter()
+                .find(|source| path.starts_with(source))
+            else {
                 continue;
-            }
-            let entry = i.path();
-            let new_job = Job {
-                target: job.target.join(entry.file_name().unwrap()),
-                source: entry,
             };
-            if new_job.source.is_file() {
-                self.jobs_todo.push(new_job);
-            } else {
-                self.resolve_dir(&new_job)
-                    .with_context(|| format!("failed to resolve dir for job {:?}", new_job))?;
+            if !path.is_file() && !path.is_dir() {
+                continue;
             }
+
+            let source_target = syncer.target_root.join(source.file_name().unwrap());
+            let relative = path.strip_prefix(source).unwrap();
+
+            // create the parent directories without syncing their content
+            let mut parent_source = source.clone();
+            let mut parent_target = source_target.clone();
+            let mut parents = vec![(parent_source.clone(), parent_target.clone())];
+            if let Some(relative_parent) = relative.parent() {
+                for component in relative_parent.components() {
+                    parent_source.push(component);
+                    parent_target.push(component);
+                    parents.push((parent_source.clone(), parent_target.clone()));
+                }
+            }
+            if relative.as_os_str().is_empty() {
+                parents.pop();
+            }
+            for (source, target) in parents {
+                if queued_dirs.insert(source.clone()) {
+                    syncer.jobs_todo.push_back(Job {
+                        source,
+                        target,
+                        recursive: false,
+                        link_to: None,
+                        move_from: None,
+                        transfer: TransferStrategy::Whole,
+                        len: None,
+                        target_len: None,
+                    });
+                }
+            }
+
+            syncer.jobs_todo.push_back(Job {
+                source: path.clone(),
+                target: source_target.join(relative),
+                recursive: true,
+                link_to: None,
+                move_from: None,
+                transfer: TransferStrategy::Whole,
+                len: None,
+                target_len: None,
+            });
         }
-        Ok(())
+
+        Ok(syncer)
     }
 
-    fn resolve(&mut self) -> Result<()> {
-        let jobs = self.jobs_todo.clone();
-        self.jobs_todo.clear();
-        for job in jobs {
-            if job.source.is_file() {
-                self.jobs_todo.push(job)
-            } else {
-                self.resolve_dir(&job)
-                    .with_context(|| format!("failed to resolve dir for job {:?}", job))?;
+    pub fn with_options(mut self, options: SyncOptions) -> Self {
+        self.context = WorkContext {
+            backend: self.context.backend.clone(),
+            limiter: RateLimiter::new(options.bandwidth_limit, options.bandwidth_schedule.clone()),
+            preserve_metadata: options.preserve_metadata,
+            preserve_acls: options.preserve_acls,
+            skip_empty_dirs: options.skip_empty_dirs,
+            acls_unsupported: Arc::default(),
+            metadata_denied: Arc::default(),
+        };
+        self.options = options;
+        self
+    }
+
+    /// Writes the targets with the backend instead of the local filesystem.
+    pub fn with_backend(mut self, backend: Arc<dyn TargetBackend>) -> Self {
+        self.context.backend = backend;
+        self
+    }
+
+    /// Overrides [`SyncOptions::space_check`], e.g. to sync anyway after a refused sync.
+    pub fn with_space_check(mut self, space_check: SpaceCheck) -> Self {
+        self.options.space_check = space_check;
+        self
+    }
+
+    /// Shares the limiter, so its limits can be changed while the sync is running.
+    /// Replaces the limiter created from the [`SyncOptions`].
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.context.limiter = limiter;
+        self
+    }
+
+    /// Returns the checkpoint of an interrupted sync of the same sources to the target.
+    /// Partial syncs do not use checkpoints.
+    pub fn find_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        if self.partial || !self.context.backend.is_local() {
+            return Ok(None);
+        }
+        Checkpoint::read(&self.target_root, &self.sources)
+    }
+
+    /// Skips the files that were completed by the interrupted sync and did not change since.
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.resume_from = Some(checkpoint);
+        self
+    }
+
+    /// Creates a [`Report`] of everything that was done so far.
+    pub fn report(&self, outcome: SyncOutcome) -> Report {
+        Report {
+            started: self.started,
+            finished: chrono::offset::Utc::now(),
+            sources: self.sources.clone(),
+            target: self.target_root.clone(),
+            outcome,
+            statistics: self.statistics.clone(),
+            files: self.files.clone(),
+            errors: self.errors.clone(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            target_id: self.target_id.clone(),
+        }
+    }
+
+    fn record_success(&mut self, job: &Job, action: &Action, duration: Duration) {
+        // a sync without checkpoints is still correct, only resuming it is not possible
+        if action.file_action().is_some()
+            && let Some(checkpoint) = &mut self.checkpoint
+            && checkpoint.record(&job.source).is_err()
+        {
+            self.checkpoint = None;
+        }
+        self.record_action(job, action, duration);
+    }
+
+    fn record_action(&mut self, job: &Job, action: &Action, duration: Duration) {
+        self.statistics.record(action);
+        if let Some((file_action, size)) = action.file_action() {
+            // without a manifest, moved files are only copied again
+            if let Some(manifest) = &mut self.manifest_writer
+                && manifest.record(&job.source, &job.target).is_err()
+            {
+                self.manifest_writer = None;
+            }
+            self.files.push(FileReport {
+                source: job.source.clone(),
+                target: job.target.clone(),
+                action: file_action,
+                size: Some(size),
+                duration_ms: duration.as_secs_f64() * 1000.0,
+                error: None,
+            });
+        }
+    }
+
+    fn record_failure(&mut self, job: &Job, error: &anyhow::Error, duration: Duration) {
+        self.statistics.failed += 1;
+        let error = SyncError::new(job.source.clone(), error);
+        self.files.push(FileReport {
+            source: job.source.clone(),
+            target: job.target.clone(),
+            action: FileAction::Failed,
+            size: None,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            error: Some(error.message().clone()),
+        });
+        self.errors.push(error);
+    }
+
+    /// Queues a found job. `inode` is set for files with further hard links.
+    fn queue_job(
+        &mut self,
+        mut job: Job,
+        is_dir: bool,
+        inode: Option<(u64, u64)>,
+        fingerprint: Option<Fingerprint>,
+    ) {
+        if is_dir {
+            self.indexed_dirs += 1;
+            self.awaited.insert(job.source.clone(), false);
+        } else {
+            self.indexed_files += 1;
+            if let Some(inode) = inode {
+                match self.inodes.get(&inode) {
+                    Some(first) => job.link_to = Some(first.clone()),
+                    None => {
+                        self.inodes
+                            .insert(inode, (job.source.clone(), job.target.clone()));
+                        self.awaited.insert(job.source.clone(), false);
+                    }
+                }
+            }
+            if let Some(size) = self
+                .resume_from
+                .as_ref()
+                .and_then(|checkpoint| checkpoint.completed_size(&job.source))
+            {
+                self.record_action(&job, &Action::Unchanged(size), Duration::ZERO);
+                self.complete(job);
+                return;
+            }
+            if job.link_to.is_none()
+                && let Some(manifest) = &mut self.manifest
+                && let Some(fingerprint) = &fingerprint
+            {
+                job.move_from = manifest.take_moved(&job.source, fingerprint);
+            }
+        }
+        self.schedule(job);
+    }
+
+    /// Starts the job once its directory and the first hard link to its file are done.
+    fn schedule(&mut self, job: Job) {
+        let awaited = job
+            .source
+            .parent()
+            .into_iter()
+            .chain(job.link_to.as_ref().map(|(source, _)| source.as_path()))
+            .find(|source| self.awaited.get(*source) == Some(&false))
+            .map(Path::to_path_buf);
+        match awaited {
+            Some(source) => self.waiting.entry(source).or_default().push(job),
+            None => self.jobs_todo.push_back(job),
+        }
+    }
+
+    /// Moves the job to the done jobs and schedules the jobs that waited for it.
+    fn complete(&mut self, job: Job) {
+        if let Some(done) = self.awaited.get_mut(&job.source) {
+            *done = true;
+            for waiting in self.waiting.remove(&job.source).unwrap_or_default() {
+                self.schedule(waiting);
+            }
+        }
+        self.jobs_done.push(job);
+    }
+
+    fn handle_found(&mut self, found: Found) -> Result<()> {
+        match found {
+            Found::Entry {
+                source,
+                target,
+                is_dir,
+                inode,
+                fingerprint,
+                len,
+                target_len,
+            } => self.queue_job(
+                Job {
+                    source,
+                    target,
+                    recursive: true,
+                    link_to: None,
+                    move_from: None,
+                    transfer: TransferStrategy::Whole,
+                    len,
+                    target_len,
+                },
+                is_dir,
+                inode,
+                fingerprint,
+            ),
+            Found::Special { source, target } => {
+                self.indexed_files += 1;
+                self.statistics.skipped += 1;
+                self.files.push(FileReport {
+                    source,
+                    target,
+                    action: FileAction::Skipped,
+                    size: None,
+                    duration_ms: 0.0,
+                    error: None,
+                });
+            }
+            Found::Error { source, error } => {
+                self.errors.push(SyncError::new(source, &error));
+                return Err(error);
             }
         }
-        self.jobs_todo.reverse();
         Ok(())
     }
 
     pub async fn prepare(&mut self) -> Result<()> {
+        let result = self.prepare_target().await;
+        if let Err(error) = &result {
+            self.lock = None;
+            self.errors
+                .push(SyncError::new(self.target_root.clone(), error));
+        }
+        result
+    }
+
+    async fn prepare_target(&mut self) -> Result<()> {
+        if self.context.backend.is_local() {
+            self.prepare_local_target()?;
+        } else {
+            // the lock, the target id, the checkpoint and the manifest are only kept in local
+            // targets, without them mirroring could remove the files of another target
+            if self.options.mirror {
+                anyhow::bail!("Mirroring is only supported for local targets");
+            }
+            if self.resume_from.is_some() {
+                anyhow::bail!("Resuming a sync is only supported for local targets");
+            }
+            self.context
+                .backend
+                .mkdir_all(&self.target_root)
+                .context(format!("Could not create directory {:?}", self.target_root))?;
+        }
+
+        // the jobs given at creation, the walker finds the content of their directories
+        let read_targets = self.awaiting_space_check();
+        let mut walk = Vec::new();
+        for mut job in std::mem::take(&mut self.jobs_todo) {
+            let metadata = std::fs::metadata(&job.source).ok();
+            let is_dir = metadata.as_ref().is_some_and(std::fs::Metadata::is_dir);
+            if is_dir && job.recursive {
+                walk.push((job.source.clone(), job.target.clone()));
+            }
+            job.len = metadata
+                .filter(std::fs::Metadata::is_file)
+                .map(|metadata| metadata.len());
+            if read_targets {
+                job.target_len = walk::file_len(&job.target);
+            }
+            self.queue_job(job, is_dir, None, None);
+        }
+        self.walker = Some(Walker::start(walk, read_targets));
+
+        Ok(())
+    }
+
+    /// Locks the target and writes the files of the sync next to the synced sources.
+    fn prepare_local_target(&mut self) -> Result<()> {
+        self.lock = Some(TargetLock::acquire(&self.target_root, self.started)?);
+        let target_id = self.check_target_id()?;
+
         // write status into file
         super::write_last_sync(
             self.target_root.clone(),
             &super::LastSync::new(
-                chrono::offset::Utc::now(),
-                self.jobs_todo
-                    .iter()
-                    .map(|job| job.source.clone())
-                    .collect(),
+                self.started,
+                self.sources.clone(),
                 self.target_root.clone(),
+                target_id,
             ),
         )
         .context("Updating the last sync file failed")?;
 
-        // resolve dirs
-        tokio::task::block_in_place(move || self.resolve())?;
+        if self.options.space_check != SpaceCheck::Off {
+            self.free_space = space::filesystem_space(&self.target_root)?;
+            if self.free_space.is_none() {
+                self.errors.push(SyncError::new(
+                    self.target_root.clone(),
+                    &anyhow::anyhow!(
+                        "the free space of the target can not be checked on this platform"
+                    ),
+                ));
+            }
+        }
 
+        if !self.partial {
+            if self.options.mirror {
+                self.manifest = Manifest::read(&self.target_root)?;
+            }
+            self.manifest_writer = Some(ManifestWriter::create(&self.target_root)?);
+            self.checkpoint = Some(CheckpointWriter::create(
+                &self.target_root,
+                self.started,
+                &self.sources,
+                self.resume_from.is_some(),
+            )?);
+        }
         Ok(())
     }
 
-    pub async fn async_next(&mut self) -> Option<Result<State>> {
-        let mut current_files: Vec<PathBuf> = Vec::new();
-        let mut futures = futures::stream::FuturesUnordered::new();
-
-        // gather next x jobs that can be executed concurrently
-        for _ in 0..10 {
-            let job = match self.jobs_todo.pop() {
-                Some(j) => j,
-                _ => return None,
+    /// Compares the id in the target with the expected one and returns the id to write.
+    fn check_target_id(&mut self) -> Result<String> {
+        let found = super::get_last_sync(self.target_root.clone())
+            .context("Reading the last sync file failed")?
+            .and_then(|last_sync| last_sync.target_id().cloned());
+
+        let target_id = match (self.target_id.take(), found) {
+            (Some(expected), Some(found)) if expected == found => found,
+            (Some(expected), found) => {
+                return Err(super::TargetMismatch { expected, found }.into());
+            }
+            (None, Some(found)) => found,
+            (None, None) => uuid::Uuid::new_v4().to_string(),
+        };
+        self.target_id = Some(target_id.clone());
+        Ok(target_id)
+    }
+
+    /// Writes the completed files to the checkpoint, unless it was written recently and not `forced`.
+    fn flush_checkpoint(&mut self, forced: bool) {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let result = if forced {
+                checkpoint.flush()
+            } else {
+                checkpoint.flush_if_due()
             };
+            if result.is_err() {
+                self.checkpoint = None;
+            }
+        }
+    }
 
-            if !current_files.is_empty() && job.source.starts_with(current_files.last().unwrap()) {
-                self.jobs_todo.push(job);
+    /// Starts jobs until the workers are busy.
+    fn fill_workers(&mut self) {
+        // deferred large files first, their directories are done already
+        while self.running_large() < self.options.large_file_workers
+            && let Some(job) = self.large_todo.pop_front()
+        {
+            if !self.fits_before_space_check(&job) {
+                self.large_todo.push_front(job);
                 break;
             }
+            self.spawn(job, true);
+        }
 
-            current_files.push(job.source.clone());
+        while let Some(mut job) = self.jobs_todo.pop_front() {
+            if job.is_large(self.options.large_file_threshold) {
+                if self.options.delta_transfer {
+                    job.transfer = TransferStrategy::Delta;
+                }
+                if self.running_large() < self.options.large_file_workers
+                    && self.fits_before_space_check(&job)
+                {
+                    self.spawn(job, true);
+                } else {
+                    self.large_todo.push_back(job);
+                }
+                continue;
+            }
 
-            let future = tokio::task::spawn_blocking(move || {
-                let res = job.work();
-                (res, job)
-            });
-            futures.push(future);
+            if self.running_sources.len() - self.running_large() >= self.options.workers
+                || !self.fits_before_space_check(&job)
+            {
+                self.jobs_todo.push_front(job);
+                break;
+            }
+            self.spawn(job, false);
         }
+    }
+
+    /// Whether the job can start before the free space of the target was checked.
+    /// Jobs start while the bytes of all started jobs fit into the free space, the others wait
+    /// for the indexing to finish, see [`Syncer::check_space`].
+    fn fits_before_space_check(&mut self, job: &Job) -> bool {
+        let Some((available, _)) = self.free_space.filter(|_| self.awaiting_space_check()) else {
+            return true;
+        };
+        let needed = job.needed_bytes();
+        if self.started_bytes + needed > available {
+            return false;
+        }
+        self.started_bytes += needed;
+        true
+    }
+
+    fn running_large(&self) -> usize {
+        self.running_sources
+            .iter()
+            .filter(|(_, large)| *large)
+            .count()
+    }
+
+    fn spawn(&mut self, job: Job, large: bool) {
+        self.running_sources.push((job.source.clone(), large));
+        let low_io_priority = self.options.low_io_priority;
+        let context = self.context.clone();
+        self.running.push(tokio::task::spawn_blocking(move || {
+            super::options::set_io_priority(low_io_priority);
+            let start = Instant::now();
+            let res = job.work(&context);
+            (res, job, start.elapsed())
+        }));
+    }
+
+    /// Records a finished job. Jobs that waited for it are queued if it was a directory.
+    fn finish_job(&mut self, finished: Result<Finished, JoinError>) -> Result<()> {
+        let (result, job, duration) =
+            finished.map_err(|error| anyhow::Error::new(error).context("sync worker failed"))?;
+
+        if let Some(index) = self
+            .running_sources
+            .iter()
+            .position(|(source, _)| *source == job.source)
+        {
+            self.running_sources.swap_remove(index);
+        }
+        match result {
+            Ok(action) => {
+                self.record_success(&job, &action, duration);
+                if action == Action::Directory {
+                    self.directories.push(job.clone());
+                }
+            }
+            Err(err) => {
+                self.record_failure(&job, &err, duration);
+                self.flush_checkpoint(true);
+                return Err(err);
+            }
+        }
+
+        self.complete(job);
+        self.flush_checkpoint(false);
+        self.report_unsupported_metadata();
+        Ok(())
+    }
+
+    /// Runs once all jobs are done, see [`Syncer::finish_directories`].
+    /// Afterwards the manifest of this sync replaces the last one.
+    fn finish(&mut self) -> Result<()> {
+        self.finish_directories()?;
+        if let Some(manifest) = self.manifest_writer.take()
+            && let Err(error) = manifest.finish()
+        {
+            self.errors
+                .push(SyncError::new(self.target_root.clone(), &error));
+        }
+        Ok(())
+    }
+
+    /// Records once that the target does not support ACLs or denied preserving metadata,
+    /// after a worker found out.
+    fn report_unsupported_metadata(&mut self) {
+        if self.context.acls_unsupported.swap(false, Ordering::Relaxed) && !self.acls_reported {
+            self.acls_reported = true;
+            self.errors.push(SyncError::from_parts(
+                self.target_root.clone(),
+                "the target does not support POSIX ACLs, they were not copied".to_owned(),
+            ));
+        }
+        if self.context.metadata_denied.swap(false, Ordering::Relaxed)
+            && !self.metadata_denied_reported
+        {
+            self.metadata_denied_reported = true;
+            self.errors.push(SyncError::from_parts(
+                self.target_root.clone(),
+                "the target did not permit to preserve the owner, permissions or extended attributes of some files".to_owned(),
+            ));
+        }
+    }
 
-        // wait for them to finish executing
-        while let Some(Ok(future)) = futures.next().await {
-            if let Err(err) = future.0 {
-                return Some(Err(err));
+    /// Runs once all jobs are done. In mirror mode, removes what was removed from the sources.
+    /// Then sets the metadata of the directories, the deepest ones first.
+    fn finish_directories(&mut self) -> Result<()> {
+        if self.options.mirror && !self.partial {
+            for source in self.sources.clone() {
+                if !source.is_dir() {
+                    continue;
+                }
+                let target = self.target_root.join(source.file_name().unwrap());
+                let mut removed = Vec::new();
+                let result = mirror::remove_vanished(
+                    self.context.backend.as_ref(),
+                    &source,
+                    &target,
+                    &mut removed,
+                );
+                for (source, target) in removed {
+                    self.statistics.removed += 1;
+                    self.files.push(FileReport {
+                        source,
+                        target,
+                        action: FileAction::Removed,
+                        size: None,
+                        duration_ms: 0.0,
+                        error: None,
+                    });
+                }
+                if let Err(error) = result {
+                    self.errors.push(SyncError::new(target, &error));
+                    return Err(error);
+                }
             }
-            self.jobs_done.push(future.1);
         }
 
+        let mut directories = std::mem::take(&mut self.directories);
+        // a directory can only be made read only after its subdirectories
+        directories.sort_by_key(|job| std::cmp::Reverse(job.target.components().count()));
+        for job in directories {
+            let start = Instant::now();
+            if let Err(error) = job.finish_directory(&self.context) {
+                self.record_failure(&job, &error, start.elapsed());
+                return Err(error);
+            }
+        }
+        self.report_unsupported_metadata();
+        Ok(())
+    }
+
+    fn state(&mut self) -> State {
+        self.last_state = Instant::now();
         let done_len = self.jobs_done.len();
-        Some(Ok(State {
-            current_work: current_files,
-            total: self.jobs_todo.len() + done_len,
+        State {
+            current_work: self
+                .running_sources
+                .iter()
+                .map(|(source, _)| source.clone())
+                .collect(),
+            total: self.jobs_todo.len()
+                + self.large_todo.len()
+                + self.waiting.values().map(Vec::len).sum::<usize>()
+                + self.running_sources.len()
+                + done_len,
             done: done_len,
-        }))
+            indexing: self.walker.is_some(),
+            indexed_dirs: self.indexed_dirs,
+            indexed_files: self.indexed_files,
+            space: self.space,
+        }
     }
-}
 
-impl Iterator for Syncer {
-    type Item = Result<State>;
+    /// Whether the free space of the target is known, but was not compared with the jobs yet.
+    /// Only the free space of local targets is known.
+    fn awaiting_space_check(&self) -> bool {
+        self.options.space_check != SpaceCheck::Off
+            && self.space.is_none()
+            && self.free_space.is_some()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let job = match self.jobs_todo.pop() {
-            Some(s) => s,
-            _ => return None,
+    /// Compares the bytes all jobs write with the free space of the target before the sync.
+    fn check_space(&mut self) -> Result<()> {
+        let Some((available, capacity)) = self.free_space else {
+            return Ok(());
+        };
+        let needed = self.started_bytes
+            + self
+                .jobs_todo
+                .iter()
+                .chain(&self.large_todo)
+                .chain(self.waiting.values().flatten())
+                .map(Job::needed_bytes)
+                .sum::<u64>();
+        let estimate = SpaceEstimate {
+            needed,
+            available,
+            capacity,
         };
+        self.space = Some(estimate);
+        if estimate.fits() {
+            return Ok(());
+        }
 
-        let job_res = job.work();
-        if let Err(err) = job_res {
-            return Some(Err(err));
+        let error = anyhow::Error::new(InsufficientSpace(estimate));
+        self.errors
+            .push(SyncError::new(self.target_root.clone(), &error));
+        if self.options.space_check == SpaceCheck::Warn {
+            return Ok(());
+        }
+        // if nothing was written, only the checkpoint of a resumed sync is worth keeping
+        if self.resume_from.is_none() && self.jobs_done.is_empty() && self.running.is_empty() {
+            self.checkpoint = None;
+            Checkpoint::remove(&self.target_root)?;
         }
+        Err(error)
+    }
 
-        let current_file = job.source.clone();
+    /// Waits for the next job to finish and starts new jobs in its place.
+    /// While the walker is still searching, its progress is reported in between.
+    pub async fn async_next(&mut self) -> Option<Result<State>> {
+        enum Next {
+            Finished(Option<Result<Finished, JoinError>>),
+            Found(Option<Vec<Found>>),
+        }
 
-        self.jobs_done.push(job);
+        if let Some(lock) = &self.lock
+            && let Err(error) = lock.check()
+        {
+            return Some(Err(error));
+        }
+
+        loop {
+            if self.walker.is_none() && self.awaiting_space_check() {
+                if let Err(error) = self.check_space() {
+                    return Some(Err(error));
+                }
+                return Some(Ok(self.state()));
+            }
+            self.fill_workers();
+            if self.running.is_empty() && self.walker.is_none() {
+                if let Err(error) = tokio::task::block_in_place(|| self.finish()) {
+                    self.flush_checkpoint(true);
+                    return Some(Err(error));
+                }
+                self.checkpoint = None;
+                if self.context.backend.is_local()
+                    && let Err(error) = Checkpoint::remove(&self.target_root)
+                {
+                    self.errors
+                        .push(SyncError::new(self.target_root.clone(), &error));
+                }
+                self.lock = None;
+                return None;
+            }
 
-        Some(Ok(State {
-            current_work: vec![current_file],
-            total: self.jobs_todo.len() + self.jobs_done.len(),
-            done: self.jobs_done.len(),
-        }))
+            let next = {
+                let running = &mut self.running;
+                let walker = &mut self.walker;
+                tokio::select! {
+                    finished = running.next(), if !running.is_empty() => Next::Finished(finished),
+                    found = async { walker.as_mut().unwrap().next_batch().await }, if walker.is_some() => {
+                        Next::Found(found)
+                    }
+                }
+            };
+
+            match next {
+                Next::Finished(Some(finished)) => {
+                    return Some(self.finish_job(finished).map(|()| self.state()));
+                }
+                Next::Finished(None) => {}
+                Next::Found(Some(batch)) => {
+                    for found in batch {
+                        if let Err(error) = self.handle_found(found) {
+                            return Some(Err(error));
+                        }
+                    }
+                    if self.last_state.elapsed() >= INDEXING_STATE_INTERVAL {
+                        return Some(Ok(self.state()));
+                    }
+                }
+                Next::Found(None) => self.walker = None,
+            }
+        }
     }
 }
 
@@ -291,6 +1418,19 @@ fn valid_syncer_parameters(
             return Err(InvalidSyncerParameters::TargetInSource(source.clone()));
         }
     }
+    // sources are synced to target directories of their name, those with the same name would be
+    // merged and in mirror mode remove the files of each other
+    for (i, source) in sources.iter().enumerate() {
+        if let Some(other) = sources[..i]
+            .iter()
+            .find(|other| other.file_name() == source.file_name())
+        {
+            return Err(InvalidSyncerParameters::SameName(
+                other.clone(),
+                source.clone(),
+            ));
+        }
+    }
     Ok(())
 }
 
@@ -299,4 +1439,350 @@ pub enum InvalidSyncerParameters {
     SourceDoesNotExist(PathBuf),
     TargetInSource(PathBuf),
     SourceInTarget(PathBuf),
+    /// Two sources have the same name.
+    SameName(PathBuf, PathBuf),
+}
+
+impl std::fmt::Display for InvalidSyncerParameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SourceDoesNotExist(source) => write!(f, "Source {:?} does not exist.", source),
+            Self::TargetInSource(source) => write!(
+                f,
+                "The target directory is located inside of source {:?}.",
+                source
+            ),
+            Self::SourceInTarget(source) => write!(
+                f,
+                "Source {:?} is located inside of the target directory.",
+                source
+            ),
+            Self::SameName(first, second) => write!(
+                f,
+                "Sources {:?} and {:?} have the same name and would be synced to the same directory.",
+                first, second
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syncing::backend::memory::MemoryBackend;
+    use std::collections::BTreeMap;
+
+    /// A source directory with a file and a subdirectory with another file.
+    fn source(name: &str) -> PathBuf {
+        let source = std::env::temp_dir()
+            .join(format!("file-sync-rs-sync-{}-{}", std::process::id(), name))
+            .join("source");
+        let _ = std::fs::remove_dir_all(&source);
+        std::fs::create_dir_all(source.join("dir")).unwrap();
+        std::fs::write(source.join("file"), b"content").unwrap();
+        std::fs::write(source.join("dir").join("nested"), b"nested content").unwrap();
+        source
+    }
+
+    fn sync(source: &Path, backend: &MemoryBackend, options: SyncOptions) -> Statistics {
+        let mut syncer = Syncer::new(vec![source.to_path_buf()], PathBuf::from("/backup"))
+            .unwrap()
+            .with_options(options)
+            .with_backend(Arc::new(backend.clone()));
+        tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                syncer.prepare().await.unwrap();
+                while let Some(state) = syncer.async_next().await {
+                    state.unwrap();
+                }
+            });
+        assert!(syncer.errors.is_empty(), "{:?}", syncer.errors);
+        syncer.statistics
+    }
+
+    #[test]
+    fn files_are_written_to_the_backend() {
+        let source = source("written");
+        let backend = MemoryBackend::default();
+
+        let statistics = sync(&source, &backend, SyncOptions::default());
+        assert_eq!(statistics.copied, 2);
+        assert_eq!(
+            backend.files(),
+            BTreeMap::from([
+                (PathBuf::from("/backup/source/file"), b"content".to_vec()),
+                (
+                    PathBuf::from("/backup/source/dir/nested"),
+                    b"nested content".to_vec()
+                ),
+            ])
+        );
+        assert!(
+            backend
+                .directories()
+                .contains(&PathBuf::from("/backup/source/dir"))
+        );
+
+        std::fs::write(source.join("file"), b"changed").unwrap();
+        // the backend only compares whole seconds
+        set_modified(&source.join("file"), 10);
+        let statistics = sync(&source, &backend, SyncOptions::default());
+        assert_eq!((statistics.copied, statistics.updated), (0, 1));
+        assert_eq!(
+            backend.files()[Path::new("/backup/source/file")],
+            b"changed".to_vec()
+        );
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    fn set_modified(path: &Path, seconds: i64) {
+        filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(seconds, 0)).unwrap();
+    }
+
+    #[test]
+    fn unchanged_files_are_not_rewritten_to_the_backend() {
+        let source = source("unchanged");
+        let target = Path::new("/backup/source/file");
+        let backend = MemoryBackend::default();
+        set_modified(&source.join("file"), 10);
+        sync(&source, &backend, SyncOptions::default());
+        let modified = |backend: &MemoryBackend| backend.stat(target).unwrap().unwrap().modified;
+        assert_eq!(
+            modified(&backend),
+            Some(UNIX_EPOCH + Duration::from_secs(10))
+        );
+
+        let statistics = sync(&source, &backend, SyncOptions::default());
+        assert_eq!(
+            (statistics.updated, statistics.skipped, statistics.bytes),
+            (0, 2, 0)
+        );
+
+        // only the time changed, so the content is compared and the time is taken over
+        set_modified(&source.join("file"), 20);
+        let statistics = sync(&source, &backend, SyncOptions::default());
+        assert_eq!((statistics.updated, statistics.bytes), (0, 0));
+        assert_eq!(
+            modified(&backend),
+            Some(UNIX_EPOCH + Duration::from_secs(20))
+        );
+
+        // the same size, but another content
+        std::fs::write(source.join("file"), b"changed").unwrap();
+        set_modified(&source.join("file"), 30);
+        let statistics = sync(&source, &backend, SyncOptions::default());
+        assert_eq!((statistics.updated, statistics.bytes), (1, 7));
+        assert_eq!(backend.files()[target], b"changed".to_vec());
+        assert_eq!(
+            modified(&backend),
+            Some(UNIX_EPOCH + Duration::from_secs(30))
+        );
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn remote_targets_refuse_to_mirror() {
+        let source = source("mirror");
+        let backend = MemoryBackend::default();
+        sync(&source, &backend, SyncOptions::default());
+
+        std::fs::remove_dir_all(source.join("dir")).unwrap();
+        let mut syncer = Syncer::new(vec![source.clone()], PathBuf::from("/backup"))
+            .unwrap()
+            .with_options(SyncOptions {
+                mirror: true,
+                ..SyncOptions::default()
+            })
+            .with_backend(Arc::new(backend.clone()));
+        let result = tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(syncer.prepare());
+        assert!(result.is_err());
+        assert_eq!(backend.files().len(), 2);
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    /// Syncs to a local target, pretending that it has `available` bytes free.
+    fn sync_with_free_space(source: &Path, target: &Path, available: u64) -> (Syncer, Result<()>) {
+        let mut syncer = Syncer::new(vec![source.to_path_buf()], target.to_path_buf()).unwrap();
+        let result = tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                syncer.prepare().await?;
+                assert!(syncer.free_space.is_some());
+                syncer.free_space = Some((available, 1000));
+                while let Some(state) = syncer.async_next().await {
+                    state?;
+                }
+                Ok(())
+            });
+        (syncer, result)
+    }
+
+    #[test]
+    fn jobs_that_fit_start_before_the_space_check() {
+        let source = source("space");
+        let target = source.parent().unwrap().join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        let job = |path: &str| Job {
+            source: source.join(path),
+            target: target.join(path),
+            recursive: false,
+            link_to: None,
+            move_from: None,
+            transfer: TransferStrategy::Whole,
+            len: walk::file_len(&source.join(path)),
+            target_len: None,
+        };
+
+        let mut syncer = Syncer::new(vec![source.clone()], target.clone()).unwrap();
+        syncer.free_space = Some((10, 1000));
+        assert!(syncer.fits_before_space_check(&job("file")));
+        assert!(!syncer.fits_before_space_check(&job("dir/nested")));
+        assert!(syncer.fits_before_space_check(&job("dir")));
+        assert_eq!(syncer.started_bytes, 7);
+        // after the check, the estimate decides
+        syncer.space = Some(SpaceEstimate {
+            needed: 21,
+            available: 10,
+            capacity: 1000,
+        });
+        assert!(syncer.fits_before_space_check(&job("dir/nested")));
+
+        // the started jobs count towards the needed space
+        let (_, result) = sync_with_free_space(&source, &target, 10);
+        let error = result.unwrap_err();
+        let InsufficientSpace(estimate) = error.downcast_ref::<InsufficientSpace>().unwrap();
+        assert_eq!((estimate.needed, estimate.available), (21, 10));
+        assert!(!target.join("source").join("dir").join("nested").exists());
+
+        let (syncer, result) = sync_with_free_space(&source, &target, 21);
+        result.unwrap();
+        assert!(syncer.space.is_some_and(|space| space.fits()));
+        assert!(target.join("source").join("dir").join("nested").exists());
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    /// Syncs to a local target directory.
+    fn sync_local(source: &Path, target: &Path, options: SyncOptions) -> Statistics {
+        let mut syncer = Syncer::new(vec![source.to_path_buf()], target.to_path_buf())
+            .unwrap()
+            .with_options(options);
+        tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                syncer.prepare().await.unwrap();
+                while let Some(state) = syncer.async_next().await {
+                    state.unwrap();
+                }
+            });
+        assert!(syncer.errors.is_empty(), "{:?}", syncer.errors);
+        syncer.statistics
+    }
+
+    #[test]
+    fn moved_and_vanished_files_are_synced_to_local_targets() {
+        let source = source("local");
+        let target = source.parent().unwrap().join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        let options = || SyncOptions {
+            mirror: true,
+            ..SyncOptions::default()
+        };
+        let statistics = sync_local(&source, &target, options());
+        assert_eq!((statistics.copied, statistics.bytes), (2, 21));
+
+        std::fs::rename(source.join("file"), source.join("renamed")).unwrap();
+        std::fs::remove_dir_all(source.join("dir")).unwrap();
+        let statistics = sync_local(&source, &target, options());
+        assert_eq!(
+            (statistics.moved, statistics.removed, statistics.copied),
+            (1, 2, 0)
+        );
+        let target = target.join("source");
+        assert_eq!(std::fs::read(target.join("renamed")).unwrap(), b"content");
+        assert!(!target.join("file").exists());
+        assert!(!target.join("dir").exists());
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_grouped_by_inode() {
+        let source = source("links");
+        let target = source.parent().unwrap().join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::hard_link(source.join("file"), source.join("dir").join("link")).unwrap();
+        std::fs::hard_link(source.join("file"), source.join("other link")).unwrap();
+        // the same content, but not linked
+        std::fs::write(source.join("copy"), b"content").unwrap();
+
+        let statistics = sync_local(&source, &target, SyncOptions::default());
+        assert_eq!((statistics.copied, statistics.linked), (3, 2));
+        let target = target.join("source");
+        assert!(same_file(
+            &target.join("file"),
+            &target.join("dir").join("link")
+        ));
+        assert!(same_file(&target.join("file"), &target.join("other link")));
+        assert!(!same_file(&target.join("file"), &target.join("copy")));
+        assert!(!same_file(&source.join("file"), &target.join("file")));
+
+        let statistics = sync_local(&source, target.parent().unwrap(), SyncOptions::default());
+        assert_eq!((statistics.copied, statistics.linked), (0, 0));
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn existing_targets_are_kept_if_linking_fails() {
+        let source = source("link-fails");
+        let target = source.parent().unwrap().join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("file"), b"old").unwrap();
+        let job = Job {
+            source: source.join("file"),
+            target: target.join("file"),
+            recursive: false,
+            // the first link does not exist, so the target can not be linked to it
+            link_to: Some((source.join("missing"), target.join("missing"))),
+            move_from: None,
+            transfer: TransferStrategy::Whole,
+            len: walk::file_len(&source.join("file")),
+            target_len: None,
+        };
+
+        let action = job.work(&WorkContext::default()).unwrap();
+        assert_eq!(action, Action::Updated(7));
+        assert_eq!(std::fs::read(target.join("file")).unwrap(), b"content");
+        assert_eq!(std::fs::read_dir(&target).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(source.parent().unwrap());
+    }
+
+    #[test]
+    fn sources_with_the_same_name_are_rejected() {
+        let first = source("name-first");
+        let second = source("name-second");
+        let target = first.parent().unwrap().join("target");
+        assert!(matches!(
+            Syncer::new(vec![first.clone(), second.clone()], target.clone()),
+            Err(InvalidSyncerParameters::SameName(a, b)) if a == first && b == second
+        ));
+        assert!(Syncer::new(vec![first.clone(), second.join("dir")], target.clone()).is_ok());
+        // a file with the name of a directory collides as well
+        std::fs::write(second.parent().unwrap().join("dir"), b"").unwrap();
+        assert!(matches!(
+            Syncer::new(
+                vec![first.join("dir"), second.parent().unwrap().join("dir")],
+                target
+            ),
+            Err(InvalidSyncerParameters::SameName(..))
+        ));
+        let _ = std::fs::remove_dir_all(first.parent().unwrap());
+        let _ = std::fs::remove_dir_all(second.parent().unwrap());
+    }
 }
@@ -0,0 +1,355 @@
+use anyhow::Context;
+use futures::StreamExt;
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::manifest::Fingerprint;
+
+/// How many directories are read at once.
+const WALKER_THREADS: usize = 4;
+
+/// How many found entries are handed out at once.
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// An entry of a directory found by the [`Walker`].
+pub(super) enum Found {
+    /// A file or directory, the content of directories is found afterwards.
+    Entry {
+        source: PathBuf,
+        target: PathBuf,
+        is_dir: bool,
+        /// device and inode of files that have further hard links
+        inode: Option<(u64, u64)>,
+        /// set for files whose metadata could be read
+        fingerprint: Option<Fingerprint>,
+        /// length of files whose metadata could be read
+        len: Option<u64>,
+        /// length of the existing target file, only read if the walker was asked to
+        target_len: Option<u64>,
+    },
+    /// Neither a file nor a directory, e.g. a symlink.
+    Special { source: PathBuf, target: PathBuf },
+    /// A directory or entry could not be read.
+    Error {
+        source: PathBuf,
+        error: anyhow::Error,
+    },
+}
+
+/// Reads directory trees on multiple threads and streams their entries.
+///
+/// An entry is always found before the entries inside of it.
+/// The threads stop when everything was found or the walker is dropped.
+#[derive(Debug)]
+pub(super) struct Walker {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    receiver: UnboundedReceiver<Found>,
+}
+
+/// The directories that wait to be read.
+#[derive(Debug)]
+struct Queue {
+    dirs: VecDeque<(PathBuf, PathBuf)>,
+    /// how many threads are reading a directory, which may add further directories
+    busy: usize,
+    stopped: bool,
+}
+
+impl Walker {
+    /// Starts walking the content of the directories, given as pairs of source and target.
+    /// With `read_targets` the length of the existing target of each file is read as well.
+    pub(super) fn start(dirs: Vec<(PathBuf, PathBuf)>, read_targets: bool) -> Self {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let queue = Arc::new((
+            Mutex::new(Queue {
+                dirs: dirs.into(),
+                busy: 0,
+                stopped: false,
+            }),
+            Condvar::new(),
+        ));
+
+        for _ in 0..WALKER_THREADS {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || walk(&queue, &sender, read_targets));
+        }
+
+        Self { queue, receiver }
+    }
+
+    /// Waits for the next entries. Returns `None` if everything was found.
+    pub(super) async fn next_batch(&mut self) -> Option<Vec<Found>> {
+        let mut batch = vec![self.receiver.next().await?];
+        while batch.len() < MAX_BATCH_SIZE
+            && let Ok(found) = self.receiver.try_recv()
+        {
+            batch.push(found);
+        }
+        Some(batch)
+    }
+}
+
+impl Drop for Walker {
+    fn drop(&mut self) {
+        let (queue, condvar) = &*self.queue;
+        if let Ok(mut queue) = queue.lock() {
+            queue.stopped = true;
+        }
+        condvar.notify_all();
+    }
+}
+
+/// Reads directories from the queue until all are read.
+fn walk(queue: &(Mutex<Queue>, Condvar), sender: &UnboundedSender<Found>, read_targets: bool) {
+    let (queue, condvar) = queue;
+    loop {
+        let (source, target) = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if queue.stopped {
+                    return;
+                }
+                if let Some(dir) = queue.dirs.pop_front() {
+                    queue.busy += 1;
+                    break dir;
+                }
+                if queue.busy == 0 {
+                    // no thread can add directories anymore
+                    condvar.notify_all();
+                    return;
+                }
+                queue = condvar.wait(queue).unwrap();
+            }
+        };
+
+        let result = read_dir(source, target, sender, read_targets);
+
+        let mut queue = queue.lock().unwrap();
+        queue.busy -= 1;
+        match result {
+            Some(subdirs) => queue.dirs.extend(subdirs),
+            // the syncer was dropped
+            None => queue.stopped = true,
+        }
+        drop(queue);
+        condvar.notify_all();
+    }
+}
+
+/// Sends the entries of the directory and returns its subdirectories.
+/// Returns `None` if nobody receives the entries anymore.
+fn read_dir(
+    source: PathBuf,
+    target: PathBuf,
+    sender: &UnboundedSender<Found>,
+    read_targets: bool,
+) -> Option<Vec<(PathBuf, PathBuf)>> {
+    let mut subdirs = Vec::new();
+    let entries = match std::fs::read_dir(&source)
+        .with_context(|| format!("failed to read directory {:?}", source))
+    {
+        Ok(entries) => entries,
+        Err(error) => {
+            sender.unbounded_send(Found::Error { source, error }).ok()?;
+            return Some(subdirs);
+        }
+    };
+
+    for entry in entries {
+        let found = match entry
+            .and_then(|entry| Ok((entry.file_type()?, entry)))
+            .with_context(|| format!("failed to read an entry of directory {:?}", source))
+        {
+            Ok((file_type, entry)) => {
+                let entry_source = entry.path();
+                let entry_target = target.join(entry.file_name());
+                if file_type.is_dir() {
+                    subdirs.push((entry_source.clone(), entry_target.clone()));
+                }
+                if file_type.is_file() || file_type.is_dir() {
+                    // a file that can not be read fails later in its job
+                    let metadata = file_type.is_file().then(|| entry.metadata().ok()).flatten();
+                    Found::Entry {
+                        inode: metadata.as_ref().and_then(linked_inode),
+                        fingerprint: metadata.as_ref().map(Fingerprint::new),
+                        len: metadata.as_ref().map(std::fs::Metadata::len),
+                        target_len: if read_targets && file_type.is_file() {
+                            file_len(&entry_target)
+                        } else {
+                            None
+                        },
+                        source: entry_source,
+                        target: entry_target,
+                        is_dir: file_type.is_dir(),
+                    }
+                } else {
+                    Found::Special {
+                        source: entry_source,
+                        target: entry_target,
+                    }
+                }
+            }
+            Err(error) => Found::Error {
+                source: source.clone(),
+                error,
+            },
+        };
+        sender.unbounded_send(found).ok()?;
+    }
+    Some(subdirs)
+}
+
+/// The length of the file at the path, `None` if there is no file.
+pub(super) fn file_len(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .ok()
+        .filter(std::fs::Metadata::is_file)
+        .map(|metadata| metadata.len())
+}
+
+/// Returns the device and inode of the file if it has further hard links.
+#[cfg(unix)]
+fn linked_inode(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.nlink() > 1).then_some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn linked_inode(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn fixture(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("file-sync-rs-walk-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn walk_all(dirs: Vec<(PathBuf, PathBuf)>) -> Vec<Found> {
+        let mut walker = Walker::start(dirs, false);
+        let mut found = Vec::new();
+        while let Some(batch) = futures::executor::block_on(walker.next_batch()) {
+            found.extend(batch);
+        }
+        found
+    }
+
+    #[test]
+    fn entries_are_found_before_their_content() {
+        let dir = fixture("order");
+        let source = dir.join("source");
+        for (i, path) in ["a/b/c", "a/d", "e/f/g/h", "i"].iter().enumerate() {
+            std::fs::create_dir_all(source.join(path)).unwrap();
+            std::fs::write(source.join(path).join(format!("file{}", i)), "x").unwrap();
+        }
+
+        let found = walk_all(vec![(source.clone(), dir.join("target"))]);
+        let mut positions = HashMap::new();
+        for (position, found) in found.iter().enumerate() {
+            let Found::Entry {
+                source: entry,
+                target,
+                is_dir,
+                ..
+            } = found
+            else {
+                panic!("unexpected special entry or error");
+            };
+            let relative = entry.strip_prefix(&source).unwrap();
+            assert_eq!(*target, dir.join("target").join(relative));
+            assert_eq!(*is_dir, entry.is_dir());
+            assert!(positions.insert(entry.clone(), position).is_none());
+        }
+        // 9 directories and 4 files
+        assert_eq!(positions.len(), 13);
+        for (entry, position) in &positions {
+            if let Some(parent) = entry.parent().filter(|parent| *parent != source) {
+                assert!(
+                    positions[parent] < *position,
+                    "{:?} before its parent",
+                    entry
+                );
+            }
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn lengths_of_sources_and_targets() {
+        let dir = fixture("lengths");
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        std::fs::create_dir_all(source.join("dir")).unwrap();
+        std::fs::write(source.join("file"), [0; 100]).unwrap();
+        std::fs::write(source.join("replaces dir"), [0; 10]).unwrap();
+        std::fs::create_dir_all(target.join("replaces dir")).unwrap();
+        std::fs::write(target.join("file"), [0; 30]).unwrap();
+
+        for read_targets in [false, true] {
+            let mut walker = Walker::start(vec![(source.clone(), target.clone())], read_targets);
+            let mut lengths = Vec::new();
+            while let Some(batch) = futures::executor::block_on(walker.next_batch()) {
+                for found in batch {
+                    if let Found::Entry {
+                        source,
+                        len,
+                        target_len,
+                        ..
+                    } = found
+                    {
+                        lengths.push((source, len, target_len));
+                    }
+                }
+            }
+            lengths.sort();
+            assert_eq!(
+                lengths,
+                vec![
+                    (source.join("dir"), None, None),
+                    (source.join("file"), Some(100), read_targets.then_some(30)),
+                    // a directory in the way of a file is replaced, it has no length
+                    (source.join("replaces dir"), Some(10), None),
+                ]
+            );
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn errors_and_special_entries_are_reported() {
+        let dir = fixture("errors");
+        std::fs::create_dir_all(dir.join("source")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("missing", dir.join("source").join("link")).unwrap();
+
+        let found = walk_all(vec![
+            (dir.join("missing"), dir.join("target")),
+            (dir.join("source"), dir.join("target")),
+        ]);
+        let errors: Vec<&PathBuf> = found
+            .iter()
+            .filter_map(|found| match found {
+                Found::Error { source, .. } => Some(source),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors, vec![&dir.join("missing")]);
+        #[cfg(unix)]
+        assert!(found.iter().any(|found| matches!(
+            found,
+            Found::Special { source, target }
+                if *source == dir.join("source").join("link") && *target == dir.join("target").join("link")
+        )));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use notify_debouncer_full::notify::event::{EventKind, MetadataKind, ModifyKind};
+use notify_debouncer_full::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{DebounceEventResult, Debouncer, RecommendedCache, new_debouncer};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Time the watcher waits for further events on a path before reporting it as changed.
+/// Editors that save through temporary files and renames settle within this window.
+const DEBOUNCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// If more paths than this changed at once, a full sync is cheaper than syncing each of them.
+const MAX_CHANGED_PATHS: usize = 10_000;
+
+/// Changes in the sources reported by a [`ChangeWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Changes {
+    /// The paths that were created or modified. Paths inside of other changed directories are left out.
+    Paths(Vec<PathBuf>),
+    /// Events were lost or too many paths changed, the sources have to be synced completely.
+    Rescan,
+}
+
+impl Changes {
+    /// Combines two batches of changes.
+    pub fn merge(self, other: Changes) -> Changes {
+        match (self, other) {
+            (Changes::Paths(mut paths), Changes::Paths(other_paths)) => {
+                paths.extend(other_paths);
+                minimize_paths(paths.into_iter().collect())
+            }
+            _ => Changes::Rescan,
+        }
+    }
+}
+
+/// Watches the sources for changes and reports them debounced.
+pub struct ChangeWatcher {
+    sources: Vec<PathBuf>,
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    receiver: futures::channel::mpsc::UnboundedReceiver<DebounceEventResult>,
+}
+
+impl ChangeWatcher {
+    pub fn new(sources: &[PathBuf]) -> Result<Self> {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let mut debouncer = new_debouncer(DEBOUNCE_WINDOW, None, move |result| {
+            let _ = sender.unbounded_send(result);
+        })
+        .context("failed to create file watcher")?;
+
+        for source in sources {
+            if source.is_dir() {
+                debouncer
+                    .watch(source, RecursiveMode::Recursive)
+                    .with_context(|| format!("failed to watch {:?}", source))?;
+            } else {
+                // Editors replace files on save, which would end a watch on the file itself.
+                let parent = source
+                    .parent()
+                    .with_context(|| format!("{:?} has no parent directory", source))?;
+                debouncer
+                    .watch(parent, RecursiveMode::NonRecursive)
+                    .with_context(|| format!("failed to watch {:?}", parent))?;
+            }
+        }
+
+        Ok(Self {
+            sources: sources.to_vec(),
+            _debouncer: debouncer,
+            receiver,
+        })
+    }
+
+    /// Waits for the next changes. Batches that arrived in the meantime are merged into one.
+    /// Batches without relevant changes in the sources are skipped.
+    /// Returns `None` if the watcher stopped.
+    pub async fn next(&mut self) -> Option<Result<Changes>> {
+        loop {
+            match self.next_batch().await? {
+                Ok(Changes::Paths(paths)) if paths.is_empty() => continue,
+                changes => return Some(changes),
+            }
+        }
+    }
+
+    async fn next_batch(&mut self) -> Option<Result<Changes>> {
+        let mut results = vec![self.receiver.next().await?];
+        while let Ok(result) = self.receiver.try_recv() {
+            results.push(result);
+        }
+
+        let mut paths = BTreeSet::new();
+        for result in results {
+            let events = match result {
+                Ok(events) => events,
+                Err(errors) => {
+                    return Some(Err(anyhow::anyhow!(
+                        "file watcher failed: {}",
+                        errors
+                            .iter()
+                            .map(|error| error.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )));
+                }
+            };
+            for event in events {
+                if event.need_rescan() {
+                    return Some(Ok(Changes::Rescan));
+                }
+                if !is_relevant(&event.kind) {
+                    continue;
+                }
+                paths.extend(
+                    event
+                        .paths
+                        .iter()
+                        .filter(|path| self.is_in_sources(path))
+                        .cloned(),
+                );
+            }
+        }
+
+        Some(Ok(minimize_paths(paths)))
+    }
+
+    fn is_in_sources(&self, path: &Path) -> bool {
+        self.sources.iter().any(|source| path.starts_with(source))
+    }
+}
+
+/// Removal is not synced and access does not change anything.
+fn is_relevant(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any | EventKind::Other => true,
+        EventKind::Access(_) | EventKind::Remove(_) => false,
+    }
+}
+
+/// Removes paths that are located inside of other paths of the set.
+fn minimize_paths(paths: BTreeSet<PathBuf>) -> Changes {
+    let mut minimized: Vec<PathBuf> = Vec::new();
+    // the ordering of paths is by components, so descendants directly follow their ancestor
+    for path in paths {
+        if minimized.last().is_some_and(|last| path.starts_with(last)) {
+            continue;
+        }
+        minimized.push(path);
+    }
+
+    if minimized.len() > MAX_CHANGED_PATHS {
+        Changes::Rescan
+    } else {
+        Changes::Paths(minimized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn minimize_paths_drops_descendants() {
+        let changes = minimize_paths(
+            paths(&["/a/b/c", "/a/b", "/a/bc", "/d", "/a/b/d/e"])
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(changes, Changes::Paths(paths(&["/a/b", "/a/bc", "/d"])));
+    }
+
+    #[test]
+    fn minimize_paths_rescans_on_too_many_paths() {
+        let many = (0..=MAX_CHANGED_PATHS)
+            .map(|i| PathBuf::from(format!("/file{}", i)))
+            .collect();
+        assert_eq!(minimize_paths(many), Changes::Rescan);
+    }
+
+    #[test]
+    fn merge_changes() {
+        let merged =
+            Changes::Paths(paths(&["/a/b", "/c"])).merge(Changes::Paths(paths(&["/a", "/d"])));
+        assert_eq!(merged, Changes::Paths(paths(&["/a", "/c", "/d"])));
+
+        assert_eq!(
+            Changes::Paths(paths(&["/a"])).merge(Changes::Rescan),
+            Changes::Rescan
+        );
+        assert_eq!(
+            Changes::Rescan.merge(Changes::Paths(vec![])),
+            Changes::Rescan
+        );
+    }
+}